@@ -0,0 +1,23 @@
+CREATE TABLE newsletter_issues(
+    newsletter_issue_id uuid NOT NULL,
+    title TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    published_at timestamptz NOT NULL,
+    PRIMARY KEY (newsletter_issue_id)
+);
+CREATE TABLE email_deliveries(
+    id uuid NOT NULL,
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions (id),
+    kind TEXT NOT NULL,
+    newsletter_issue_id uuid NULL
+        REFERENCES newsletter_issues (newsletter_issue_id),
+    message_id TEXT NULL,
+    sent_at timestamptz NOT NULL,
+    PRIMARY KEY (id)
+);
+CREATE UNIQUE INDEX email_deliveries_message_id_idx
+    ON email_deliveries (message_id)
+    WHERE message_id IS NOT NULL;
+CREATE INDEX email_deliveries_subscriber_id_idx ON email_deliveries (subscriber_id);
@@ -0,0 +1,8 @@
+-- Tokens issued before this migration get a fresh week from now rather than
+-- expiring on the spot.
+ALTER TABLE subscription_tokens
+    ADD COLUMN issued_at timestamptz NOT NULL DEFAULT now(),
+    ADD COLUMN expires_at timestamptz NOT NULL DEFAULT now() + interval '7 days';
+ALTER TABLE subscription_tokens ALTER COLUMN issued_at DROP DEFAULT;
+ALTER TABLE subscription_tokens ALTER COLUMN expires_at DROP DEFAULT;
+CREATE INDEX subscription_tokens_subscriber_id_idx ON subscription_tokens (subscriber_id);
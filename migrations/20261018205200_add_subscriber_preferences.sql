@@ -0,0 +1,17 @@
+ALTER TABLE subscriptions
+    ADD COLUMN topics TEXT[] NOT NULL DEFAULT '{}',
+    ADD COLUMN delivery_frequency TEXT NOT NULL DEFAULT 'immediately'
+        CHECK (delivery_frequency IN ('immediately', 'weekly', 'monthly'));
+
+-- A subscriber asked to move to another address. The new address is only
+-- used once its owner follows the link sent to it.
+CREATE TABLE email_change_requests(
+    token TEXT NOT NULL,
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions (id) ON DELETE CASCADE,
+    new_email TEXT NOT NULL,
+    issued_at timestamptz NOT NULL,
+    expires_at timestamptz NOT NULL,
+    PRIMARY KEY (token)
+);
+CREATE INDEX email_change_requests_subscriber_id_idx ON email_change_requests (subscriber_id);
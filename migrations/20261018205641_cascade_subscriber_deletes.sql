@@ -0,0 +1,10 @@
+-- Erasing a subscriber must take everything we hold about them along, rather
+-- than being blocked by their tokens and delivery history.
+ALTER TABLE subscription_tokens
+    DROP CONSTRAINT subscription_tokens_subscriber_id_fkey,
+    ADD CONSTRAINT subscription_tokens_subscriber_id_fkey
+        FOREIGN KEY (subscriber_id) REFERENCES subscriptions (id) ON DELETE CASCADE;
+ALTER TABLE email_deliveries
+    DROP CONSTRAINT email_deliveries_subscriber_id_fkey,
+    ADD CONSTRAINT email_deliveries_subscriber_id_fkey
+        FOREIGN KEY (subscriber_id) REFERENCES subscriptions (id) ON DELETE CASCADE;
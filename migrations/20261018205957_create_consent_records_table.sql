@@ -0,0 +1,35 @@
+-- Proof of consent: who agreed to what, when, from where.
+CREATE TABLE consent_records(
+    id uuid NOT NULL,
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions (id) ON DELETE CASCADE,
+    event TEXT NOT NULL,
+    -- The address at the time, it may change later on.
+    email TEXT NOT NULL,
+    recorded_at timestamptz NOT NULL,
+    ip_address TEXT NULL,
+    user_agent TEXT NULL,
+    form_source TEXT NULL,
+    privacy_policy_version TEXT NOT NULL,
+    PRIMARY KEY (id)
+);
+CREATE INDEX consent_records_subscriber_id_idx ON consent_records (subscriber_id);
+
+-- The log is append-only. Rows only go away together with their subscriber
+-- when that subscriber's data is erased, which reaches this table through
+-- the foreign key's cascade, one trigger level down.
+CREATE FUNCTION consent_records_append_only() RETURNS trigger AS $$
+BEGIN
+    IF TG_OP = 'DELETE' AND pg_trigger_depth() > 1 THEN
+        RETURN OLD;
+    END IF;
+    RAISE EXCEPTION 'consent_records is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER consent_records_append_only
+    BEFORE UPDATE OR DELETE ON consent_records
+    FOR EACH ROW EXECUTE FUNCTION consent_records_append_only();
+CREATE TRIGGER consent_records_no_truncate
+    BEFORE TRUNCATE ON consent_records
+    FOR EACH STATEMENT EXECUTE FUNCTION consent_records_append_only();
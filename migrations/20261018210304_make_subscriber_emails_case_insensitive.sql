@@ -0,0 +1,48 @@
+-- Addresses used to be stored verbatim, so `Foo@Example.com` and
+-- `foo@example.com` could both be on the list. From now on they are the same
+-- subscriber.
+
+-- Several rows for the same address are merged into one. The row that is
+-- kept is the most restrictive one, so that nobody who unsubscribed or was
+-- suppressed starts receiving email again, and the oldest among those.
+CREATE TEMPORARY TABLE merged_subscribers AS
+SELECT id, first_value(id) OVER (
+    PARTITION BY lower(email)
+    ORDER BY
+        CASE status
+            WHEN 'suppressed' THEN 0
+            WHEN 'unsubscribed' THEN 1
+            WHEN 'confirmed' THEN 2
+            ELSE 3
+        END,
+        subscribed_at,
+        id
+) AS kept_id
+FROM subscriptions;
+DELETE FROM merged_subscribers WHERE id = kept_id;
+
+-- History moves to the kept row. Tokens and pending email changes of the
+-- merged rows are dropped with them.
+UPDATE email_deliveries d
+    SET subscriber_id = m.kept_id
+    FROM merged_subscribers m
+    WHERE d.subscriber_id = m.id;
+ALTER TABLE consent_records DISABLE TRIGGER consent_records_append_only;
+UPDATE consent_records c
+    SET subscriber_id = m.kept_id
+    FROM merged_subscribers m
+    WHERE c.subscriber_id = m.id;
+ALTER TABLE consent_records ENABLE TRIGGER consent_records_append_only;
+DELETE FROM subscriptions WHERE id IN (SELECT id FROM merged_subscribers);
+DROP TABLE merged_subscribers;
+
+-- Domains are case-insensitive, new addresses get a lowercase one. Existing
+-- non-ASCII domains are left alone, Postgres can't convert them to IDNA.
+UPDATE subscriptions
+    SET email = substring(email FROM '^(.*)@') || '@' || lower(substring(email FROM '@([^@]*)$'))
+    WHERE email ~ '@[\x21-\x7e]*$';
+
+ALTER TABLE subscriptions
+    ADD COLUMN email_canonical TEXT GENERATED ALWAYS AS (lower(email)) STORED;
+ALTER TABLE subscriptions DROP CONSTRAINT subscriptions_email_key;
+ALTER TABLE subscriptions ADD CONSTRAINT subscriptions_email_canonical_key UNIQUE (email_canonical);
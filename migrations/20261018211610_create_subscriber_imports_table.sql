@@ -0,0 +1,15 @@
+-- Bulk imports of subscribers from a CSV file, with the rows that didn't
+-- make it in.
+CREATE TABLE subscriber_imports(
+    id uuid PRIMARY KEY,
+    imported_by uuid NOT NULL REFERENCES users (user_id),
+    mode TEXT NOT NULL CHECK (mode IN ('confirmed', 'send_confirmation')),
+    -- How the subscribers consented, when they are imported as confirmed.
+    attestation TEXT NULL,
+    started_at timestamptz NOT NULL,
+    finished_at timestamptz NULL,
+    imported_rows INT NOT NULL DEFAULT 0,
+    failed_rows INT NOT NULL DEFAULT 0,
+    -- A CSV file of the failed rows.
+    error_report TEXT NOT NULL DEFAULT ''
+);
@@ -0,0 +1,5 @@
+-- The admin subscriber browser pages through subscribers by (subscribed_at, id)
+-- and searches them by email or name prefix.
+CREATE INDEX subscriptions_subscribed_at_id_idx ON subscriptions (subscribed_at, id);
+CREATE INDEX subscriptions_email_canonical_prefix_idx ON subscriptions (email_canonical text_pattern_ops);
+CREATE INDEX subscriptions_name_prefix_idx ON subscriptions (lower(name) text_pattern_ops);
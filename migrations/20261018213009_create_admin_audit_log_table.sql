@@ -0,0 +1,11 @@
+-- What admins did to subscribers, and when.
+CREATE TABLE admin_audit_log(
+    id uuid PRIMARY KEY,
+    user_id uuid NOT NULL REFERENCES users (user_id),
+    action TEXT NOT NULL,
+    -- No foreign key: the entry has to outlive a deleted subscriber.
+    subscriber_id uuid NULL,
+    detail TEXT NULL,
+    performed_at timestamptz NOT NULL
+);
+CREATE INDEX admin_audit_log_subscriber_id_idx ON admin_audit_log (subscriber_id);
@@ -0,0 +1,11 @@
+-- Custom fields per subscriber (company, country, ...), as described by the
+-- admin-defined attribute_definitions.
+CREATE TABLE attribute_definitions(
+    name TEXT PRIMARY KEY,
+    kind TEXT NOT NULL CHECK (kind IN ('text', 'number', 'boolean', 'date')),
+    required BOOLEAN NOT NULL,
+    created_at timestamptz NOT NULL
+);
+ALTER TABLE subscriptions ADD COLUMN attributes JSONB NOT NULL DEFAULT '{}';
+-- Segments select subscribers with `attributes @> ...`.
+CREATE INDEX subscriptions_attributes_idx ON subscriptions USING GIN (attributes jsonb_path_ops);
@@ -0,0 +1,12 @@
+-- Where a signup came from: the form or app that sent it, the page that
+-- linked to it and the campaign's utm_* parameters. First touch only.
+ALTER TABLE subscriptions
+    ADD COLUMN signup_source TEXT,
+    ADD COLUMN referrer TEXT,
+    ADD COLUMN utm_source TEXT,
+    ADD COLUMN utm_medium TEXT,
+    ADD COLUMN utm_campaign TEXT,
+    ADD COLUMN utm_term TEXT,
+    ADD COLUMN utm_content TEXT;
+-- The signup report groups confirmed subscribers by when they subscribed.
+CREATE INDEX subscriptions_confirmed_subscribed_at_idx ON subscriptions (subscribed_at) WHERE status = 'confirmed';
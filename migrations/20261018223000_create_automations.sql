@@ -0,0 +1,53 @@
+-- Automations are series of emails sent after a subscriber confirms, e.g. a
+-- welcome mail right away, tips on day 3 and a survey on day 7.
+CREATE TABLE automations(
+    id uuid NOT NULL,
+    name TEXT NOT NULL,
+    active BOOLEAN NOT NULL,
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY (id)
+);
+CREATE TABLE automation_steps(
+    id uuid NOT NULL,
+    automation_id uuid NOT NULL
+        REFERENCES automations (id) ON DELETE CASCADE,
+    -- Counted from enrollment, not from the previous step.
+    delay_hours INTEGER NOT NULL CHECK (delay_hours >= 0),
+    subject TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY (id)
+);
+CREATE INDEX automation_steps_automation_id_idx ON automation_steps (automation_id, delay_hours);
+CREATE TABLE automation_enrollments(
+    id uuid NOT NULL,
+    automation_id uuid NOT NULL
+        REFERENCES automations (id) ON DELETE CASCADE,
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions (id) ON DELETE CASCADE,
+    enrolled_at timestamptz NOT NULL,
+    -- Set when the subscriber leaves before the last step.
+    stopped_at timestamptz NULL,
+    PRIMARY KEY (id),
+    UNIQUE (automation_id, subscriber_id)
+);
+CREATE INDEX automation_enrollments_subscriber_id_idx ON automation_enrollments (subscriber_id);
+-- One row per step of every enrollment, scheduled on enrollment. The
+-- background worker picks up the scheduled ones that are due.
+CREATE TABLE automation_step_deliveries(
+    enrollment_id uuid NOT NULL
+        REFERENCES automation_enrollments (id) ON DELETE CASCADE,
+    step_id uuid NOT NULL
+        REFERENCES automation_steps (id) ON DELETE CASCADE,
+    due_at timestamptz NOT NULL,
+    status TEXT NOT NULL CHECK (status IN ('scheduled', 'sent', 'failed', 'skipped')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT NULL,
+    email_delivery_id uuid NULL
+        REFERENCES email_deliveries (id) ON DELETE SET NULL,
+    updated_at timestamptz NOT NULL,
+    PRIMARY KEY (enrollment_id, step_id)
+);
+CREATE INDEX automation_step_deliveries_due_idx ON automation_step_deliveries (due_at) WHERE status = 'scheduled';
+CREATE INDEX automation_step_deliveries_step_id_idx ON automation_step_deliveries (step_id, status);
@@ -0,0 +1,5 @@
+-- Only a SHA-256 hash of each token is kept, hex encoded, so a copy of the
+-- database can't be used to confirm anybody. Outstanding tokens are hashed in
+-- place and the links already sent keep working.
+ALTER TABLE subscription_tokens RENAME COLUMN subscription_token TO token_hash;
+UPDATE subscription_tokens SET token_hash = encode(sha256(convert_to(token_hash, 'UTF8')), 'hex');
@@ -0,0 +1,3 @@
+-- The statuses of `SubscriptionStatus`, see `src/domain/subscription_status.rs`.
+ALTER TABLE subscriptions ADD CONSTRAINT subscriptions_status_check
+    CHECK (status IN ('pending_confirmation', 'confirmed', 'unsubscribed', 'bounced', 'suppressed'));
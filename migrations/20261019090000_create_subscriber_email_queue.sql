@@ -0,0 +1,13 @@
+-- Emails to a subscriber that a background worker sends, so that the request
+-- which caused them doesn't wait on the email provider. At most one of each
+-- kind waits per subscriber, the row is gone once it is sent.
+CREATE TABLE subscriber_email_queue(
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    kind TEXT NOT NULL CHECK (kind IN ('confirmation')),
+    queued_at timestamptz NOT NULL,
+    due_at timestamptz NOT NULL,
+    attempts INT NOT NULL DEFAULT 0,
+    last_error TEXT NULL,
+    PRIMARY KEY (subscriber_id, kind)
+);
+CREATE INDEX subscriber_email_queue_due_at_idx ON subscriber_email_queue (due_at);
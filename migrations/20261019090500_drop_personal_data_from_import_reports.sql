@@ -0,0 +1,4 @@
+-- Error reports now only hold row numbers and reasons. The ones written so
+-- far also held the names and addresses of the failed rows, which nothing
+-- scrubbed when one of those people asked to be erased.
+UPDATE subscriber_imports SET error_report = '' WHERE error_report <> '';
@@ -0,0 +1,5 @@
+-- Someone already on the list who signs up again is told so by email, the
+-- same way a new subscriber gets their confirmation email.
+ALTER TABLE subscriber_email_queue DROP CONSTRAINT subscriber_email_queue_kind_check;
+ALTER TABLE subscriber_email_queue
+    ADD CONSTRAINT subscriber_email_queue_kind_check CHECK (kind IN ('confirmation', 'already_subscribed'));
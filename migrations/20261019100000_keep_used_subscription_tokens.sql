@@ -0,0 +1,3 @@
+-- Confirming marks the tokens of a subscriber as used instead of deleting
+-- them, so that following a link twice says it was already confirmed.
+ALTER TABLE subscription_tokens ADD COLUMN used_at timestamptz NULL;
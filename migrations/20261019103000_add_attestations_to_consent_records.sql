@@ -0,0 +1,5 @@
+-- Consent an admin vouches for, e.g. when confirming a subscriber by hand:
+-- who vouched, and their statement of how the subscriber consented.
+ALTER TABLE consent_records
+    ADD COLUMN attested_by TEXT NULL,
+    ADD COLUMN attestation TEXT NULL;
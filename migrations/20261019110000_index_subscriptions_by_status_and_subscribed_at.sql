@@ -0,0 +1,4 @@
+-- The signup report binds the status it counts as a parameter, which a
+-- partial index on one literal status can't serve.
+DROP INDEX subscriptions_confirmed_subscribed_at_idx;
+CREATE INDEX subscriptions_status_subscribed_at_idx ON subscriptions (status, subscribed_at);
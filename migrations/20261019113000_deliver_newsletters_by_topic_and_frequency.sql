@@ -0,0 +1,18 @@
+-- An issue can be about one of the topics subscribers pick on their
+-- preferences page, NULL is for everybody. The segment is the `@>` filter on
+-- attributes it was published with, kept for the digests that include it
+-- later.
+ALTER TABLE newsletter_issues
+    ADD COLUMN topic TEXT NULL,
+    ADD COLUMN segment JSONB NOT NULL DEFAULT '{}';
+CREATE INDEX newsletter_issues_published_at_idx ON newsletter_issues (published_at);
+
+-- Weekly and monthly subscribers get everything published after this in
+-- their next digest. Everybody got issues straight away until now.
+ALTER TABLE subscriptions
+    ADD COLUMN digest_covered_until timestamptz NOT NULL DEFAULT now();
+
+ALTER TABLE subscriber_email_queue
+    DROP CONSTRAINT subscriber_email_queue_kind_check,
+    ADD CONSTRAINT subscriber_email_queue_kind_check
+        CHECK (kind IN ('confirmation', 'already_subscribed', 'digest'));
@@ -0,0 +1,2 @@
+-- The subscriber email worker regularly deletes the tokens that expired.
+CREATE INDEX subscription_tokens_expires_at_idx ON subscription_tokens (expires_at);
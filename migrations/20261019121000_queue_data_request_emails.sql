@@ -0,0 +1,6 @@
+-- Data export links and erasure links go out through the queue, so that
+-- asking for one looks the same whether or not the address is known.
+ALTER TABLE subscriber_email_queue
+    DROP CONSTRAINT subscriber_email_queue_kind_check,
+    ADD CONSTRAINT subscriber_email_queue_kind_check
+        CHECK (kind IN ('confirmation', 'already_subscribed', 'digest', 'data_export', 'erasure_link'));
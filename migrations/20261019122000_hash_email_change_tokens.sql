@@ -0,0 +1,4 @@
+-- Like subscription tokens, email change tokens are only stored as their
+-- SHA-256 hash, hex encoded. Links already sent keep working.
+ALTER TABLE email_change_requests RENAME COLUMN token TO token_hash;
+UPDATE email_change_requests SET token_hash = encode(sha256(convert_to(token_hash, 'UTF8')), 'hex');
@@ -0,0 +1,6 @@
+-- Nothing ever set 'bounced': an address the provider can't deliver to is
+-- suppressed. Rows that have it anyway are suppressed too.
+UPDATE subscriptions SET status = 'suppressed' WHERE status = 'bounced';
+ALTER TABLE subscriptions DROP CONSTRAINT subscriptions_status_check;
+ALTER TABLE subscriptions ADD CONSTRAINT subscriptions_status_check
+    CHECK (status IN ('pending_confirmation', 'confirmed', 'unsubscribed', 'suppressed'));
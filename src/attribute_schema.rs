@@ -0,0 +1,66 @@
+use chrono::Utc;
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
+
+use crate::domain::{AttributeDefinition, AttributeKind, AttributeSchema};
+
+/// The custom attributes admins have defined, in the order they were added.
+#[tracing::instrument(name = "Fetch the attribute schema", skip(executor))]
+pub async fn get_attribute_schema(executor: impl PgExecutor<'_>) -> Result<AttributeSchema, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"SELECT name, kind, required FROM attribute_definitions ORDER BY created_at, name"#,
+    )
+    .fetch_all(executor)
+    .await?;
+    let definitions = rows
+        .into_iter()
+        .map(|row| {
+            let kind = AttributeKind::parse(&row.kind).map_err(|e| anyhow::anyhow!(e))?;
+            Ok(AttributeDefinition { name: row.name, kind, required: row.required })
+        })
+        .collect::<Result<Vec<_>, anyhow::Error>>()?;
+    Ok(AttributeSchema::new(definitions))
+}
+
+/// Returns false if an attribute with that name already exists.
+#[tracing::instrument(name = "Add an attribute definition", skip(pool))]
+pub async fn insert_attribute_definition(
+    pool: &PgPool,
+    definition: &AttributeDefinition,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO attribute_definitions (name, kind, required, created_at)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (name) DO NOTHING
+        "#,
+        definition.name,
+        definition.kind.as_str(),
+        definition.required,
+        Utc::now(),
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Removes the attribute, and its value from every subscriber. Returns false
+/// if there was no such attribute.
+#[tracing::instrument(name = "Delete an attribute definition", skip(transaction))]
+pub async fn delete_attribute_definition(
+    transaction: &mut Transaction<'_, Postgres>,
+    name: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(r#"DELETE FROM attribute_definitions WHERE name = $1"#, name)
+        .execute(&mut *transaction)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+    sqlx::query!(
+        r#"UPDATE subscriptions SET attributes = attributes - $1 WHERE attributes ? $1"#,
+        name,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(true)
+}
@@ -0,0 +1,77 @@
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// What the audit log records an admin doing to a subscriber.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdminAction {
+    Confirm,
+    ResendConfirmation,
+    Unsubscribe,
+    Suppress,
+    Delete,
+    EditAttributes,
+}
+
+impl AdminAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AdminAction::Confirm => "confirm",
+            AdminAction::ResendConfirmation => "resend_confirmation",
+            AdminAction::Unsubscribe => "unsubscribe",
+            AdminAction::Suppress => "suppress",
+            AdminAction::Delete => "delete",
+            AdminAction::EditAttributes => "edit_attributes",
+        }
+    }
+}
+
+/// Appends to the audit log, in the same transaction as the action.
+#[tracing::instrument(name = "Record an admin action", skip(transaction))]
+pub async fn record_admin_action(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    action: AdminAction,
+    subscriber_id: Uuid,
+    detail: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO admin_audit_log (id, user_id, action, subscriber_id, detail, performed_at)
+        VALUES ($1, $2, $3, $4, $5, now())
+        "#,
+        Uuid::new_v4(),
+        user_id,
+        action.as_str(),
+        subscriber_id,
+        detail,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct AuditLogEntry {
+    pub username: String,
+    pub action: String,
+    pub detail: Option<String>,
+    pub performed_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Fetch the admin actions on a subscriber", skip(pool))]
+pub async fn get_admin_actions(pool: &PgPool, subscriber_id: Uuid) -> Result<Vec<AuditLogEntry>, sqlx::Error> {
+    sqlx::query_as!(
+        AuditLogEntry,
+        r#"
+        SELECT u.username, a.action, a.detail, a.performed_at
+        FROM admin_audit_log a
+        JOIN users u ON u.user_id = a.user_id
+        WHERE a.subscriber_id = $1
+        ORDER BY a.performed_at
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await
+}
@@ -38,7 +38,7 @@ pub async fn validate_credentials(
     );
     if let Some((stored_user_id, stored_password_hash)) = get_stored_credentials(
             &credentials.username, 
-            &pool
+            pool
         )
         .await?
     {
@@ -69,7 +69,7 @@ fn verify_password_hash(
     password_candidate: Secret<String>,
 ) -> Result<(), AuthError> {
     let expected_password_hash = PasswordHash::new(
-            &expected_password_hash.expose_secret()
+            expected_password_hash.expose_secret()
         )
         .context("Failed to parse hash in PHC string format.")?;
     
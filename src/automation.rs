@@ -0,0 +1,227 @@
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+// No step is sent more than a year after the subscriber confirmed.
+pub const MAX_STEP_DELAY_HOURS: i32 = 365 * 24;
+
+#[derive(Debug)]
+pub struct Automation {
+    pub id: Uuid,
+    pub name: String,
+    pub active: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub struct AutomationSummary {
+    pub id: Uuid,
+    pub name: String,
+    pub active: bool,
+    pub steps: i64,
+    pub enrollments: i64,
+}
+
+/// One email of an automation, with how far it got over all enrollments.
+#[derive(Debug)]
+pub struct AutomationStep {
+    pub id: Uuid,
+    pub delay_hours: i32,
+    pub subject: String,
+    pub scheduled: i64,
+    pub sent: i64,
+    pub failed: i64,
+    pub skipped: i64,
+}
+
+pub struct NewAutomationStep {
+    pub delay_hours: i32,
+    pub subject: String,
+    pub text_content: String,
+    pub html_content: String,
+}
+
+#[tracing::instrument(name = "Fetch the automations", skip(pool))]
+pub async fn get_automations(pool: &PgPool) -> Result<Vec<AutomationSummary>, sqlx::Error> {
+    sqlx::query_as!(
+        AutomationSummary,
+        r#"
+        SELECT
+            a.id, a.name, a.active,
+            (SELECT count(*) FROM automation_steps s WHERE s.automation_id = a.id) AS "steps!",
+            (SELECT count(*) FROM automation_enrollments e WHERE e.automation_id = a.id) AS "enrollments!"
+        FROM automations a
+        ORDER BY a.created_at
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[tracing::instrument(name = "Fetch an automation", skip(pool))]
+pub async fn get_automation(pool: &PgPool, automation_id: Uuid) -> Result<Option<Automation>, sqlx::Error> {
+    sqlx::query_as!(
+        Automation,
+        r#"SELECT id, name, active, created_at FROM automations WHERE id = $1"#,
+        automation_id,
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// The steps in the order they are sent.
+#[tracing::instrument(name = "Fetch the steps of an automation", skip(pool))]
+pub async fn get_automation_steps(pool: &PgPool, automation_id: Uuid) -> Result<Vec<AutomationStep>, sqlx::Error> {
+    sqlx::query_as!(
+        AutomationStep,
+        r#"
+        SELECT
+            s.id, s.delay_hours, s.subject,
+            count(*) FILTER (WHERE d.status = 'scheduled') AS "scheduled!",
+            count(*) FILTER (WHERE d.status = 'sent') AS "sent!",
+            count(*) FILTER (WHERE d.status = 'failed') AS "failed!",
+            count(*) FILTER (WHERE d.status = 'skipped') AS "skipped!"
+        FROM automation_steps s
+        LEFT JOIN automation_step_deliveries d ON d.step_id = s.id
+        WHERE s.automation_id = $1
+        GROUP BY s.id
+        ORDER BY s.delay_hours, s.created_at
+        "#,
+        automation_id,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// New automations start inactive, so nobody is enrolled in a half-written
+/// series.
+#[tracing::instrument(name = "Add an automation", skip(pool))]
+pub async fn insert_automation(pool: &PgPool, name: &str) -> Result<Uuid, sqlx::Error> {
+    let automation_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO automations (id, name, active, created_at)
+        VALUES ($1, $2, false, now())
+        "#,
+        automation_id,
+        name,
+    )
+    .execute(pool)
+    .await?;
+    Ok(automation_id)
+}
+
+/// Only subscribers who confirm while the automation is active are enrolled.
+/// Returns false if there is no such automation.
+#[tracing::instrument(name = "Activate or deactivate an automation", skip(pool))]
+pub async fn set_automation_active(pool: &PgPool, automation_id: Uuid, active: bool) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"UPDATE automations SET active = $2 WHERE id = $1"#,
+        automation_id,
+        active,
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Subscribers already enrolled don't get steps added afterwards.
+#[tracing::instrument(name = "Add an automation step", skip(pool, step))]
+pub async fn insert_automation_step(
+    pool: &PgPool,
+    automation_id: Uuid,
+    step: &NewAutomationStep,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO automation_steps (
+            id, automation_id, delay_hours, subject, text_content, html_content, created_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, now())
+        "#,
+        Uuid::new_v4(),
+        automation_id,
+        step.delay_hours,
+        step.subject,
+        step.text_content,
+        step.html_content,
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Removes the step along with its delivery tracking. Returns false if there
+/// was no such step.
+#[tracing::instrument(name = "Delete an automation step", skip(pool))]
+pub async fn delete_automation_step(pool: &PgPool, automation_id: Uuid, step_id: Uuid) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"DELETE FROM automation_steps WHERE id = $1 AND automation_id = $2"#,
+        step_id,
+        automation_id,
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Enrolls a subscriber who just confirmed in every active automation, and
+/// schedules each step counting from now. Someone who was enrolled before,
+/// e.g. before unsubscribing and coming back, is not enrolled again.
+#[tracing::instrument(name = "Enroll a subscriber in automations", skip(transaction))]
+pub async fn enroll_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        WITH enrollments AS (
+            INSERT INTO automation_enrollments (id, automation_id, subscriber_id, enrolled_at)
+            SELECT gen_random_uuid(), id, $1, now()
+            FROM automations
+            WHERE active
+            ON CONFLICT (automation_id, subscriber_id) DO NOTHING
+            RETURNING id, automation_id, enrolled_at
+        )
+        INSERT INTO automation_step_deliveries (enrollment_id, step_id, due_at, status, updated_at)
+        SELECT e.id, s.id, e.enrolled_at + make_interval(hours => s.delay_hours), 'scheduled', e.enrolled_at
+        FROM enrollments e
+        JOIN automation_steps s ON s.automation_id = e.automation_id
+        "#,
+        subscriber_id,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Stops every automation a subscriber is in: the steps that were still to
+/// come are skipped.
+#[tracing::instrument(name = "Stop the automations of a subscriber", skip(transaction))]
+pub async fn stop_enrollments(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE automation_enrollments
+        SET stopped_at = now()
+        WHERE subscriber_id = $1 AND stopped_at IS NULL
+        "#,
+        subscriber_id,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE automation_step_deliveries
+        SET status = 'skipped', updated_at = now()
+        WHERE status = 'scheduled'
+            AND enrollment_id IN (SELECT id FROM automation_enrollments WHERE subscriber_id = $1)
+        "#,
+        subscriber_id,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,237 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use axum_extra::extract::cookie::Key;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::attribute_schema::get_attribute_schema;
+use crate::automation::stop_enrollments;
+use crate::background_worker::{self, ExecutionOutcome, FailedTask, TaskError};
+use crate::domain::{SubscriberEmail, SubscriptionStatus};
+use crate::email_client::{EmailClient, EmailMessage};
+use crate::email_delivery::{record_email_delivery, EmailKind};
+use crate::newsletter_template::{NewsletterTemplate, Recipient};
+use crate::routes::{decode_status, preferences_link, suppress_subscriber};
+
+/// Sends the automation steps that are due, next to the web server.
+#[derive(Clone)]
+pub struct AutomationWorker {
+    pool: PgPool,
+    email_client: EmailClient,
+    base_url: String,
+    key: Key,
+}
+
+impl AutomationWorker {
+    pub fn new(pool: PgPool, email_client: EmailClient, base_url: String, key: Key) -> Self {
+        Self { pool, email_client, base_url, key }
+    }
+
+    pub async fn run_until_stopped(self) -> Result<(), anyhow::Error> {
+        let worker = &self;
+        background_worker::run_until_stopped(
+            move || worker.try_execute_task(),
+            Duration::from_secs(10),
+            "an automation step",
+        )
+        .await
+    }
+
+    /// Sends one due step, if there is any. Several workers can run at once,
+    /// each step is only picked up by one of them.
+    #[tracing::instrument(
+        skip_all,
+        fields(enrollment_id = tracing::field::Empty, step_id = tracing::field::Empty),
+        err
+    )]
+    pub async fn try_execute_task(&self) -> Result<ExecutionOutcome, anyhow::Error> {
+        let mut transaction = self.pool.begin().await.context("Failed to acquire a database connection.")?;
+        let Some(task) = dequeue_task(&mut transaction).await.context("Failed to dequeue a step.")? else {
+            return Ok(ExecutionOutcome::EmptyQueue);
+        };
+        tracing::Span::current()
+            .record("enrollment_id", tracing::field::display(task.enrollment_id))
+            .record("step_id", tracing::field::display(task.step_id));
+        if task.subscriber_status != SubscriptionStatus::Confirmed || task.stopped_at.is_some() {
+            // Whatever took them off the list should already have stopped
+            // the automation, e.g. a bounce reported by the provider.
+            stop_enrollments(&mut transaction, task.subscriber_id)
+                .await
+                .context("Failed to stop the automations of a subscriber.")?;
+        } else {
+            let outcome = match self.send_step(&mut transaction, &task).await {
+                Ok(delivery) => StepOutcome::Sent(delivery),
+                Err(e) => StepOutcome::Failed(e.into_failed_task(task.attempts, "an automation step")),
+            };
+            record_outcome(&mut transaction, &task, outcome)
+                .await
+                .context("Failed to record the outcome of an automation step.")?;
+        }
+        transaction.commit().await.context("Failed to commit SQL transaction for an automation step.")?;
+        Ok(ExecutionOutcome::TaskCompleted)
+    }
+
+    async fn send_step(
+        &self,
+        transaction: &mut Transaction<'_, Postgres>,
+        task: &Task,
+    ) -> Result<Option<Uuid>, TaskError> {
+        let email = SubscriberEmail::parse(task.email.clone())
+            .map_err(|e| TaskError::Permanent(anyhow::anyhow!(e).context("The stored email address is invalid.")))?;
+        // The schema may have changed since the step was written.
+        let schema = get_attribute_schema(&mut *transaction).await.map_err(TaskError::Transient)?;
+        let parse = |template: &str| {
+            NewsletterTemplate::parse(template, &schema).map_err(|e| {
+                TaskError::Permanent(anyhow::anyhow!("The step no longer matches the attributes: {}", e))
+            })
+        };
+        let subject = parse(&task.subject)?;
+        let html = parse(&task.html_content)?;
+        let text = parse(&task.text_content)?;
+        let empty = serde_json::Map::new();
+        let recipient = Recipient {
+            name: &task.name,
+            email: &task.email,
+            attributes: task.attributes.as_object().unwrap_or(&empty),
+        };
+        let preferences_link = preferences_link(&self.base_url, &self.key, task.subscriber_id);
+        let message = EmailMessage::builder(
+            email.clone(),
+            subject.render(&recipient, str::to_string),
+            format!(
+                "{}<p><a href=\"{}\">Manage your preferences or unsubscribe</a></p>",
+                html.render(&recipient, htmlescape::encode_minimal),
+                preferences_link,
+            ),
+            format!(
+                "{}\n\nManage your preferences or unsubscribe: {}",
+                text.render(&recipient, str::to_string),
+                preferences_link,
+            ),
+        )
+        .tag("automation")
+        .metadata("automation_step_id", task.step_id.to_string())
+        .build()
+        .map_err(|e| TaskError::Permanent(anyhow::Error::new(e)))?;
+        let receipt = match self.email_client.send(&message).await {
+            Ok(receipt) => receipt,
+            Err(e) if e.is_undeliverable_recipient() => {
+                suppress_subscriber(&self.pool, &email)
+                    .await
+                    .context("Failed to suppress an undeliverable subscriber.")
+                    .map_err(TaskError::Transient)?;
+                stop_enrollments(transaction, task.subscriber_id)
+                    .await
+                    .context("Failed to stop the automations of a subscriber.")
+                    .map_err(TaskError::Transient)?;
+                return Err(TaskError::Permanent(anyhow::Error::new(e)));
+            }
+            Err(e) => return Err(TaskError::Transient(anyhow::Error::new(e))),
+        };
+        // The email is already on its way, so losing track of it must not get
+        // it sent again.
+        match record_email_delivery(&self.pool, task.subscriber_id, EmailKind::Automation, &receipt).await {
+            Ok(delivery_id) => Ok(Some(delivery_id)),
+            Err(e) => {
+                tracing::error!(error.cause_chain = ?e, "Failed to record an automation email delivery.");
+                Ok(None)
+            }
+        }
+    }
+}
+
+enum StepOutcome {
+    Sent(Option<Uuid>),
+    Failed(FailedTask),
+}
+
+struct Task {
+    enrollment_id: Uuid,
+    step_id: Uuid,
+    attempts: i32,
+    subscriber_id: Uuid,
+    stopped_at: Option<DateTime<Utc>>,
+    subscriber_status: SubscriptionStatus,
+    email: String,
+    name: String,
+    attributes: serde_json::Value,
+    subject: String,
+    text_content: String,
+    html_content: String,
+}
+
+#[tracing::instrument(skip_all)]
+async fn dequeue_task(transaction: &mut Transaction<'_, Postgres>) -> Result<Option<Task>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            d.enrollment_id, d.step_id, d.attempts,
+            e.subscriber_id, e.stopped_at,
+            s.status AS subscriber_status, s.email, s.name, s.attributes,
+            st.subject, st.text_content, st.html_content
+        FROM automation_step_deliveries d
+        JOIN automation_enrollments e ON e.id = d.enrollment_id
+        JOIN subscriptions s ON s.id = e.subscriber_id
+        JOIN automation_steps st ON st.id = d.step_id
+        WHERE d.status = 'scheduled' AND d.due_at <= now()
+        ORDER BY d.due_at
+        FOR UPDATE OF d SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(transaction)
+    .await?;
+    row.map(|row| {
+        Ok(Task {
+            enrollment_id: row.enrollment_id,
+            step_id: row.step_id,
+            attempts: row.attempts,
+            subscriber_id: row.subscriber_id,
+            stopped_at: row.stopped_at,
+            subscriber_status: decode_status(&row.subscriber_status)?,
+            email: row.email,
+            name: row.name,
+            attributes: row.attributes,
+            subject: row.subject,
+            text_content: row.text_content,
+            html_content: row.html_content,
+        })
+    })
+    .transpose()
+}
+
+#[tracing::instrument(skip(transaction, task, outcome))]
+async fn record_outcome(
+    transaction: &mut Transaction<'_, Postgres>,
+    task: &Task,
+    outcome: StepOutcome,
+) -> Result<(), sqlx::Error> {
+    let (status, error, email_delivery_id, retry_in_minutes) = match outcome {
+        StepOutcome::Sent(email_delivery_id) => ("sent", None, email_delivery_id, 0),
+        StepOutcome::Failed(FailedTask::GaveUp { error }) => ("failed", Some(error), None, 0),
+        StepOutcome::Failed(FailedTask::RetryIn { minutes, error }) => ("scheduled", Some(error), None, minutes),
+    };
+    sqlx::query!(
+        r#"
+        UPDATE automation_step_deliveries
+        SET status = $3,
+            attempts = attempts + 1,
+            last_error = $4,
+            email_delivery_id = $5,
+            due_at = CASE WHEN $3 = 'scheduled' THEN now() + make_interval(mins => $6) ELSE due_at END,
+            updated_at = now()
+        WHERE enrollment_id = $1 AND step_id = $2
+        "#,
+        task.enrollment_id,
+        task.step_id,
+        status,
+        error,
+        email_delivery_id,
+        retry_in_minutes,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
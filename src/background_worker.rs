@@ -0,0 +1,95 @@
+use std::future::Future;
+use std::time::Duration;
+
+// A task that keeps failing is given up on after this many attempts.
+pub const MAX_ATTEMPTS: i32 = 5;
+
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+pub enum TaskError {
+    /// Trying again won't help.
+    Permanent(anyhow::Error),
+    Transient(anyhow::Error),
+}
+
+/// What becomes of a task that failed.
+pub enum FailedTask {
+    GaveUp { error: String },
+    RetryIn { minutes: i32, error: String },
+}
+
+impl TaskError {
+    /// Decides whether the task is tried again, given the attempts made
+    /// before this one, and logs it. `task` names it in the logs.
+    pub fn into_failed_task(self, attempts: i32, task: &str) -> FailedTask {
+        match self {
+            Self::Permanent(e) => {
+                tracing::warn!(error.cause_chain = ?e, "Gave up on {}.", task);
+                FailedTask::GaveUp { error: format!("{:#}", e) }
+            }
+            Self::Transient(e) if attempts + 1 >= MAX_ATTEMPTS => {
+                tracing::warn!(error.cause_chain = ?e, "Gave up on {} after retrying it.", task);
+                FailedTask::GaveUp { error: format!("{:#}", e) }
+            }
+            Self::Transient(e) => {
+                tracing::warn!(error.cause_chain = ?e, "Failed {}, it will be retried.", task);
+                // 1, 4, 9, 16 minutes.
+                FailedTask::RetryIn { minutes: (attempts + 1).pow(2), error: format!("{:#}", e) }
+            }
+        }
+    }
+}
+
+/// Executes tasks one after the other, waiting for `idle` whenever the queue
+/// is empty. `task` names them in the logs.
+pub async fn run_until_stopped<F, Fut>(mut execute_task: F, idle: Duration, task: &str) -> Result<(), anyhow::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<ExecutionOutcome, anyhow::Error>>,
+{
+    loop {
+        match execute_task().await {
+            Ok(ExecutionOutcome::EmptyQueue) => tokio::time::sleep(idle).await,
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+            Err(e) => {
+                tracing::error!(error.cause_chain = ?e, "Failed to execute {}.", task);
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FailedTask, TaskError, MAX_ATTEMPTS};
+
+    fn transient() -> TaskError {
+        TaskError::Transient(anyhow::anyhow!("timed out"))
+    }
+
+    #[test]
+    fn a_transient_failure_is_retried_later_each_time() {
+        let delays: Vec<i32> = (0..MAX_ATTEMPTS - 1)
+            .map(|attempts| match transient().into_failed_task(attempts, "a task") {
+                FailedTask::RetryIn { minutes, .. } => minutes,
+                FailedTask::GaveUp { .. } => panic!("Gave up after {} attempts", attempts),
+            })
+            .collect();
+        assert_eq!(delays, vec![1, 4, 9, 16]);
+    }
+
+    #[test]
+    fn a_transient_failure_is_given_up_on_after_the_last_attempt() {
+        let failed = transient().into_failed_task(MAX_ATTEMPTS - 1, "a task");
+        assert!(matches!(failed, FailedTask::GaveUp { error } if error == "timed out"));
+    }
+
+    #[test]
+    fn a_permanent_failure_is_never_retried() {
+        let failed = TaskError::Permanent(anyhow::anyhow!("invalid address")).into_failed_task(0, "a task");
+        assert!(matches!(failed, FailedTask::GaveUp { .. }));
+    }
+}
@@ -0,0 +1,203 @@
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use axum_extra::extract::cookie::Key;
+use chrono::Utc;
+
+use crate::domain::SubscriberEmail;
+use crate::rate_limit::RateLimiter;
+use crate::signature;
+
+const FORM_TOKEN_PURPOSE: &str = "subscribe-form";
+
+/// Why a subscription looked automated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BotRejection {
+    /// The hidden field only bots fill in had a value.
+    Honeypot,
+    /// The form wasn't the one served by the home page.
+    InvalidFormToken,
+    /// The form came back faster than a person can fill it in.
+    SubmittedTooFast,
+    /// The form was served too long ago to be replayed.
+    ExpiredFormToken,
+    IpRateLimited,
+    DomainRateLimited,
+}
+
+impl BotRejection {
+    pub const ALL: [Self; 6] = [
+        Self::Honeypot,
+        Self::InvalidFormToken,
+        Self::SubmittedTooFast,
+        Self::ExpiredFormToken,
+        Self::IpRateLimited,
+        Self::DomainRateLimited,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Honeypot => "honeypot",
+            Self::InvalidFormToken => "invalid_form_token",
+            Self::SubmittedTooFast => "submitted_too_fast",
+            Self::ExpiredFormToken => "expired_form_token",
+            Self::IpRateLimited => "ip_rate_limited",
+            Self::DomainRateLimited => "domain_rate_limited",
+        }
+    }
+
+    pub fn is_rate_limit(&self) -> bool {
+        matches!(self, Self::IpRateLimited | Self::DomainRateLimited)
+    }
+}
+
+/// The checks standing between the subscription form and our email quota.
+/// Each layer is cheap on its own; together they keep scripts from making us
+/// write to arbitrary addresses.
+pub struct BotProtection {
+    key: Key,
+    min_form_time: chrono::Duration,
+    max_form_age: chrono::Duration,
+    ip_limiter: RateLimiter,
+    domain_limiter: RateLimiter,
+    rejections: [AtomicU64; BotRejection::ALL.len()],
+}
+
+impl BotProtection {
+    pub fn new(
+        key: Key,
+        min_form_time: chrono::Duration,
+        max_form_age: chrono::Duration,
+        ip_limiter: RateLimiter,
+        domain_limiter: RateLimiter,
+    ) -> Self {
+        Self {
+            key,
+            min_form_time,
+            max_form_age,
+            ip_limiter,
+            domain_limiter,
+            rejections: Default::default(),
+        }
+    }
+
+    /// A token for a freshly served form, recording when it was served.
+    pub fn form_token(&self) -> String {
+        signature::sign(&self.key, FORM_TOKEN_PURPOSE, &Utc::now().timestamp().to_string())
+    }
+
+    pub fn check_form(&self, honeypot: Option<&str>, form_token: Option<&str>) -> Result<(), BotRejection> {
+        if honeypot.is_some_and(|value| !value.is_empty()) {
+            return Err(BotRejection::Honeypot);
+        }
+        let served_at = form_token
+            .and_then(|token| signature::verify(&self.key, FORM_TOKEN_PURPOSE, token))
+            .and_then(|served_at| served_at.parse::<i64>().ok())
+            .ok_or(BotRejection::InvalidFormToken)?;
+        let time_on_page = chrono::Duration::seconds(Utc::now().timestamp() - served_at);
+        if time_on_page < self.min_form_time {
+            return Err(BotRejection::SubmittedTooFast);
+        }
+        if time_on_page > self.max_form_age {
+            return Err(BotRejection::ExpiredFormToken);
+        }
+        Ok(())
+    }
+
+    /// Requests without a known client address are not limited.
+    pub fn check_ip(&self, ip: Option<IpAddr>) -> Result<(), BotRejection> {
+        match ip {
+            Some(ip) if !self.ip_limiter.check(&ip.to_string()) => Err(BotRejection::IpRateLimited),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn check_domain(&self, email: &SubscriberEmail) -> Result<(), BotRejection> {
+        let (_, domain) = email
+            .as_ref()
+            .rsplit_once('@')
+            .expect("A valid email address has an @");
+        if self.domain_limiter.check(&domain.to_lowercase()) {
+            Ok(())
+        } else {
+            Err(BotRejection::DomainRateLimited)
+        }
+    }
+
+    pub fn record(&self, rejection: BotRejection) {
+        self.counter(rejection).fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn rejections(&self, rejection: BotRejection) -> u64 {
+        self.counter(rejection).load(Ordering::Relaxed)
+    }
+
+    fn counter(&self, rejection: BotRejection) -> &AtomicU64 {
+        let index = BotRejection::ALL
+            .iter()
+            .position(|r| *r == rejection)
+            .expect("Every rejection is listed in ALL");
+        &self.rejections[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BotProtection, BotRejection};
+    use crate::rate_limit::RateLimiter;
+    use crate::signature;
+    use axum_extra::extract::cookie::Key;
+    use std::time::Duration;
+
+    fn protection(min_form_seconds: i64) -> BotProtection {
+        BotProtection::new(
+            Key::generate(),
+            chrono::Duration::seconds(min_form_seconds),
+            chrono::Duration::hours(1),
+            RateLimiter::new(1, Duration::from_secs(60)),
+            RateLimiter::new(1, Duration::from_secs(60)),
+        )
+    }
+
+    #[test]
+    fn a_filled_in_honeypot_is_rejected() {
+        let protection = protection(0);
+        let token = protection.form_token();
+        assert_eq!(protection.check_form(Some("http://spam"), Some(&token)), Err(BotRejection::Honeypot));
+        assert_eq!(protection.check_form(Some(""), Some(&token)), Ok(()));
+    }
+
+    #[test]
+    fn a_missing_or_forged_form_token_is_rejected() {
+        let protection = protection(0);
+        assert_eq!(protection.check_form(None, None), Err(BotRejection::InvalidFormToken));
+        let forged = signature::sign(&Key::generate(), "subscribe-form", "0");
+        assert_eq!(protection.check_form(None, Some(&forged)), Err(BotRejection::InvalidFormToken));
+    }
+
+    #[test]
+    fn a_form_submitted_right_away_is_rejected() {
+        let protection = protection(60);
+        let token = protection.form_token();
+        assert_eq!(protection.check_form(None, Some(&token)), Err(BotRejection::SubmittedTooFast));
+    }
+
+    #[test]
+    fn an_old_form_token_is_rejected() {
+        let protection = protection(0);
+        let served_two_hours_ago = (chrono::Utc::now() - chrono::Duration::hours(2)).timestamp();
+        let token = signature::sign(&protection.key, "subscribe-form", &served_two_hours_ago.to_string());
+        assert_eq!(protection.check_form(None, Some(&token)), Err(BotRejection::ExpiredFormToken));
+    }
+
+    #[test]
+    fn rejections_are_counted_per_reason() {
+        let protection = protection(0);
+        protection.record(BotRejection::Honeypot);
+        protection.record(BotRejection::Honeypot);
+        protection.record(BotRejection::IpRateLimited);
+        assert_eq!(protection.rejections(BotRejection::Honeypot), 2);
+        assert_eq!(protection.rejections(BotRejection::IpRateLimited), 1);
+        assert_eq!(protection.rejections(BotRejection::DomainRateLimited), 0);
+    }
+}
@@ -1,15 +1,25 @@
+use axum_extra::extract::cookie::Key;
 use secrecy::{ExposeSecret, Secret};
 use serde::Deserialize;
 use serde_aux::field_attributes::deserialize_number_from_string;
 use sqlx::{postgres::{PgConnectOptions, PgSslMode}, ConnectOptions};
 
+use crate::bot_protection::BotProtection;
 use crate::domain::SubscriberEmail;
+use crate::domain_blocklist::{DomainBlocklist, DEFAULT_DISPOSABLE_DOMAINS};
+use crate::rate_limit::RateLimiter;
+use crate::email_client::{
+    CircuitBreaker, DkimAlgorithm, DkimSigner, EmailClient, Mailbox, RetryPolicy, SmtpTls,
+    SmtpTransport,
+};
 
 #[derive(Deserialize, Clone)]
 pub struct Settings {
     pub application: ApplicationSettings,
     pub database: DatabaseSettings,
     pub email_client: EmailClientSettings,
+    pub subscriptions: SubscriptionSettings,
+    pub branding: BrandingSettings,
     pub redis_uri: Secret<String>,
 }
 
@@ -20,6 +30,12 @@ pub struct ApplicationSettings {
     pub host: String,
     pub base_url: String,
     pub hmac_secret: Secret<String>,
+    /// Take the client address from the last `X-Forwarded-For` entry. Only
+    /// safe behind a proxy that sets the header.
+    #[serde(default)]
+    pub trust_forwarded_for: bool,
+    // Filled in from APP_ENVIRONMENT by `get_configuration`.
+    pub environment: Environment,
 }
 
 #[derive(Deserialize, Clone)]
@@ -35,13 +51,113 @@ pub struct DatabaseSettings {
 
 #[derive(Deserialize, Clone)]
 pub struct EmailClientSettings {
+    pub transport: EmailTransport,
     pub sender_email: String,
     pub base_url: String,
     pub authorization_token: Secret<String>,
     pub timeout_milliseconds: u64,
+    pub circuit_breaker: CircuitBreakerSettings,
+    pub retry: RetrySettings,
+    // Only required when `transport` is "smtp".
+    pub smtp: Option<SmtpSettings>,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailTransport {
+    Postmark,
+    /// Keep outgoing email in memory so it can be browsed at /dev/mailbox.
+    Capture,
+    Smtp,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    pub tls: SmtpTls,
+    pub username: Option<String>,
+    pub password: Option<Secret<String>>,
+    pub dkim: Option<DkimSettings>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct DkimSettings {
+    pub domain: String,
+    pub selector: String,
+    pub algorithm: DkimAlgorithm,
+    /// PKCS#1 PEM for RSA, the base64-encoded seed for Ed25519.
+    pub private_key: Secret<String>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct CircuitBreakerSettings {
+    pub failure_threshold: u32,
+    pub cooldown_milliseconds: u64,
+}
+
+impl CircuitBreakerSettings {
+    pub fn cooldown(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.cooldown_milliseconds)
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct RetrySettings {
+    pub max_retries: u32,
+    pub base_delay_milliseconds: u64,
+}
+
+impl RetrySettings {
+    pub fn policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_retries: self.max_retries,
+            base_delay: std::time::Duration::from_millis(self.base_delay_milliseconds),
+        }
+    }
 }
 
 impl EmailClientSettings {
+    pub fn client(self) -> EmailClient {
+        let sender_email = self.sender().expect("Invalid sender email address.");
+        if self.transport == EmailTransport::Capture {
+            return EmailClient::capturing(sender_email, std::sync::Arc::new(Mailbox::new()));
+        }
+        let timeout = self.timeout();
+        let circuit_breaker = CircuitBreaker::new(
+            self.circuit_breaker.failure_threshold,
+            self.circuit_breaker.cooldown(),
+        );
+        if self.transport == EmailTransport::Smtp {
+            let smtp = self
+                .smtp
+                .expect("The smtp transport requires email_client.smtp settings.");
+            let dkim = smtp.dkim.map(|dkim| {
+                DkimSigner::new(dkim.domain, dkim.selector, dkim.algorithm, &dkim.private_key)
+                    .expect("Invalid DKIM settings.")
+            });
+            let credentials = smtp.username.zip(smtp.password);
+            let transport =
+                SmtpTransport::new(&smtp.host, smtp.port, smtp.tls, credentials, timeout, dkim)
+                    .expect("Invalid SMTP settings.");
+            return EmailClient::smtp(
+                sender_email,
+                transport,
+                circuit_breaker,
+                self.retry.policy(),
+            );
+        }
+        EmailClient::new(
+            sender_email,
+            self.base_url,
+            self.authorization_token,
+            timeout,
+            circuit_breaker,
+            self.retry.policy(),
+        )
+    }
+
     pub fn sender(&self) -> Result<SubscriberEmail, String> {
         SubscriberEmail::parse(self.sender_email.clone())
     }
@@ -51,6 +167,122 @@ impl EmailClientSettings {
     }
 }
 
+/// How the pages subscribers see are branded.
+#[derive(Deserialize, Clone, Debug)]
+pub struct BrandingSettings {
+    pub name: String,
+    /// Any CSS color, used for headings and buttons.
+    pub accent_color: String,
+    pub logo_url: Option<String>,
+    pub support_email: Option<String>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct SubscriptionSettings {
+    pub confirmation_token_ttl_hours: u32,
+    /// The privacy policy currently shown next to the subscription form,
+    /// recorded with every consent.
+    pub privacy_policy_version: String,
+    /// Limits `POST /subscriptions/resend` per email address.
+    pub resend_rate_limit: RateLimitSettings,
+    /// How long the link to download a data export keeps working.
+    pub data_export_link_ttl_hours: u32,
+    /// Limits `POST /subscriptions/data_export` per email address.
+    pub data_export_rate_limit: RateLimitSettings,
+    /// How long the link that confirms deleting a subscriber's data keeps
+    /// working.
+    pub erasure_link_ttl_hours: u32,
+    pub bot_protection: BotProtectionSettings,
+    #[serde(default)]
+    pub domain_blocklist: DomainBlocklistSettings,
+    /// The topics subscribers can pick from on their preferences page.
+    #[serde(default)]
+    pub topics: Vec<TopicSettings>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct BotProtectionSettings {
+    /// A person needs at least this long to fill in the subscription form.
+    pub min_form_seconds: u32,
+    /// How long a served subscription form can be submitted.
+    pub max_form_age_hours: u32,
+    /// Limits `POST /subscriptions` per client address.
+    pub ip_rate_limit: RateLimitSettings,
+    /// Limits `POST /subscriptions` per email domain.
+    pub domain_rate_limit: RateLimitSettings,
+}
+
+impl BotProtectionSettings {
+    pub fn protection(&self, key: Key) -> BotProtection {
+        BotProtection::new(
+            key,
+            chrono::Duration::seconds(self.min_form_seconds.into()),
+            chrono::Duration::hours(self.max_form_age_hours.into()),
+            self.ip_rate_limit.limiter(),
+            self.domain_rate_limit.limiter(),
+        )
+    }
+}
+
+/// Email domains signups are refused from.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct DomainBlocklistSettings {
+    /// A file with one domain per line, replacing the list shipped with the
+    /// application.
+    pub path: Option<String>,
+    /// Domains accepted even though they, or a parent domain, are blocked.
+    #[serde(default)]
+    pub allowlist: Vec<String>,
+}
+
+impl DomainBlocklistSettings {
+    pub fn load(&self) -> Result<DomainBlocklist, std::io::Error> {
+        let blocklist = match &self.path {
+            Some(path) => std::fs::read_to_string(path)?,
+            None => DEFAULT_DISPOSABLE_DOMAINS.to_string(),
+        };
+        Ok(DomainBlocklist::new(&blocklist, self.allowlist.iter().map(String::as_str)))
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct TopicSettings {
+    /// Stored against subscribers, so it must not change once in use.
+    pub id: String,
+    pub name: String,
+}
+
+impl SubscriptionSettings {
+    pub fn confirmation_token_ttl(&self) -> chrono::Duration {
+        chrono::Duration::hours(self.confirmation_token_ttl_hours.into())
+    }
+
+    pub fn data_export_link_ttl(&self) -> chrono::Duration {
+        chrono::Duration::hours(self.data_export_link_ttl_hours.into())
+    }
+
+    pub fn erasure_link_ttl(&self) -> chrono::Duration {
+        chrono::Duration::hours(self.erasure_link_ttl_hours.into())
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct RateLimitSettings {
+    pub max_requests: u32,
+    pub window_seconds: u64,
+}
+
+impl RateLimitSettings {
+    pub fn limiter(&self) -> RateLimiter {
+        RateLimiter::new(
+            self.max_requests,
+            std::time::Duration::from_secs(self.window_seconds),
+        )
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
 pub enum Environment {
     Local,
     Production,
@@ -112,6 +344,7 @@ pub fn get_configuration() -> Result<Settings, config::ConfigError> {
         .add_source(config::File::from(configuration_directory.join("base")))
         .add_source(config::File::from(configuration_directory.join(environment.as_str())))
         .add_source(config::Environment::with_prefix("APP").separator("__"))
+        .set_override("application.environment", environment.as_str())?
         .build()?
         .try_deserialize()
 }
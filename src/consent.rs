@@ -0,0 +1,179 @@
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::routes::ClientInfo;
+
+/// What a subscriber consented to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsentEvent {
+    /// The subscription form was submitted.
+    Subscribed,
+    /// The confirmation link was followed.
+    Confirmed,
+    /// An admin imported the subscriber as confirmed, attesting to consent
+    /// given elsewhere.
+    Imported,
+    /// An admin confirmed the subscriber by hand, attesting to consent given
+    /// elsewhere.
+    ConfirmedByAdmin,
+}
+
+impl ConsentEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConsentEvent::Subscribed => "subscribed",
+            ConsentEvent::Confirmed => "confirmed",
+            ConsentEvent::Imported => "imported",
+            ConsentEvent::ConfirmedByAdmin => "confirmed_by_admin",
+        }
+    }
+}
+
+/// Appends to the consent log, in the same transaction as the change it
+/// gives proof for.
+#[tracing::instrument(name = "Record consent", skip(transaction, client, form_source))]
+pub async fn record_consent(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    event: ConsentEvent,
+    client: &ClientInfo,
+    form_source: Option<&str>,
+    privacy_policy_version: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO consent_records (
+            id, subscriber_id, event, email, recorded_at,
+            ip_address, user_agent, form_source, privacy_policy_version
+        )
+        SELECT $1, id, $2, email, now(), $3, $4, $5, $6
+        FROM subscriptions
+        WHERE id = $7
+        "#,
+        Uuid::new_v4(),
+        event.as_str(),
+        client.ip.map(|ip| ip.to_string()),
+        client.user_agent,
+        form_source,
+        privacy_policy_version,
+        subscriber_id,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// `record_consent` for consent an admin vouches for: `attested_by` names
+/// the admin, `attestation` is their statement of how the subscriber
+/// consented.
+#[tracing::instrument(name = "Record attested consent", skip(transaction, client, attestation))]
+pub async fn record_attested_consent(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    event: ConsentEvent,
+    client: &ClientInfo,
+    attested_by: &str,
+    attestation: &str,
+    privacy_policy_version: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO consent_records (
+            id, subscriber_id, event, email, recorded_at,
+            ip_address, user_agent, privacy_policy_version, attested_by, attestation
+        )
+        SELECT $1, id, $2, email, now(), $3, $4, $5, $6, $7
+        FROM subscriptions
+        WHERE id = $8
+        "#,
+        Uuid::new_v4(),
+        event.as_str(),
+        client.ip.map(|ip| ip.to_string()),
+        client.user_agent,
+        privacy_policy_version,
+        attested_by,
+        attestation,
+        subscriber_id,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// `record_attested_consent` for a whole batch of subscribers, e.g. an
+/// import. `form_source` says which one.
+#[tracing::instrument(
+    name = "Record attested consent for a batch",
+    skip(transaction, subscriber_ids, client, form_source, attestation)
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn record_attested_consent_batch(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_ids: &[Uuid],
+    event: ConsentEvent,
+    client: &ClientInfo,
+    form_source: Option<&str>,
+    attested_by: &str,
+    attestation: &str,
+    privacy_policy_version: &str,
+) -> Result<(), sqlx::Error> {
+    let record_ids: Vec<Uuid> = subscriber_ids.iter().map(|_| Uuid::new_v4()).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO consent_records (
+            id, subscriber_id, event, email, recorded_at,
+            ip_address, user_agent, form_source, privacy_policy_version, attested_by, attestation
+        )
+        SELECT batch.record_id, subscriptions.id, $3, subscriptions.email, now(), $4, $5, $6, $7, $8, $9
+        FROM UNNEST($1::uuid[], $2::uuid[]) AS batch(record_id, subscriber_id)
+        JOIN subscriptions ON subscriptions.id = batch.subscriber_id
+        "#,
+        &record_ids,
+        subscriber_ids,
+        event.as_str(),
+        client.ip.map(|ip| ip.to_string()),
+        client.user_agent,
+        form_source,
+        privacy_policy_version,
+        attested_by,
+        attestation,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct ConsentRecord {
+    pub event: String,
+    pub email: String,
+    pub recorded_at: DateTime<Utc>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub form_source: Option<String>,
+    pub privacy_policy_version: String,
+    pub attested_by: Option<String>,
+    pub attestation: Option<String>,
+}
+
+#[tracing::instrument(name = "Fetch the consent records of a subscriber", skip(executor))]
+pub async fn get_consent_records(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+) -> Result<Vec<ConsentRecord>, sqlx::Error> {
+    sqlx::query_as!(
+        ConsentRecord,
+        r#"
+        SELECT
+            event, email, recorded_at, ip_address, user_agent, form_source, privacy_policy_version,
+            attested_by, attestation
+        FROM consent_records
+        WHERE subscriber_id = $1
+        ORDER BY recorded_at
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(executor)
+    .await
+}
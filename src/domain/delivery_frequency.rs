@@ -0,0 +1,52 @@
+/// How often a subscriber wants to hear from us.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryFrequency {
+    Immediately,
+    Weekly,
+    Monthly,
+}
+
+impl DeliveryFrequency {
+    pub const ALL: [Self; 3] = [Self::Immediately, Self::Weekly, Self::Monthly];
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|frequency| frequency.as_str() == s)
+            .ok_or_else(|| format!("{} is not a valid delivery frequency", s))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Immediately => "immediately",
+            Self::Weekly => "weekly",
+            Self::Monthly => "monthly",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Immediately => "Every issue, as soon as it is out",
+            Self::Weekly => "A weekly digest",
+            Self::Monthly => "A monthly digest",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DeliveryFrequency;
+
+    #[test]
+    fn every_frequency_round_trips_through_its_name() {
+        for frequency in DeliveryFrequency::ALL {
+            assert_eq!(DeliveryFrequency::parse(frequency.as_str()), Ok(frequency));
+        }
+    }
+
+    #[test]
+    fn an_unknown_frequency_is_rejected() {
+        assert!(DeliveryFrequency::parse("hourly").is_err());
+        assert!(DeliveryFrequency::parse("Weekly").is_err());
+    }
+}
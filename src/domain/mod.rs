@@ -1,7 +1,15 @@
 mod subscriber_name;
 mod subscriber_email;
 mod new_subscriber;
+mod delivery_frequency;
+mod subscriber_attributes;
+mod subscription_status;
 
 pub use subscriber_name::SubscriberName;
 pub use subscriber_email::SubscriberEmail;
 pub use new_subscriber::NewSubscriber;
+pub use delivery_frequency::DeliveryFrequency;
+pub use subscription_status::{IllegalTransition, SubscriptionStatus};
+pub use subscriber_attributes::{
+    AttributeDefinition, AttributeError, AttributeKind, AttributeSchema, SubscriberAttributes,
+};
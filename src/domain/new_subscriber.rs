@@ -1,7 +1,9 @@
 pub use crate::domain::SubscriberName;
 pub use crate::domain::SubscriberEmail;
+pub use crate::domain::SubscriberAttributes;
 
 pub struct NewSubscriber {
     pub email: SubscriberEmail,
     pub name: SubscriberName,
+    pub attributes: SubscriberAttributes,
 }
@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+
+use serde_json::{Map, Value};
+
+/// The type of a custom attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttributeKind {
+    Text,
+    Number,
+    Boolean,
+    /// A calendar date, stored as `YYYY-MM-DD`.
+    Date,
+}
+
+impl AttributeKind {
+    pub const ALL: [Self; 4] = [Self::Text, Self::Number, Self::Boolean, Self::Date];
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == s)
+            .ok_or_else(|| format!("{} is not a valid attribute type", s))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Text => "text",
+            Self::Number => "number",
+            Self::Boolean => "boolean",
+            Self::Date => "date",
+        }
+    }
+
+    // What a form sends: always text, a checkbox only when it is ticked.
+    fn parse_form_value(&self, value: &str) -> Result<Value, String> {
+        match self {
+            Self::Text => Ok(Value::String(value.to_string())),
+            Self::Number => value
+                .parse::<f64>()
+                .ok()
+                .and_then(serde_json::Number::from_f64)
+                .map(Value::Number)
+                .ok_or_else(|| "must be a number".to_string()),
+            Self::Boolean => match value {
+                "on" | "true" | "yes" | "1" => Ok(Value::Bool(true)),
+                "false" | "no" | "0" => Ok(Value::Bool(false)),
+                _ => Err("must be true or false".to_string()),
+            },
+            Self::Date => parse_date(value),
+        }
+    }
+
+    fn parse_json_value(&self, value: &Value) -> Result<Value, String> {
+        match (self, value) {
+            (Self::Text, Value::String(_)) | (Self::Number, Value::Number(_)) | (Self::Boolean, Value::Bool(_)) => {
+                Ok(value.clone())
+            }
+            (Self::Date, Value::String(date)) => parse_date(date),
+            (Self::Text, _) => Err("must be a string".to_string()),
+            (Self::Number, _) => Err("must be a number".to_string()),
+            (Self::Boolean, _) => Err("must be true or false".to_string()),
+            (Self::Date, _) => Err("must be a date (YYYY-MM-DD)".to_string()),
+        }
+    }
+}
+
+fn parse_date(value: &str) -> Result<Value, String> {
+    chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(|date| Value::String(date.format("%Y-%m-%d").to_string()))
+        .map_err(|_| "must be a date (YYYY-MM-DD)".to_string())
+}
+
+const MAX_ATTRIBUTE_NAME_LENGTH: usize = 40;
+const MAX_TEXT_ATTRIBUTE_LENGTH: usize = 1000;
+
+/// A custom attribute admins asked subscribers for.
+#[derive(Debug, Clone)]
+pub struct AttributeDefinition {
+    pub name: String,
+    pub kind: AttributeKind,
+    pub required: bool,
+}
+
+impl AttributeDefinition {
+    /// Names end up in form field names and template variables, so they are
+    /// kept to lowercase letters, digits and underscores.
+    pub fn parse_name(s: &str) -> Result<String, String> {
+        let mut chars = s.chars();
+        let starts_with_a_letter = chars.next().is_some_and(|c| c.is_ascii_lowercase());
+        let is_valid = starts_with_a_letter
+            && s.len() <= MAX_ATTRIBUTE_NAME_LENGTH
+            && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+        if is_valid {
+            Ok(s.to_string())
+        } else {
+            Err(format!(
+                "{} is not a valid attribute name: use up to {} lowercase letters, digits and underscores, starting with a letter",
+                s, MAX_ATTRIBUTE_NAME_LENGTH,
+            ))
+        }
+    }
+}
+
+/// Why the value of an attribute was refused.
+#[derive(Debug, PartialEq, Eq)]
+pub struct AttributeError {
+    pub attribute: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for AttributeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.attribute, self.message)
+    }
+}
+
+/// Attribute values checked against the schema.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SubscriberAttributes(Map<String, Value>);
+
+impl SubscriberAttributes {
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.0.get(name)
+    }
+
+    pub fn to_json(&self) -> Value {
+        Value::Object(self.0.clone())
+    }
+}
+
+/// All the attribute definitions.
+#[derive(Debug, Clone, Default)]
+pub struct AttributeSchema(Vec<AttributeDefinition>);
+
+impl AttributeSchema {
+    pub fn new(definitions: Vec<AttributeDefinition>) -> Self {
+        Self(definitions)
+    }
+
+    pub fn definitions(&self) -> &[AttributeDefinition] {
+        &self.0
+    }
+
+    pub fn get(&self, name: &str) -> Option<&AttributeDefinition> {
+        self.0.iter().find(|definition| definition.name == name)
+    }
+
+    /// A single value from a JSON body, e.g. what a segment compares with.
+    pub fn parse_json_value(&self, name: &str, value: &Value) -> Result<Value, AttributeError> {
+        let error = |message: String| AttributeError { attribute: name.to_string(), message };
+        match self.get(name) {
+            None => Err(error("is not a known attribute".to_string())),
+            Some(definition) => definition.kind.parse_json_value(value).map_err(error),
+        }
+    }
+
+    /// Values from an HTML form. An empty field is a missing value.
+    pub fn parse_form(&self, values: &HashMap<String, String>) -> Result<SubscriberAttributes, Vec<AttributeError>> {
+        self.parse(
+            values.iter().map(|(name, value)| (name, value.trim())).filter(|(_, value)| !value.is_empty()),
+            |definition, value| definition.kind.parse_form_value(value),
+        )
+    }
+
+    /// Values from a JSON body. `null` is a missing value.
+    pub fn parse_json(&self, values: &Map<String, Value>) -> Result<SubscriberAttributes, Vec<AttributeError>> {
+        self.parse(
+            values.iter().filter(|(_, value)| !value.is_null()),
+            |definition, value| definition.kind.parse_json_value(value),
+        )
+    }
+
+    fn parse<'a, V>(
+        &self,
+        values: impl Iterator<Item = (&'a String, V)>,
+        parse_value: impl Fn(&AttributeDefinition, V) -> Result<Value, String>,
+    ) -> Result<SubscriberAttributes, Vec<AttributeError>> {
+        let mut attributes = Map::new();
+        let mut errors = Vec::new();
+        for (name, value) in values {
+            let result = match self.get(name) {
+                None => Err("is not a known attribute".to_string()),
+                Some(definition) => parse_value(definition, value).and_then(|value| match &value {
+                    Value::String(text) if text.chars().count() > MAX_TEXT_ATTRIBUTE_LENGTH => {
+                        Err(format!("must be at most {} characters long", MAX_TEXT_ATTRIBUTE_LENGTH))
+                    }
+                    _ => Ok(value),
+                }),
+            };
+            match result {
+                Ok(value) => {
+                    attributes.insert(name.clone(), value);
+                }
+                Err(message) => errors.push(AttributeError { attribute: name.clone(), message }),
+            }
+        }
+        for definition in &self.0 {
+            if definition.required && !attributes.contains_key(&definition.name)
+                && !errors.iter().any(|e| e.attribute == definition.name)
+            {
+                errors.push(AttributeError { attribute: definition.name.clone(), message: "is required".to_string() });
+            }
+        }
+        if errors.is_empty() {
+            Ok(SubscriberAttributes(attributes))
+        } else {
+            errors.sort_by(|a, b| a.attribute.cmp(&b.attribute));
+            Err(errors)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AttributeDefinition, AttributeKind, AttributeSchema};
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    fn schema() -> AttributeSchema {
+        AttributeSchema::new(vec![
+            AttributeDefinition { name: "company".into(), kind: AttributeKind::Text, required: true },
+            AttributeDefinition { name: "employees".into(), kind: AttributeKind::Number, required: false },
+            AttributeDefinition { name: "customer".into(), kind: AttributeKind::Boolean, required: false },
+            AttributeDefinition { name: "birthday".into(), kind: AttributeKind::Date, required: false },
+        ])
+    }
+
+    fn form(values: &[(&str, &str)]) -> HashMap<String, String> {
+        values.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn form_values_are_converted_to_their_type() {
+        let attributes = schema()
+            .parse_form(&form(&[
+                ("company", " Earthsea Press "),
+                ("employees", "12"),
+                ("customer", "on"),
+                ("birthday", "1929-10-21"),
+            ]))
+            .unwrap();
+
+        assert_eq!(
+            attributes.to_json(),
+            json!({"company": "Earthsea Press", "employees": 12.0, "customer": true, "birthday": "1929-10-21"})
+        );
+    }
+
+    #[test]
+    fn json_values_must_have_the_right_type() {
+        let errors = schema()
+            .parse_json(json!({"company": 42, "employees": "12", "birthday": "21/10/1929"}).as_object().unwrap())
+            .unwrap_err();
+
+        let attributes: Vec<&str> = errors.iter().map(|e| e.attribute.as_str()).collect();
+        assert_eq!(attributes, vec!["birthday", "company", "employees"]);
+    }
+
+    #[test]
+    fn required_attributes_must_be_present_and_not_empty() {
+        let errors = schema().parse_form(&form(&[("company", "  ")])).unwrap_err();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].to_string(), "company is required");
+        assert!(schema().parse_json(json!({"company": null}).as_object().unwrap()).is_err());
+    }
+
+    #[test]
+    fn unknown_attributes_are_rejected() {
+        let errors = schema().parse_form(&form(&[("company", "Roke"), ("shoe_size", "42")])).unwrap_err();
+
+        assert_eq!(errors[0].attribute, "shoe_size");
+    }
+
+    #[test]
+    fn attribute_names_are_restricted() {
+        assert!(AttributeDefinition::parse_name("company_size2").is_ok());
+        for name in ["", "Company", "2fast", "shoe size", "a.b", &"a".repeat(41)] {
+            assert!(AttributeDefinition::parse_name(name).is_err(), "{} was accepted", name);
+        }
+    }
+}
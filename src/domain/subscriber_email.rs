@@ -1,14 +1,26 @@
 use validator::validate_email;
 
+/// A valid email address. The domain is lowercased and converted to its
+/// ASCII (IDNA) form, the local part is kept as typed for display.
+///
+/// Two addresses that only differ in case belong to the same subscriber: the
+/// database enforces that on `lower(email)`.
 #[derive(Debug, Clone)]
 pub struct SubscriberEmail(String);
 
 impl SubscriberEmail {
     pub fn parse(s: String) -> Result<Self, String> {
-        if validate_email(&s) {
-            Ok(Self(s))
-        } else {
-            Err(format!("{} is not a valid email address", s))
+        if !validate_email(&s) {
+            return Err(format!("{} is not a valid email address", s));
+        }
+        let (local_part, domain) = s.rsplit_once('@').expect("A valid email address has an @");
+        // IP literals such as `[127.0.0.1]` are not domain names.
+        if domain.starts_with('[') {
+            return Ok(Self(s));
+        }
+        match idna::domain_to_ascii(domain) {
+            Ok(domain) => Ok(Self(format!("{}@{}", local_part, domain))),
+            Err(_) => Err(format!("{} is not a valid email address", s)),
         }
     }
 }
@@ -58,6 +70,18 @@ mod tests {
         assert!(email.is_err());
     }
 
+    #[test]
+    fn the_domain_is_lowercased_but_not_the_local_part() {
+        let email = SubscriberEmail::parse("Ursula.Le.Guin@Example.COM".to_string()).unwrap();
+        assert_eq!(email.as_ref(), "Ursula.Le.Guin@example.com");
+    }
+
+    #[test]
+    fn an_international_domain_is_stored_in_its_ascii_form() {
+        let email = SubscriberEmail::parse("ursula@Bücher.example".to_string()).unwrap();
+        assert_eq!(email.as_ref(), "ursula@xn--bcher-kva.example");
+    }
+
     #[test]
     fn email_missing_subject_is_rejected() {
         let email = SubscriberEmail::parse("@test.com".to_string());
@@ -0,0 +1,120 @@
+/// Where a subscriber stands. Every change goes through
+/// [`SubscriptionStatus::transition`], the database only accepts these values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubscriptionStatus {
+    PendingConfirmation,
+    Confirmed,
+    Unsubscribed,
+    /// Never emailed again, whatever happens, e.g. the provider could not
+    /// deliver to the address.
+    Suppressed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("A subscriber who is {from} can't become {to}")]
+pub struct IllegalTransition {
+    pub from: SubscriptionStatus,
+    pub to: SubscriptionStatus,
+}
+
+impl SubscriptionStatus {
+    pub const ALL: [Self; 4] = [Self::PendingConfirmation, Self::Confirmed, Self::Unsubscribed, Self::Suppressed];
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|status| status.as_str() == s)
+            .ok_or_else(|| format!("{} is not a valid subscription status", s))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::PendingConfirmation => "pending_confirmation",
+            Self::Confirmed => "confirmed",
+            Self::Unsubscribed => "unsubscribed",
+            Self::Suppressed => "suppressed",
+        }
+    }
+
+    /// Still on the list, whether or not they confirmed yet.
+    pub fn is_subscribed(&self) -> bool {
+        matches!(self, Self::PendingConfirmation | Self::Confirmed)
+    }
+
+    /// The status after moving to `to`, if a subscriber with this one can.
+    /// Only pending subscribers can be confirmed, anybody can be suppressed,
+    /// and someone who unsubscribed can only come back by signing up and
+    /// confirming again.
+    pub fn transition(self, to: Self) -> Result<Self, IllegalTransition> {
+        use SubscriptionStatus::*;
+
+        match (self, to) {
+            (PendingConfirmation, Confirmed | Unsubscribed | Suppressed)
+            | (Confirmed, Unsubscribed | Suppressed)
+            | (Unsubscribed, PendingConfirmation | Suppressed) => Ok(to),
+            _ => Err(IllegalTransition { from: self, to }),
+        }
+    }
+
+    /// Every status a subscriber can move to `to` from.
+    pub fn preceding(to: Self) -> Vec<Self> {
+        Self::ALL
+            .into_iter()
+            .filter(|status| status.transition(to).is_ok())
+            .collect()
+    }
+}
+
+impl std::fmt::Display for SubscriptionStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// Exports show the same names the database stores.
+impl serde::Serialize for SubscriptionStatus {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SubscriptionStatus::{self, *};
+
+    #[test]
+    fn every_status_round_trips_through_its_name() {
+        for status in SubscriptionStatus::ALL {
+            assert_eq!(SubscriptionStatus::parse(status.as_str()), Ok(status));
+        }
+        assert!(SubscriptionStatus::parse("Confirmed").is_err());
+    }
+
+    #[test]
+    fn a_pending_subscriber_can_be_confirmed_once() {
+        assert_eq!(PendingConfirmation.transition(Confirmed), Ok(Confirmed));
+        assert!(Confirmed.transition(Confirmed).is_err());
+    }
+
+    #[test]
+    fn nobody_who_left_can_be_confirmed_again() {
+        for status in [Unsubscribed, Suppressed] {
+            let error = status.transition(Confirmed).unwrap_err();
+            assert_eq!(error.from, status);
+        }
+    }
+
+    #[test]
+    fn only_unsubscribed_subscribers_can_sign_up_again() {
+        assert_eq!(Unsubscribed.transition(PendingConfirmation), Ok(PendingConfirmation));
+        assert_eq!(SubscriptionStatus::preceding(PendingConfirmation), vec![Unsubscribed]);
+    }
+
+    #[test]
+    fn suppression_is_final() {
+        for status in SubscriptionStatus::ALL {
+            assert!(Suppressed.transition(status).is_err());
+        }
+        assert_eq!(SubscriptionStatus::preceding(Suppressed), vec![PendingConfirmation, Confirmed, Unsubscribed]);
+    }
+}
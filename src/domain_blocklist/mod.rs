@@ -0,0 +1,115 @@
+use std::collections::HashSet;
+
+use crate::domain::SubscriberEmail;
+
+/// The list shipped with the application, used unless another file is
+/// configured.
+pub const DEFAULT_DISPOSABLE_DOMAINS: &str = include_str!("disposable_domains.txt");
+
+/// Email domains we don't accept signups from, typically throwaway address
+/// providers. A blocked domain blocks its subdomains too, unless they are
+/// allowed explicitly.
+#[derive(Debug, Default)]
+pub struct DomainBlocklist {
+    blocked: HashSet<String>,
+    allowed: HashSet<String>,
+}
+
+impl DomainBlocklist {
+    /// `blocklist` has one domain per line, `#` starts a comment.
+    pub fn new<'a>(blocklist: &str, allowlist: impl IntoIterator<Item = &'a str>) -> Self {
+        let blocked = blocklist
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default())
+            .filter_map(normalize)
+            .collect();
+        let allowed = allowlist.into_iter().filter_map(normalize).collect();
+        Self { blocked, allowed }
+    }
+
+    pub fn check(&self, email: &SubscriberEmail) -> Result<(), String> {
+        let (_, domain) = email
+            .as_ref()
+            .rsplit_once('@')
+            .expect("A valid email address has an @");
+        let domain = domain.to_lowercase();
+        if self.is_blocked(&domain) {
+            Err(format!(
+                "{} is a disposable email domain, please subscribe with a permanent address",
+                domain
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    // The most specific entry wins: `mail.example.com` can be allowed while
+    // `example.com` is blocked.
+    fn is_blocked(&self, domain: &str) -> bool {
+        for suffix in suffixes(domain) {
+            if self.allowed.contains(suffix) {
+                return false;
+            }
+            if self.blocked.contains(suffix) {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+fn normalize(domain: &str) -> Option<String> {
+    let domain = domain.trim().trim_end_matches('.');
+    (!domain.is_empty()).then(|| domain.to_lowercase())
+}
+
+// `a.b.c` -> `a.b.c`, `b.c`, `c`
+fn suffixes(domain: &str) -> impl Iterator<Item = &str> {
+    std::iter::successors(Some(domain), |domain| domain.split_once('.').map(|(_, rest)| rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DomainBlocklist, DEFAULT_DISPOSABLE_DOMAINS};
+    use crate::domain::SubscriberEmail;
+
+    fn email(s: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(s.to_string()).unwrap()
+    }
+
+    #[test]
+    fn the_default_list_blocks_well_known_providers() {
+        let blocklist = DomainBlocklist::new(DEFAULT_DISPOSABLE_DOMAINS, []);
+        assert!(blocklist.check(&email("someone@mailinator.com")).is_err());
+        assert!(blocklist.check(&email("someone@Yopmail.com")).is_err());
+        assert!(blocklist.check(&email("someone@gmail.com")).is_ok());
+    }
+
+    #[test]
+    fn subdomains_of_a_blocked_domain_are_blocked() {
+        let blocklist = DomainBlocklist::new("example.com", []);
+        assert!(blocklist.check(&email("someone@mail.example.com")).is_err());
+        assert!(blocklist.check(&email("someone@notexample.com")).is_ok());
+    }
+
+    #[test]
+    fn the_allowlist_overrides_the_blocklist() {
+        let blocklist = DomainBlocklist::new("example.com", ["team.example.com"]);
+        assert!(blocklist.check(&email("someone@team.example.com")).is_ok());
+        assert!(blocklist.check(&email("someone@example.com")).is_err());
+    }
+
+    #[test]
+    fn comments_and_blank_lines_are_ignored() {
+        let blocklist = DomainBlocklist::new("# a comment\n\n  example.com  # trailing\n", []);
+        assert!(blocklist.check(&email("someone@example.com")).is_err());
+        assert!(blocklist.check(&email("someone@comment")).is_ok());
+    }
+
+    #[test]
+    fn the_error_explains_why_the_address_was_rejected() {
+        let blocklist = DomainBlocklist::new("example.com", []);
+        let error = blocklist.check(&email("someone@example.com")).unwrap_err();
+        assert!(error.contains("disposable"));
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use super::{EmailMessage, SendReceipt};
+use crate::domain::SubscriberEmail;
+
+// Old messages are dropped once the mailbox is full so that a long-running
+// dev server doesn't grow without bound.
+const MAILBOX_CAPACITY: usize = 500;
+
+#[derive(Debug, Clone)]
+pub struct CapturedEmail {
+    pub id: Uuid,
+    pub captured_at: DateTime<Utc>,
+    pub from: String,
+    pub to: String,
+    pub cc: Vec<String>,
+    pub bcc: Vec<String>,
+    pub reply_to: Option<String>,
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+    pub tag: Option<String>,
+}
+
+/// Keeps every email "sent" through the capture transport so that it can be
+/// browsed during local development.
+#[derive(Debug, Default)]
+pub struct Mailbox {
+    messages: RwLock<VecDeque<CapturedEmail>>,
+}
+
+impl Mailbox {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(super) fn capture(&self, sender: &SubscriberEmail, message: &EmailMessage) -> SendReceipt {
+        let to_strings = |recipients: &[SubscriberEmail]| {
+            recipients.iter().map(ToString::to_string).collect()
+        };
+        let captured = CapturedEmail {
+            id: Uuid::new_v4(),
+            captured_at: Utc::now(),
+            from: sender.to_string(),
+            to: message.to.to_string(),
+            cc: to_strings(&message.cc),
+            bcc: to_strings(&message.bcc),
+            reply_to: message.reply_to.as_ref().map(ToString::to_string),
+            subject: message.subject.clone(),
+            html_body: message.html_body.clone(),
+            text_body: message.text_body.clone(),
+            tag: message.tag.clone(),
+        };
+        let receipt = SendReceipt {
+            message_id: Some(captured.id.to_string()),
+        };
+        tracing::info!(to = %captured.to, subject = %captured.subject, "Captured an outgoing email");
+
+        let mut messages = self.messages.write().unwrap();
+        if messages.len() == MAILBOX_CAPACITY {
+            messages.pop_back();
+        }
+        messages.push_front(captured);
+        receipt
+    }
+
+    /// Captured messages, newest first.
+    pub fn messages(&self) -> Vec<CapturedEmail> {
+        self.messages.read().unwrap().iter().cloned().collect()
+    }
+
+    pub fn get(&self, id: Uuid) -> Option<CapturedEmail> {
+        self.messages
+            .read()
+            .unwrap()
+            .iter()
+            .find(|message| message.id == id)
+            .cloned()
+    }
+
+    pub fn clear(&self) {
+        self.messages.write().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Mailbox, MAILBOX_CAPACITY};
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::EmailMessage;
+
+    fn email(s: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(s.to_string()).unwrap()
+    }
+
+    fn message(subject: &str) -> EmailMessage {
+        EmailMessage::builder(email("ursula@example.com"), subject, "<p>Hi</p>", "Hi")
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn captured_messages_are_listed_newest_first() {
+        let mailbox = Mailbox::new();
+        let sender = email("sender@example.com");
+        mailbox.capture(&sender, &message("first"));
+        let receipt = mailbox.capture(&sender, &message("second"));
+
+        let messages = mailbox.messages();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].subject, "second");
+        assert_eq!(
+            receipt.message_id,
+            Some(messages[0].id.to_string())
+        );
+    }
+
+    #[test]
+    fn the_oldest_message_is_dropped_when_the_mailbox_is_full() {
+        let mailbox = Mailbox::new();
+        let sender = email("sender@example.com");
+        for i in 0..=MAILBOX_CAPACITY {
+            mailbox.capture(&sender, &message(&i.to_string()));
+        }
+
+        let messages = mailbox.messages();
+        assert_eq!(messages.len(), MAILBOX_CAPACITY);
+        assert_eq!(messages.last().unwrap().subject, "1");
+    }
+}
@@ -0,0 +1,201 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+impl CircuitState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CircuitState::Closed => "closed",
+            CircuitState::Open => "open",
+            CircuitState::HalfOpen => "half_open",
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Inner {
+    Closed { consecutive_failures: u32 },
+    Open { since: Instant },
+    // Only one trial request is let through while half-open. If the trial
+    // never reports back (e.g. its future was dropped) another one is allowed
+    // once the cooldown has elapsed again.
+    HalfOpen { trial_started_at: Instant },
+}
+
+/// Fails fast while the email provider looks dead.
+///
+/// The breaker opens after `failure_threshold` consecutive failures, rejects
+/// every call for `cooldown`, then lets a single trial call through
+/// (half-open). A successful trial closes the circuit, a failed one opens it
+/// again.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    cooldown: Duration,
+    inner: Mutex<Inner>,
+    times_opened: AtomicU64,
+    rejected_calls: AtomicU64,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("The circuit breaker is open")]
+pub struct CircuitOpen;
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+            inner: Mutex::new(Inner::Closed { consecutive_failures: 0 }),
+            times_opened: AtomicU64::new(0),
+            rejected_calls: AtomicU64::new(0),
+        }
+    }
+
+    /// Ask for permission to perform a call.
+    pub fn acquire(&self) -> Result<(), CircuitOpen> {
+        let mut inner = self.inner.lock().unwrap();
+        match *inner {
+            Inner::Closed { .. } => Ok(()),
+            Inner::Open { since } | Inner::HalfOpen { trial_started_at: since } => {
+                if since.elapsed() >= self.cooldown {
+                    *inner = Inner::HalfOpen { trial_started_at: Instant::now() };
+                    Ok(())
+                } else {
+                    self.rejected_calls.fetch_add(1, Ordering::Relaxed);
+                    Err(CircuitOpen)
+                }
+            }
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        if matches!(*inner, Inner::HalfOpen { .. }) {
+            tracing::info!("Email provider circuit breaker closed");
+        }
+        *inner = Inner::Closed { consecutive_failures: 0 };
+    }
+
+    pub fn record_failure(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        let should_open = match *inner {
+            Inner::Closed { consecutive_failures } => {
+                let consecutive_failures = consecutive_failures + 1;
+                *inner = Inner::Closed { consecutive_failures };
+                consecutive_failures >= self.failure_threshold
+            }
+            Inner::HalfOpen { .. } => true,
+            // A call that was acquired before the circuit opened.
+            Inner::Open { .. } => false,
+        };
+        if should_open {
+            tracing::warn!("Email provider circuit breaker opened");
+            self.times_opened.fetch_add(1, Ordering::Relaxed);
+            *inner = Inner::Open { since: Instant::now() };
+        }
+    }
+
+    pub fn state(&self) -> CircuitState {
+        match *self.inner.lock().unwrap() {
+            Inner::Closed { .. } => CircuitState::Closed,
+            Inner::Open { since } if since.elapsed() >= self.cooldown => CircuitState::HalfOpen,
+            Inner::Open { .. } => CircuitState::Open,
+            Inner::HalfOpen { .. } => CircuitState::HalfOpen,
+        }
+    }
+
+    pub fn consecutive_failures(&self) -> u32 {
+        match *self.inner.lock().unwrap() {
+            Inner::Closed { consecutive_failures } => consecutive_failures,
+            _ => self.failure_threshold,
+        }
+    }
+
+    pub fn times_opened(&self) -> u64 {
+        self.times_opened.load(Ordering::Relaxed)
+    }
+
+    pub fn rejected_calls(&self) -> u64 {
+        self.rejected_calls.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CircuitBreaker, CircuitState};
+    use std::time::Duration;
+
+    #[test]
+    fn the_circuit_opens_after_consecutive_failures() {
+        let breaker = CircuitBreaker::new(3, Duration::from_secs(60));
+        for _ in 0..2 {
+            breaker.acquire().unwrap();
+            breaker.record_failure();
+        }
+        assert_eq!(breaker.state(), CircuitState::Closed);
+
+        breaker.acquire().unwrap();
+        breaker.record_failure();
+
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(breaker.acquire().is_err());
+        assert_eq!(breaker.times_opened(), 1);
+        assert_eq!(breaker.rejected_calls(), 1);
+    }
+
+    #[test]
+    fn a_success_resets_the_failure_count() {
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(60));
+        breaker.record_failure();
+        breaker.record_success();
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert_eq!(breaker.consecutive_failures(), 1);
+    }
+
+    #[test]
+    fn only_one_trial_call_is_allowed_when_half_open() {
+        let breaker = CircuitBreaker::new(1, Duration::from_millis(20));
+        breaker.record_failure();
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+
+        assert!(breaker.acquire().is_ok());
+        assert!(breaker.acquire().is_err());
+    }
+
+    #[test]
+    fn a_successful_trial_closes_the_circuit() {
+        let breaker = CircuitBreaker::new(1, Duration::from_millis(20));
+        breaker.record_failure();
+        std::thread::sleep(Duration::from_millis(30));
+
+        breaker.acquire().unwrap();
+        breaker.record_success();
+
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(breaker.acquire().is_ok());
+    }
+
+    #[test]
+    fn a_failed_trial_opens_the_circuit_again() {
+        let breaker = CircuitBreaker::new(1, Duration::from_millis(20));
+        breaker.record_failure();
+        std::thread::sleep(Duration::from_millis(30));
+
+        breaker.acquire().unwrap();
+        breaker.record_failure();
+
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(breaker.acquire().is_err());
+        assert_eq!(breaker.times_opened(), 2);
+    }
+}
@@ -0,0 +1,284 @@
+use std::collections::BTreeMap;
+
+use crate::domain::SubscriberEmail;
+
+// Limits enforced by Postmark. Checking them up front turns a 422 from the
+// provider into an error we can point at.
+const MAX_RECIPIENTS: usize = 50;
+const MAX_TAG_LENGTH: usize = 1000;
+const MAX_METADATA_FIELDS: usize = 10;
+const MAX_METADATA_KEY_LENGTH: usize = 20;
+const MAX_METADATA_VALUE_LENGTH: usize = 80;
+
+// Headers the client already sets from dedicated fields.
+const RESERVED_HEADERS: [&str; 8] = [
+    "from",
+    "to",
+    "cc",
+    "bcc",
+    "subject",
+    "reply-to",
+    "content-type",
+    "message-id",
+];
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum InvalidMessage {
+    #[error("An email can't have more than {MAX_RECIPIENTS} recipients")]
+    TooManyRecipients,
+    #[error("The tag is empty or longer than {MAX_TAG_LENGTH} characters")]
+    InvalidTag,
+    #[error("An email can't carry more than {MAX_METADATA_FIELDS} metadata fields")]
+    TooManyMetadataFields,
+    #[error("Invalid metadata field {0:?}")]
+    InvalidMetadataField(String),
+    #[error("Invalid message stream {0:?}")]
+    InvalidMessageStream(String),
+    #[error("Invalid header {0:?}")]
+    InvalidHeader(String),
+    #[error("The {0} header can't be set as a custom header")]
+    ReservedHeader(String),
+}
+
+/// A fully validated email, ready to be handed to the provider.
+#[derive(Debug, Clone)]
+pub struct EmailMessage {
+    pub(super) to: SubscriberEmail,
+    pub(super) cc: Vec<SubscriberEmail>,
+    pub(super) bcc: Vec<SubscriberEmail>,
+    pub(super) reply_to: Option<SubscriberEmail>,
+    pub(super) subject: String,
+    pub(super) html_body: String,
+    pub(super) text_body: String,
+    pub(super) tag: Option<String>,
+    pub(super) metadata: BTreeMap<String, String>,
+    pub(super) message_stream: Option<String>,
+    pub(super) headers: Vec<(String, String)>,
+}
+
+impl EmailMessage {
+    pub fn builder(
+        to: SubscriberEmail,
+        subject: impl Into<String>,
+        html_body: impl Into<String>,
+        text_body: impl Into<String>,
+    ) -> EmailMessageBuilder {
+        EmailMessageBuilder {
+            message: EmailMessage {
+                to,
+                cc: Vec::new(),
+                bcc: Vec::new(),
+                reply_to: None,
+                subject: subject.into(),
+                html_body: html_body.into(),
+                text_body: text_body.into(),
+                tag: None,
+                metadata: BTreeMap::new(),
+                message_stream: None,
+                headers: Vec::new(),
+            },
+        }
+    }
+
+    pub fn to(&self) -> &SubscriberEmail {
+        &self.to
+    }
+
+    pub fn subject(&self) -> &str {
+        &self.subject
+    }
+
+    pub fn html_body(&self) -> &str {
+        &self.html_body
+    }
+
+    pub fn text_body(&self) -> &str {
+        &self.text_body
+    }
+
+    pub fn tag(&self) -> Option<&str> {
+        self.tag.as_deref()
+    }
+}
+
+pub struct EmailMessageBuilder {
+    message: EmailMessage,
+}
+
+impl EmailMessageBuilder {
+    pub fn cc(mut self, recipient: SubscriberEmail) -> Self {
+        self.message.cc.push(recipient);
+        self
+    }
+
+    pub fn bcc(mut self, recipient: SubscriberEmail) -> Self {
+        self.message.bcc.push(recipient);
+        self
+    }
+
+    pub fn reply_to(mut self, reply_to: SubscriberEmail) -> Self {
+        self.message.reply_to = Some(reply_to);
+        self
+    }
+
+    /// Tags show up in the provider's reporting, e.g. "confirmation".
+    pub fn tag(mut self, tag: impl Into<String>) -> Self {
+        self.message.tag = Some(tag.into());
+        self
+    }
+
+    pub fn metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.message.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn message_stream(mut self, message_stream: impl Into<String>) -> Self {
+        self.message.message_stream = Some(message_stream.into());
+        self
+    }
+
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.message.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn build(self) -> Result<EmailMessage, InvalidMessage> {
+        let message = self.message;
+        if 1 + message.cc.len() + message.bcc.len() > MAX_RECIPIENTS {
+            return Err(InvalidMessage::TooManyRecipients);
+        }
+        if let Some(tag) = &message.tag {
+            if tag.trim().is_empty() || tag.chars().count() > MAX_TAG_LENGTH {
+                return Err(InvalidMessage::InvalidTag);
+            }
+        }
+        if message.metadata.len() > MAX_METADATA_FIELDS {
+            return Err(InvalidMessage::TooManyMetadataFields);
+        }
+        for (key, value) in &message.metadata {
+            if key.trim().is_empty()
+                || key.chars().count() > MAX_METADATA_KEY_LENGTH
+                || value.chars().count() > MAX_METADATA_VALUE_LENGTH
+            {
+                return Err(InvalidMessage::InvalidMetadataField(key.clone()));
+            }
+        }
+        if let Some(stream) = &message.message_stream {
+            let is_valid = !stream.is_empty()
+                && stream
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+            if !is_valid {
+                return Err(InvalidMessage::InvalidMessageStream(stream.clone()));
+            }
+        }
+        for (name, value) in &message.headers {
+            // RFC 5322 field names are printable ASCII without ':' and a
+            // value must not be able to smuggle in extra header lines.
+            let name_is_valid = !name.is_empty()
+                && name.bytes().all(|b| (33..=126).contains(&b) && b != b':');
+            let value_is_valid = !value.contains(['\r', '\n']);
+            if !name_is_valid || !value_is_valid {
+                return Err(InvalidMessage::InvalidHeader(name.clone()));
+            }
+            if RESERVED_HEADERS.contains(&name.to_ascii_lowercase().as_str()) {
+                return Err(InvalidMessage::ReservedHeader(name.clone()));
+            }
+        }
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EmailMessage, EmailMessageBuilder, InvalidMessage};
+    use crate::domain::SubscriberEmail;
+
+    fn email(s: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(s.to_string()).unwrap()
+    }
+
+    fn builder() -> EmailMessageBuilder {
+        EmailMessage::builder(email("ursula@example.com"), "Subject", "<p>Hi</p>", "Hi")
+    }
+
+    #[test]
+    fn a_message_with_every_optional_field_is_valid() {
+        let message = builder()
+            .cc(email("cc@example.com"))
+            .bcc(email("bcc@example.com"))
+            .reply_to(email("support@example.com"))
+            .tag("confirmation")
+            .metadata("subscriber-id", "42")
+            .message_stream("outbound")
+            .header("X-Campaign", "spring")
+            .build();
+        assert!(message.is_ok());
+    }
+
+    #[test]
+    fn empty_tags_are_rejected() {
+        let message = builder().tag(" ").build();
+        assert_eq!(message.unwrap_err(), InvalidMessage::InvalidTag);
+    }
+
+    #[test]
+    fn tags_longer_than_1000_characters_are_rejected() {
+        let message = builder().tag("a".repeat(1001)).build();
+        assert_eq!(message.unwrap_err(), InvalidMessage::InvalidTag);
+    }
+
+    #[test]
+    fn more_than_50_recipients_are_rejected() {
+        let mut builder = builder();
+        for i in 0..50 {
+            builder = builder.bcc(email(&format!("bcc{}@example.com", i)));
+        }
+        assert_eq!(builder.build().unwrap_err(), InvalidMessage::TooManyRecipients);
+    }
+
+    #[test]
+    fn more_than_10_metadata_fields_are_rejected() {
+        let mut builder = builder();
+        for i in 0..11 {
+            builder = builder.metadata(format!("key{}", i), "value");
+        }
+        assert_eq!(builder.build().unwrap_err(), InvalidMessage::TooManyMetadataFields);
+    }
+
+    #[test]
+    fn oversized_metadata_keys_and_values_are_rejected() {
+        let message = builder().metadata("k".repeat(21), "value").build();
+        assert!(matches!(message, Err(InvalidMessage::InvalidMetadataField(_))));
+        let message = builder().metadata("key", "v".repeat(81)).build();
+        assert!(matches!(message, Err(InvalidMessage::InvalidMetadataField(_))));
+    }
+
+    #[test]
+    fn message_streams_must_be_identifiers() {
+        let message = builder().message_stream("Broadcast Stream").build();
+        assert!(matches!(message, Err(InvalidMessage::InvalidMessageStream(_))));
+    }
+
+    #[test]
+    fn header_values_cannot_inject_new_lines() {
+        let message = builder().header("X-Campaign", "spring\r\nBcc: victim@example.com").build();
+        assert!(matches!(message, Err(InvalidMessage::InvalidHeader(_))));
+    }
+
+    #[test]
+    fn header_names_must_be_valid_field_names() {
+        for name in ["", "X Campaign", "X-Campaign:"] {
+            let message = builder().header(name, "spring").build();
+            assert!(matches!(message, Err(InvalidMessage::InvalidHeader(_))), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn reserved_headers_are_rejected() {
+        let message = builder().header("Reply-To", "attacker@example.com").build();
+        assert!(matches!(message, Err(InvalidMessage::ReservedHeader(_))));
+        let message = builder().header("BCC", "attacker@example.com").build();
+        assert!(matches!(message, Err(InvalidMessage::ReservedHeader(_))));
+    }
+}
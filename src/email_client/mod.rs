@@ -0,0 +1,750 @@
+mod capture;
+mod circuit_breaker;
+mod dkim;
+mod message;
+mod postmark_error;
+mod smtp;
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Arc;
+use crate::domain::SubscriberEmail;
+use reqwest::Client;
+use secrecy::{Secret, ExposeSecret};
+
+pub use capture::{CapturedEmail, Mailbox};
+pub use circuit_breaker::{CircuitBreaker, CircuitOpen, CircuitState};
+pub use dkim::{DkimAlgorithm, DkimSigner, InvalidDkimKey};
+pub use message::{EmailMessage, EmailMessageBuilder, InvalidMessage};
+pub use postmark_error::PostmarkError;
+pub use smtp::{SmtpTls, SmtpTransport};
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cc: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bcc: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply_to: Option<&'a str>,
+    subject: &'a str,
+    html_body: &'a str,
+    text_body: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tag: Option<&'a str>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    metadata: &'a BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message_stream: Option<&'a str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    headers: Vec<Header<'a>>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Header<'a> {
+    name: &'a str,
+    value: &'a str,
+}
+
+impl<'a> SendEmailRequest<'a> {
+    fn new(sender: &'a SubscriberEmail, message: &'a EmailMessage) -> Self {
+        let join = |recipients: &[SubscriberEmail]| {
+            (!recipients.is_empty()).then(|| {
+                recipients
+                    .iter()
+                    .map(AsRef::as_ref)
+                    .collect::<Vec<&str>>()
+                    .join(", ")
+            })
+        };
+        Self {
+            from: sender.as_ref(),
+            to: message.to.as_ref(),
+            cc: join(&message.cc),
+            bcc: join(&message.bcc),
+            reply_to: message.reply_to.as_ref().map(AsRef::as_ref),
+            subject: &message.subject,
+            html_body: &message.html_body,
+            text_body: &message.text_body,
+            tag: message.tag.as_deref(),
+            metadata: &message.metadata,
+            message_stream: message.message_stream.as_deref(),
+            headers: message
+                .headers
+                .iter()
+                .map(|(name, value)| Header { name, value })
+                .collect(),
+        }
+    }
+}
+
+/// What Postmark tells us about an email it accepted.
+#[derive(serde::Deserialize, Debug, Clone, Default)]
+pub struct SendReceipt {
+    // Postmark always sends a MessageID for accepted email, but an email that
+    // was accepted must never be reported as a failure (retrying it would
+    // send it twice), so a missing or unreadable body is tolerated.
+    #[serde(rename = "MessageID")]
+    pub message_id: Option<String>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SendEmailError {
+    #[error("The email provider is unavailable")]
+    CircuitOpen(#[from] CircuitOpen),
+    #[error(transparent)]
+    Provider(#[from] PostmarkError),
+    #[error("Failed to reach the email provider")]
+    Transport(#[from] reqwest::Error),
+    #[error("The SMTP relay did not accept the email")]
+    Smtp(#[from] lettre::transport::smtp::Error),
+    #[error("Failed to build the email for SMTP delivery")]
+    InvalidSmtpMessage(#[source] Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl SendEmailError {
+    /// Whether trying again right away is safe and may help. Retries happen
+    /// while a request waits, and the email may already be on its way when
+    /// an attempt times out: there is no idempotency key to stop it from
+    /// being sent twice. Timeouts are left to the circuit breaker.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::CircuitOpen(_) => false,
+            Self::Provider(e) => e.is_retryable(),
+            // Only when the request never reached the provider.
+            Self::Transport(e) => e.is_connect(),
+            // 4xx replies and connection failures, anything but a 5xx.
+            Self::Smtp(e) => !e.is_permanent() && !e.is_timeout(),
+            Self::InvalidSmtpMessage(_) => false,
+        }
+    }
+
+    pub fn is_undeliverable_recipient(&self) -> bool {
+        match self {
+            Self::Provider(e) => e.is_undeliverable_recipient(),
+            // 550 mailbox unavailable, 551 user not local, 553 mailbox name
+            // not allowed.
+            Self::Smtp(e) => e
+                .status()
+                .is_some_and(|code| matches!(code.to_string().as_str(), "550" | "551" | "553")),
+            _ => false,
+        }
+    }
+
+    // Only failures that suggest the provider itself is down count against
+    // the circuit breaker.
+    fn is_provider_outage(&self) -> bool {
+        matches!(
+            self,
+            Self::Transport(_) | Self::Provider(PostmarkError::ServerError(_))
+        ) || matches!(self, Self::Smtp(e) if !e.is_permanent())
+    }
+}
+
+/// How many times, and how quickly, a retryable failure is retried.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: std::time::Duration,
+}
+
+impl RetryPolicy {
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            base_delay: std::time::Duration::ZERO,
+        }
+    }
+
+    // Exponential backoff: base_delay, 2 * base_delay, 4 * base_delay, ...
+    fn delay_before_retry(&self, retry: u32) -> std::time::Duration {
+        self.base_delay.saturating_mul(2u32.saturating_pow(retry))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EmailClient {
+    sender: SubscriberEmail,
+    transport: Transport,
+    circuit_breaker: Arc<CircuitBreaker>,
+    retry_policy: RetryPolicy,
+}
+
+#[derive(Debug, Clone)]
+enum Transport {
+    Postmark(PostmarkTransport),
+    // Nothing leaves the process: messages are kept in the mailbox instead.
+    Capture(Arc<Mailbox>),
+    Smtp(SmtpTransport),
+}
+
+#[derive(Debug, Clone)]
+struct PostmarkTransport {
+    http_client: Client,
+    base_url: String,
+    authorization_token: Secret<String>,
+}
+
+impl EmailClient {
+    pub fn new(
+        sender: SubscriberEmail, 
+        base_url: String,
+        authorization_token: Secret<String>,
+        timeout: std::time::Duration,
+        circuit_breaker: CircuitBreaker,
+        retry_policy: RetryPolicy,
+    ) -> Self {
+        let http_client = Client::builder()
+            .timeout(timeout)
+            .build()
+            .unwrap();
+        Self {
+            sender,
+            transport: Transport::Postmark(PostmarkTransport {
+                http_client,
+                base_url,
+                authorization_token,
+            }),
+            circuit_breaker: Arc::new(circuit_breaker),
+            retry_policy,
+        }
+    }
+
+    /// A client that stores outgoing email in a [`Mailbox`] instead of
+    /// delivering it. Meant for local development.
+    pub fn capturing(sender: SubscriberEmail, mailbox: Arc<Mailbox>) -> Self {
+        Self {
+            sender,
+            transport: Transport::Capture(mailbox),
+            circuit_breaker: Arc::new(CircuitBreaker::new(u32::MAX, std::time::Duration::ZERO)),
+            retry_policy: RetryPolicy::none(),
+        }
+    }
+
+    /// A client that delivers through an SMTP relay instead of Postmark's
+    /// HTTP API.
+    pub fn smtp(
+        sender: SubscriberEmail,
+        transport: SmtpTransport,
+        circuit_breaker: CircuitBreaker,
+        retry_policy: RetryPolicy,
+    ) -> Self {
+        Self {
+            sender,
+            transport: Transport::Smtp(transport),
+            circuit_breaker: Arc::new(circuit_breaker),
+            retry_policy,
+        }
+    }
+
+    pub fn mailbox(&self) -> Option<&Arc<Mailbox>> {
+        match &self.transport {
+            Transport::Capture(mailbox) => Some(mailbox),
+            Transport::Postmark(_) | Transport::Smtp(_) => None,
+        }
+    }
+
+    pub fn circuit_breaker(&self) -> &CircuitBreaker {
+        &self.circuit_breaker
+    }
+
+    pub async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_body: &str,
+        text_body: &str,
+    ) -> Result<SendReceipt, SendEmailError> {
+        let message = EmailMessage::builder(recipient.clone(), subject, html_body, text_body)
+            .build()
+            .expect("A message without optional fields is always valid");
+        self.send(&message).await
+    }
+
+    pub async fn send(&self, message: &EmailMessage) -> Result<SendReceipt, SendEmailError> {
+        match &self.transport {
+            Transport::Capture(mailbox) => Ok(mailbox.capture(&self.sender, message)),
+            Transport::Postmark(postmark) => {
+                let request = SendEmailRequest::new(&self.sender, message);
+                self.with_retries(|| postmark.post(&request)).await
+            }
+            Transport::Smtp(smtp) => {
+                let (email, receipt) = smtp.build_message(&self.sender, message)?;
+                self.with_retries(|| smtp.send(&email)).await?;
+                Ok(receipt)
+            }
+        }
+    }
+
+    async fn with_retries<T, F, Fut>(&self, mut attempt: F) -> Result<T, SendEmailError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, SendEmailError>>,
+    {
+        let mut retry = 0;
+        loop {
+            match self.try_send(attempt()).await {
+                Err(e) if e.is_retryable() && retry < self.retry_policy.max_retries => {
+                    tracing::warn!(
+                        error.cause_chain = ?e,
+                        retry = retry + 1,
+                        "Sending an email failed, retrying.",
+                    );
+                    tokio::time::sleep(self.retry_policy.delay_before_retry(retry)).await;
+                    retry += 1;
+                }
+                outcome => return outcome,
+            }
+        }
+    }
+
+    async fn try_send<T>(
+        &self,
+        attempt: impl Future<Output = Result<T, SendEmailError>>,
+    ) -> Result<T, SendEmailError> {
+        self.circuit_breaker.acquire()?;
+        let outcome = attempt.await;
+        match &outcome {
+            Err(e) if e.is_provider_outage() => self.circuit_breaker.record_failure(),
+            _ => self.circuit_breaker.record_success(),
+        }
+        outcome
+    }
+}
+
+impl PostmarkTransport {
+    async fn post(&self, request: &SendEmailRequest<'_>) -> Result<SendReceipt, SendEmailError> {
+        let url = format!("{}/email", self.base_url);
+        let response = self.http_client
+            .post(&url)
+            .header(
+                "X-Postmark-Server-Token",
+                self.authorization_token.expose_secret(),
+            )
+            .json(request)
+            .send()
+            .await?;
+        let status = response.status();
+        if status.is_success() {
+            let receipt = response.json::<SendReceipt>().await.unwrap_or_else(|e| {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    "The email provider accepted an email without a readable receipt.",
+                );
+                SendReceipt::default()
+            });
+            return Ok(receipt);
+        }
+        let body = response.bytes().await?;
+        Err(PostmarkError::from_response(status, &body).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{
+        CircuitBreaker, EmailClient, EmailMessage, PostmarkError, RetryPolicy, SendEmailError,
+    };
+    use fake::faker::internet::en::SafeEmail;
+    use fake::faker::lorem::en::{Paragraph, Sentence};
+    use fake::{Fake, Faker};
+    use wiremock::matchers::{any, body_partial_json, header, header_exists, path, method};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+    use secrecy::Secret;
+
+    struct SendEmailBodyMatcher;
+
+    impl wiremock::Match for SendEmailBodyMatcher {
+        fn matches(&self, request: &wiremock::Request) -> bool {
+            let result: Result<serde_json::Value, _> = 
+                serde_json::from_slice(&request.body);
+            if let Ok(body) = result {
+                dbg!(&body);
+                body.get("From").is_some()
+                    && body.get("To").is_some()
+                    && body.get("Subject").is_some()
+                    && body.get("HtmlBody").is_some()
+                    && body.get("TextBody").is_some()
+            } else {
+                false
+            }
+        }
+    }
+
+    // testing helper functions
+    fn subject() -> String {
+        Sentence(1..2).fake()
+    }
+
+    fn body() -> String {
+        Paragraph(1..10).fake()
+    }
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    fn email_client(base_url: String) -> EmailClient {
+        EmailClient::new(
+            email(),
+            base_url,
+            Secret::new(Faker.fake()),
+            std::time::Duration::from_millis(200),
+            CircuitBreaker::new(3, std::time::Duration::from_secs(60)),
+            RetryPolicy::none(),
+        )
+    }
+
+    fn email_client_with_retries(base_url: String, max_retries: u32) -> EmailClient {
+        EmailClient::new(
+            email(),
+            base_url,
+            Secret::new(Faker.fake()),
+            std::time::Duration::from_millis(200),
+            CircuitBreaker::new(10, std::time::Duration::from_secs(60)),
+            RetryPolicy {
+                max_retries,
+                base_delay: std::time::Duration::from_millis(1),
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn send_email_sends_the_expected_request() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        email_client
+            .send_email(&email(), &subject(), &body(), &body())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn send_email_succeeds_if_the_server_returns_200() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &body(), &body())
+            .await;
+
+        assert!(outcome.is_ok());
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_the_server_returns_500() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &body(), &body())
+            .await;
+
+        assert!(outcome.is_err());
+    }
+
+    #[tokio::test]
+    async fn send_email_times_out_if_the_server_takes_too_long() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(15)))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &body(), &body())
+            .await;
+
+        assert!(outcome.is_err());
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_fast_once_the_circuit_is_open() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(3)
+            .mount(&mock_server)
+            .await;
+
+        for _ in 0..3 {
+            let _ = email_client
+                .send_email(&email(), &subject(), &body(), &body())
+                .await;
+        }
+        let outcome = email_client
+            .send_email(&email(), &subject(), &body(), &body())
+            .await;
+
+        assert!(matches!(outcome, Err(SendEmailError::CircuitOpen(_))));
+    }
+
+    #[tokio::test]
+    async fn client_errors_do_not_open_the_circuit() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(422))
+            .expect(4)
+            .mount(&mock_server)
+            .await;
+
+        for _ in 0..4 {
+            let outcome = email_client
+                .send_email(&email(), &subject(), &body(), &body())
+                .await;
+            assert!(matches!(outcome, Err(SendEmailError::Provider(_))));
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_surfaces_the_postmark_error_code() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(422).set_body_json(serde_json::json!({
+                "ErrorCode": 406,
+                "Message": "You tried to send to a recipient that has been marked as inactive."
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &body(), &body())
+            .await;
+
+        let error = outcome.unwrap_err();
+        assert!(matches!(error, SendEmailError::Provider(PostmarkError::InactiveRecipient)));
+        assert!(error.is_undeliverable_recipient());
+    }
+
+    #[tokio::test]
+    async fn retryable_errors_are_retried() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client_with_retries(mock_server.uri(), 2);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &body(), &body())
+            .await;
+
+        assert!(outcome.is_ok());
+    }
+
+    #[tokio::test]
+    async fn permanent_errors_are_not_retried() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client_with_retries(mock_server.uri(), 2);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(422).set_body_json(serde_json::json!({
+                "ErrorCode": 300,
+                "Message": "Invalid 'To' address."
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &body(), &body())
+            .await;
+
+        assert!(matches!(
+            outcome,
+            Err(SendEmailError::Provider(PostmarkError::InvalidEmailRequest(_)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn timeouts_are_not_retried() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client_with_retries(mock_server.uri(), 2);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(1)))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let error = email_client
+            .send_email(&email(), &subject(), &body(), &body())
+            .await
+            .unwrap_err();
+
+        assert!(matches!(error, SendEmailError::Transport(_)));
+        assert!(!error.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn connection_failures_are_retryable() {
+        // Bind and drop to get a port nothing listens on.
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let email_client = email_client_with_retries(format!("http://127.0.0.1:{}", port), 2);
+
+        let error = email_client
+            .send_email(&email(), &subject(), &body(), &body())
+            .await
+            .unwrap_err();
+
+        assert!(error.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn retries_give_up_after_max_retries() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client_with_retries(mock_server.uri(), 2);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429))
+            .expect(3)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &body(), &body())
+            .await;
+
+        assert!(matches!(
+            outcome,
+            Err(SendEmailError::Provider(PostmarkError::RateLimited))
+        ));
+    }
+
+    #[tokio::test]
+    async fn send_email_returns_the_provider_message_id() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "To": "receiver@example.com",
+                "SubmittedAt": "2014-02-17T07:25:01.4178645-05:00",
+                "MessageID": "0a129aee-e1cd-480d-b08d-4f48548ff48d",
+                "ErrorCode": 0,
+                "Message": "OK"
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let receipt = email_client
+            .send_email(&email(), &subject(), &body(), &body())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            receipt.message_id.as_deref(),
+            Some("0a129aee-e1cd-480d-b08d-4f48548ff48d")
+        );
+    }
+
+    #[tokio::test]
+    async fn send_includes_the_optional_message_fields() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let message = EmailMessage::builder(
+            SubscriberEmail::parse("ursula@example.com".into()).unwrap(),
+            subject(),
+            body(),
+            body(),
+        )
+        .cc(SubscriberEmail::parse("cc1@example.com".into()).unwrap())
+        .cc(SubscriberEmail::parse("cc2@example.com".into()).unwrap())
+        .reply_to(SubscriberEmail::parse("support@example.com".into()).unwrap())
+        .tag("newsletter")
+        .metadata("issue", "42")
+        .message_stream("broadcast")
+        .header("X-Campaign", "spring")
+        .build()
+        .unwrap();
+
+        Mock::given(path("/email"))
+            .and(body_partial_json(serde_json::json!({
+                "To": "ursula@example.com",
+                "Cc": "cc1@example.com, cc2@example.com",
+                "ReplyTo": "support@example.com",
+                "Tag": "newsletter",
+                "Metadata": { "issue": "42" },
+                "MessageStream": "broadcast",
+                "Headers": [{ "Name": "X-Campaign", "Value": "spring" }]
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        email_client.send(&message).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn unset_optional_fields_are_not_sent() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        email_client
+            .send_email(&email(), &subject(), &body(), &body())
+            .await
+            .unwrap();
+
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        for field in ["Cc", "Bcc", "ReplyTo", "Tag", "Metadata", "MessageStream", "Headers"] {
+            assert!(body.get(field).is_none(), "{} should not be sent", field);
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use http::StatusCode;
+
+/// The error body Postmark sends back alongside a non-2xx status.
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct PostmarkErrorBody {
+    error_code: i64,
+    message: String,
+}
+
+/// An error reported by Postmark.
+///
+/// See <https://postmarkapp.com/developer/api/overview#error-codes>.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum PostmarkError {
+    #[error("Postmark rejected the server token")]
+    InvalidApiToken,
+    #[error("The recipient is inactive (hard bounce, spam complaint or manual suppression)")]
+    InactiveRecipient,
+    #[error("Postmark rejected the email request: {0}")]
+    InvalidEmailRequest(String),
+    #[error("Postmark refused the sender (error code {error_code}): {message}")]
+    SenderRejected { error_code: i64, message: String },
+    #[error("Postmark rate limit exceeded")]
+    RateLimited,
+    #[error("Postmark failed with status {0}")]
+    ServerError(StatusCode),
+    #[error("Postmark error code {error_code} (status {status}): {message}")]
+    Other {
+        status: StatusCode,
+        error_code: i64,
+        message: String,
+    },
+}
+
+impl PostmarkError {
+    pub fn from_response(status: StatusCode, body: &[u8]) -> Self {
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return Self::RateLimited;
+        }
+        if status.is_server_error() {
+            return Self::ServerError(status);
+        }
+        let error_body: Option<PostmarkErrorBody> = serde_json::from_slice(body).ok();
+        match (status, error_body) {
+            (_, Some(PostmarkErrorBody { error_code: 10, .. })) | (StatusCode::UNAUTHORIZED, _) => {
+                Self::InvalidApiToken
+            }
+            (_, Some(PostmarkErrorBody { error_code: 406, .. })) => Self::InactiveRecipient,
+            (_, Some(PostmarkErrorBody { error_code: 300, message })) => {
+                Self::InvalidEmailRequest(message)
+            }
+            (_, Some(PostmarkErrorBody { error_code, message }))
+                if matches!(error_code, 400 | 401 | 405 | 412 | 413) =>
+            {
+                Self::SenderRejected { error_code, message }
+            }
+            (status, Some(PostmarkErrorBody { error_code, message })) => Self::Other {
+                status,
+                error_code,
+                message,
+            },
+            (status, None) => Self::Other {
+                status,
+                error_code: 0,
+                message: String::from_utf8_lossy(body_preview(body)).into_owned(),
+            },
+        }
+    }
+
+    /// Whether sending the same email again later might succeed.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Self::RateLimited | Self::ServerError(_))
+    }
+
+    /// Whether the recipient can never receive email from us, in which case
+    /// the address should be suppressed rather than retried.
+    pub fn is_undeliverable_recipient(&self) -> bool {
+        matches!(self, Self::InactiveRecipient)
+    }
+}
+
+fn body_preview(body: &[u8]) -> &[u8] {
+    &body[..body.len().min(256)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PostmarkError;
+    use http::StatusCode;
+
+    fn error_body(error_code: i64) -> Vec<u8> {
+        serde_json::json!({ "ErrorCode": error_code, "Message": "Something went wrong" })
+            .to_string()
+            .into_bytes()
+    }
+
+    #[test]
+    fn inactive_recipients_are_undeliverable_and_not_retryable() {
+        let error = PostmarkError::from_response(StatusCode::UNPROCESSABLE_ENTITY, &error_body(406));
+        assert_eq!(error, PostmarkError::InactiveRecipient);
+        assert!(error.is_undeliverable_recipient());
+        assert!(!error.is_retryable());
+    }
+
+    #[test]
+    fn a_bad_token_is_recognised() {
+        let error = PostmarkError::from_response(StatusCode::UNPROCESSABLE_ENTITY, &error_body(10));
+        assert_eq!(error, PostmarkError::InvalidApiToken);
+        let error = PostmarkError::from_response(StatusCode::UNAUTHORIZED, b"");
+        assert_eq!(error, PostmarkError::InvalidApiToken);
+        assert!(!error.is_retryable());
+    }
+
+    #[test]
+    fn rate_limits_and_server_errors_are_retryable() {
+        let error = PostmarkError::from_response(StatusCode::TOO_MANY_REQUESTS, b"");
+        assert_eq!(error, PostmarkError::RateLimited);
+        assert!(error.is_retryable());
+
+        let error = PostmarkError::from_response(StatusCode::SERVICE_UNAVAILABLE, b"");
+        assert!(error.is_retryable());
+        assert!(!error.is_undeliverable_recipient());
+    }
+
+    #[test]
+    fn unknown_error_codes_are_kept() {
+        let error = PostmarkError::from_response(StatusCode::UNPROCESSABLE_ENTITY, &error_body(1234));
+        assert!(matches!(error, PostmarkError::Other { error_code: 1234, .. }));
+        assert!(!error.is_retryable());
+    }
+
+    #[test]
+    fn unparseable_bodies_fall_back_on_the_status_code() {
+        let error = PostmarkError::from_response(StatusCode::BAD_REQUEST, b"<html>nope</html>");
+        assert!(matches!(
+            error,
+            PostmarkError::Other { status: StatusCode::BAD_REQUEST, error_code: 0, .. }
+        ));
+    }
+}
@@ -0,0 +1,313 @@
+use std::sync::Arc;
+
+use lettre::message::header::{HeaderName, HeaderValue};
+use lettre::message::MultiPart;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use secrecy::{ExposeSecret, Secret};
+use uuid::Uuid;
+
+use super::{DkimSigner, EmailMessage, SendEmailError, SendReceipt};
+use crate::domain::SubscriberEmail;
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Connect in plaintext and upgrade with STARTTLS (usually port 587).
+    StartTls,
+    /// TLS from the first byte (usually port 465).
+    Tls,
+    /// No encryption at all. Only meant for a relay on the same host.
+    Plaintext,
+}
+
+/// Delivers email to an SMTP relay, optionally signing it with DKIM.
+#[derive(Debug, Clone)]
+pub struct SmtpTransport {
+    mailer: AsyncSmtpTransport<Tokio1Executor>,
+    dkim: Option<Arc<DkimSigner>>,
+}
+
+impl SmtpTransport {
+    pub fn new(
+        host: &str,
+        port: u16,
+        tls: SmtpTls,
+        credentials: Option<(String, Secret<String>)>,
+        timeout: std::time::Duration,
+        dkim: Option<DkimSigner>,
+    ) -> Result<Self, lettre::transport::smtp::Error> {
+        let mut builder = match tls {
+            SmtpTls::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?,
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+            SmtpTls::Plaintext => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+        }
+        .port(port)
+        .timeout(Some(timeout));
+        if let Some((username, password)) = credentials {
+            builder =
+                builder.credentials(Credentials::new(username, password.expose_secret().clone()));
+        }
+        Ok(Self {
+            mailer: builder.build(),
+            dkim: dkim.map(Arc::new),
+        })
+    }
+
+    /// Formats (and signs) the message once so that retries resend exactly
+    /// the same bytes, Message-ID included.
+    pub(super) fn build_message(
+        &self,
+        sender: &SubscriberEmail,
+        message: &EmailMessage,
+    ) -> Result<(Message, SendReceipt), SendEmailError> {
+        let sender_domain = sender.as_ref().rsplit('@').next().unwrap_or("localhost");
+        let message_id = format!("{}@{}", Uuid::new_v4(), sender_domain);
+
+        let mut builder = Message::builder()
+            .from(mailbox(sender)?)
+            .to(mailbox(&message.to)?)
+            .subject(&message.subject)
+            .message_id(Some(format!("<{}>", message_id)))
+            .date_now();
+        for recipient in &message.cc {
+            builder = builder.cc(mailbox(recipient)?);
+        }
+        for recipient in &message.bcc {
+            builder = builder.bcc(mailbox(recipient)?);
+        }
+        if let Some(reply_to) = &message.reply_to {
+            builder = builder.reply_to(mailbox(reply_to)?);
+        }
+        // Tag, metadata and message stream are Postmark concepts with no SMTP
+        // equivalent, they are not sent.
+        for (name, value) in &message.headers {
+            let name = HeaderName::new_from_ascii(name.clone())
+                .map_err(|e| SendEmailError::InvalidSmtpMessage(e.into()))?;
+            builder = builder.raw_header(HeaderValue::new(name, value.clone()));
+        }
+        let mut email = builder
+            .multipart(MultiPart::alternative_plain_html(
+                message.text_body.clone(),
+                message.html_body.clone(),
+            ))
+            .map_err(|e| SendEmailError::InvalidSmtpMessage(e.into()))?;
+        if let Some(dkim) = &self.dkim {
+            dkim.sign(&mut email);
+        }
+        let receipt = SendReceipt {
+            message_id: Some(message_id),
+        };
+        Ok((email, receipt))
+    }
+
+    pub(super) async fn send(&self, email: &Message) -> Result<(), SendEmailError> {
+        self.mailer.send(email.clone()).await?;
+        Ok(())
+    }
+}
+
+fn mailbox(email: &SubscriberEmail) -> Result<lettre::message::Mailbox, SendEmailError> {
+    email
+        .as_ref()
+        .parse()
+        .map_err(|e: lettre::address::AddressError| SendEmailError::InvalidSmtpMessage(e.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SmtpTls, SmtpTransport};
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::dkim::tests::{signer, verify};
+    use crate::email_client::dkim::DkimAlgorithm;
+    use crate::email_client::{CircuitBreaker, EmailClient, EmailMessage, RetryPolicy};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    fn email(s: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(s.to_string()).unwrap()
+    }
+
+    fn message() -> EmailMessage {
+        EmailMessage::builder(
+            email("ursula@example.com"),
+            "Welcome   to our newsletter!",
+            "<p>Hi  there,</p>\n<p>welcome aboard.</p>",
+            "Hi  there,\nwelcome aboard.\n\n",
+        )
+        .cc(email("cc@example.com"))
+        .reply_to(email("support@example.com"))
+        .header("X-Campaign", "spring")
+        .build()
+        .unwrap()
+    }
+
+    fn transport(port: u16, dkim: Option<DkimAlgorithm>) -> SmtpTransport {
+        SmtpTransport::new(
+            "127.0.0.1",
+            port,
+            SmtpTls::Plaintext,
+            None,
+            std::time::Duration::from_secs(5),
+            dkim.map(signer),
+        )
+        .unwrap()
+    }
+
+    /// Accepts a single SMTP session and hands back the DATA it received, or
+    /// rejects every recipient with `rcpt_reply`.
+    async fn smtp_server(rcpt_reply: &'static str) -> (u16, tokio::task::JoinHandle<Vec<u8>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut reader = BufReader::new(reader);
+            writer.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+            let mut data = Vec::new();
+            let mut line = String::new();
+            loop {
+                line.clear();
+                if reader.read_line(&mut line).await.unwrap() == 0 {
+                    return data;
+                }
+                let command = line.to_ascii_uppercase();
+                let reply = if command.starts_with("EHLO") {
+                    "250 localhost\r\n"
+                } else if command.starts_with("RCPT") {
+                    rcpt_reply
+                } else if command.starts_with("DATA") {
+                    writer.write_all(b"354 Go ahead\r\n").await.unwrap();
+                    loop {
+                        line.clear();
+                        reader.read_line(&mut line).await.unwrap();
+                        if line == ".\r\n" {
+                            break;
+                        }
+                        // Undo dot-stuffing
+                        let line = line.strip_prefix('.').unwrap_or(&line);
+                        data.extend_from_slice(line.as_bytes());
+                    }
+                    "250 Queued\r\n"
+                } else if command.starts_with("QUIT") {
+                    writer.write_all(b"221 Bye\r\n").await.unwrap();
+                    return data;
+                } else {
+                    "250 OK\r\n"
+                };
+                writer.write_all(reply.as_bytes()).await.unwrap();
+            }
+        });
+        (port, handle)
+    }
+
+    #[test]
+    fn rsa_signatures_verify_against_the_public_key() {
+        let transport = transport(25, Some(DkimAlgorithm::Rsa));
+        let (email, _) = transport
+            .build_message(&email("newsletter@example.com"), &message())
+            .unwrap();
+        assert!(verify(&email.formatted(), DkimAlgorithm::Rsa));
+    }
+
+    #[test]
+    fn ed25519_signatures_verify_against_the_public_key() {
+        let transport = transport(25, Some(DkimAlgorithm::Ed25519));
+        let (email, _) = transport
+            .build_message(&email("newsletter@example.com"), &message())
+            .unwrap();
+        assert!(verify(&email.formatted(), DkimAlgorithm::Ed25519));
+    }
+
+    #[test]
+    fn a_tampered_message_fails_verification() {
+        let transport = transport(25, Some(DkimAlgorithm::Rsa));
+        let (email, _) = transport
+            .build_message(&email("newsletter@example.com"), &message())
+            .unwrap();
+        let tampered = String::from_utf8(email.formatted())
+            .unwrap()
+            .replace("Welcome", "Wellcome");
+        assert!(!verify(tampered.as_bytes(), DkimAlgorithm::Rsa));
+    }
+
+    #[test]
+    fn messages_are_not_signed_without_a_dkim_key() {
+        let transport = transport(25, None);
+        let (email, _) = transport
+            .build_message(&email("newsletter@example.com"), &message())
+            .unwrap();
+        let raw = String::from_utf8(email.formatted()).unwrap();
+        assert!(!raw.contains("DKIM-Signature"));
+        assert!(raw.contains("X-Campaign: spring"));
+    }
+
+    #[test]
+    fn the_receipt_carries_the_message_id_header() {
+        let transport = transport(25, None);
+        let (email, receipt) = transport
+            .build_message(&email("newsletter@example.com"), &message())
+            .unwrap();
+        let message_id = receipt.message_id.unwrap();
+        assert!(message_id.ends_with("@example.com"));
+        let raw = String::from_utf8(email.formatted()).unwrap();
+        assert!(raw.contains(&format!("Message-ID: <{}>", message_id)));
+    }
+
+    #[tokio::test]
+    async fn signed_email_is_delivered_to_the_relay() {
+        let (port, server) = smtp_server("250 OK\r\n").await;
+        let client = EmailClient::smtp(
+            email("newsletter@example.com"),
+            transport(port, Some(DkimAlgorithm::Ed25519)),
+            CircuitBreaker::new(3, std::time::Duration::from_secs(60)),
+            RetryPolicy::none(),
+        );
+
+        let receipt = client.send(&message()).await.unwrap();
+
+        let data = server.await.unwrap();
+        assert!(verify(&data, DkimAlgorithm::Ed25519));
+        let data = String::from_utf8(data).unwrap();
+        assert!(data.contains(&receipt.message_id.unwrap()));
+    }
+
+    #[tokio::test]
+    async fn a_rejected_mailbox_is_an_undeliverable_recipient() {
+        let (port, _server) = smtp_server("550 5.1.1 No such user\r\n").await;
+        let client = EmailClient::smtp(
+            email("newsletter@example.com"),
+            transport(port, None),
+            CircuitBreaker::new(1, std::time::Duration::from_secs(60)),
+            RetryPolicy::none(),
+        );
+
+        let error = client.send(&message()).await.unwrap_err();
+
+        assert!(error.is_undeliverable_recipient());
+        assert!(!error.is_retryable());
+        assert_eq!(client.circuit_breaker().times_opened(), 0);
+    }
+
+    #[tokio::test]
+    async fn an_unreachable_relay_is_retryable_and_trips_the_breaker() {
+        // Bind and drop to get a port nothing listens on.
+        let port = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let client = EmailClient::smtp(
+            email("newsletter@example.com"),
+            transport(port, None),
+            CircuitBreaker::new(1, std::time::Duration::from_secs(60)),
+            RetryPolicy::none(),
+        );
+
+        let error = client.send(&message()).await.unwrap_err();
+
+        assert!(error.is_retryable());
+        assert_eq!(client.circuit_breaker().times_opened(), 1);
+    }
+}
@@ -0,0 +1,75 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::email_client::SendReceipt;
+
+/// Why an email was sent to a subscriber.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmailKind {
+    Confirmation,
+    /// Someone already on the list signed up again.
+    AlreadySubscribed,
+    Newsletter(Uuid),
+    /// Several issues at once, for weekly and monthly subscribers.
+    Digest,
+    EmailChange,
+    DataExport,
+    /// The link that confirms deleting a subscriber's data.
+    ErasureLink,
+    /// A step of an automation, e.g. a welcome email.
+    Automation,
+}
+
+impl EmailKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmailKind::Confirmation => "confirmation",
+            EmailKind::AlreadySubscribed => "already_subscribed",
+            EmailKind::Newsletter(_) => "newsletter",
+            EmailKind::Digest => "digest",
+            EmailKind::EmailChange => "email_change",
+            EmailKind::DataExport => "data_export",
+            EmailKind::ErasureLink => "erasure_link",
+            EmailKind::Automation => "automation",
+        }
+    }
+
+    fn newsletter_issue_id(&self) -> Option<Uuid> {
+        match self {
+            EmailKind::Newsletter(issue_id) => Some(*issue_id),
+            _ => None,
+        }
+    }
+}
+
+/// Remember that an email went out, keyed by the provider's MessageID so that
+/// bounce and delivery webhooks can be matched to the exact message.
+#[tracing::instrument(
+    name = "Record an email delivery",
+    skip(pool, receipt),
+    fields(message_id = ?receipt.message_id)
+)]
+pub async fn record_email_delivery(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    kind: EmailKind,
+    receipt: &SendReceipt,
+) -> Result<Uuid, sqlx::Error> {
+    let delivery_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO email_deliveries (
+            id, subscriber_id, kind, newsletter_issue_id, message_id, sent_at
+        )
+        VALUES ($1, $2, $3, $4, $5, now())
+        "#,
+        delivery_id,
+        subscriber_id,
+        kind.as_str(),
+        kind.newsletter_issue_id(),
+        receipt.message_id,
+    )
+    .execute(pool)
+    .await?;
+    Ok(delivery_id)
+}
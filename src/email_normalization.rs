@@ -0,0 +1,138 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::SubscriberEmail;
+
+/// Brings addresses stored before `SubscriberEmail::parse` converted domains
+/// to IDNA in line with it, e.g. `ursula@Bücher.de` becomes
+/// `ursula@xn--bcher-kva.de`. The migration that made addresses unique could
+/// only lowercase ASCII domains, Postgres doesn't implement UTS #46.
+///
+/// An address that now matches another one is merged into it the way that
+/// migration merged duplicates: the most restrictive row is kept, and the
+/// deliveries and consent records of the others move to it.
+///
+/// Runs on every start and only finds work once; addresses `parse` rejects
+/// are left as they are. Returns how many addresses were rewritten.
+#[tracing::instrument(name = "Normalise stored email addresses", skip(pool))]
+pub async fn normalize_stored_emails(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let stored = sqlx::query!(
+        r#"
+        SELECT id, email
+        FROM subscriptions
+        WHERE substring(email FROM '@([^@]*)$') ~ '[^\x01-\x7f]'
+        FOR UPDATE
+        "#,
+    )
+    .fetch_all(&mut transaction)
+    .await?;
+    let mut ids = Vec::new();
+    let mut emails = Vec::new();
+    for row in stored {
+        match SubscriberEmail::parse(row.email.clone()) {
+            Ok(email) if email.as_ref() != row.email => {
+                ids.push(row.id);
+                emails.push(email.as_ref().to_owned());
+            }
+            Ok(_) => {}
+            Err(e) => tracing::warn!(subscriber_id = %row.id, error = %e, "Left a stored address as it is."),
+        }
+    }
+    if ids.is_empty() {
+        return Ok(0);
+    }
+
+    let merged = sqlx::query!(
+        r#"
+        WITH normalised AS (
+            SELECT * FROM UNNEST($1::uuid[], $2::text[]) AS n(id, email)
+        ), ranked AS (
+            SELECT s.id, first_value(s.id) OVER (
+                PARTITION BY lower(coalesce(n.email, s.email))
+                ORDER BY
+                    CASE s.status
+                        WHEN 'suppressed' THEN 0
+                        WHEN 'unsubscribed' THEN 1
+                        WHEN 'confirmed' THEN 2
+                        ELSE 3
+                    END,
+                    s.subscribed_at,
+                    s.id
+            ) AS kept_id
+            FROM subscriptions s
+            LEFT JOIN normalised n ON n.id = s.id
+        )
+        SELECT id AS "id!", kept_id AS "kept_id!" FROM ranked WHERE id <> kept_id
+        "#,
+        &ids,
+        &emails,
+    )
+    .fetch_all(&mut transaction)
+    .await?;
+    if !merged.is_empty() {
+        let merged_ids: Vec<Uuid> = merged.iter().map(|row| row.id).collect();
+        let kept_ids: Vec<Uuid> = merged.iter().map(|row| row.kept_id).collect();
+        merge_subscribers(&mut transaction, &merged_ids, &kept_ids).await?;
+        tracing::info!(merged = merged_ids.len(), "Merged subscribers whose addresses turned out the same.");
+    }
+
+    let updated = sqlx::query!(
+        r#"
+        UPDATE subscriptions s
+        SET email = n.email
+        FROM UNNEST($1::uuid[], $2::text[]) AS n(id, email)
+        WHERE s.id = n.id
+        "#,
+        &ids,
+        &emails,
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    tracing::info!(rewritten = updated.rows_affected(), "Normalised stored email addresses.");
+    Ok(updated.rows_affected())
+}
+
+// History moves to the kept rows. Tokens, pending email changes, queued
+// emails and automations of the merged rows are dropped with them.
+async fn merge_subscribers(
+    transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    merged_ids: &[Uuid],
+    kept_ids: &[Uuid],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE email_deliveries d
+        SET subscriber_id = m.kept_id
+        FROM UNNEST($1::uuid[], $2::uuid[]) AS m(id, kept_id)
+        WHERE d.subscriber_id = m.id
+        "#,
+        merged_ids,
+        kept_ids,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!("ALTER TABLE consent_records DISABLE TRIGGER consent_records_append_only")
+        .execute(&mut *transaction)
+        .await?;
+    sqlx::query!(
+        r#"
+        UPDATE consent_records c
+        SET subscriber_id = m.kept_id
+        FROM UNNEST($1::uuid[], $2::uuid[]) AS m(id, kept_id)
+        WHERE c.subscriber_id = m.id
+        "#,
+        merged_ids,
+        kept_ids,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!("ALTER TABLE consent_records ENABLE TRIGGER consent_records_append_only")
+        .execute(&mut *transaction)
+        .await?;
+    sqlx::query!("DELETE FROM subscriptions WHERE id = ANY($1)", merged_ids)
+        .execute(&mut *transaction)
+        .await?;
+    Ok(())
+}
@@ -5,4 +5,21 @@ pub mod startup;
 pub mod telemetry;
 pub mod domain;
 pub mod email_client;
+pub mod email_delivery;
+pub mod email_normalization;
+pub mod newsletter_template;
+pub mod newsletter_digest;
+pub mod attribute_schema;
+pub mod automation;
+pub mod automation_worker;
+pub mod background_worker;
+pub mod audit_log;
+pub mod consent;
+pub mod signup_attribution;
+pub mod subscriber_import;
+pub mod subscriber_email_queue;
+pub mod rate_limit;
+pub mod bot_protection;
+pub mod domain_blocklist;
+pub mod signature;
 pub mod authentication;
@@ -1,15 +1,52 @@
+use std::fmt::{Debug, Display};
+
+use tokio::task::JoinError;
 use zero2prod::configuration::get_configuration;
 use zero2prod::startup::Application;
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 
 #[tokio::main]
-async fn main() -> hyper::Result<()> {
+async fn main() -> anyhow::Result<()> {
     // telemetry setup
     let subscriber = get_subscriber("zero2prod".into(), "info".into(), std::io::stdout);
     init_subscriber(subscriber);
 
     let config = get_configuration().expect("Failed to read configuration.");
     let application = Application::build(config).await?;
-    application.run_until_stopped().await?;
+    let automation_worker = application.automation_worker();
+    let subscriber_email_worker = application.subscriber_email_worker();
+    let application_task = tokio::spawn(application.run_until_stopped());
+    let worker_task = tokio::spawn(automation_worker.run_until_stopped());
+    let subscriber_email_task = tokio::spawn(subscriber_email_worker.run_until_stopped());
+    // Any one of them stopping takes the whole process down.
+    tokio::select! {
+        outcome = application_task => report_exit("API", outcome),
+        outcome = worker_task => report_exit("Automation worker", outcome),
+        outcome = subscriber_email_task => report_exit("Subscriber email worker", outcome),
+    };
     Ok(())
 }
+
+fn report_exit(task_name: &str, outcome: Result<Result<(), impl Debug + Display>, JoinError>) {
+    match outcome {
+        Ok(Ok(())) => {
+            tracing::info!("{} has exited", task_name)
+        }
+        Ok(Err(e)) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "{} failed",
+                task_name
+            )
+        }
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "{} task failed to complete",
+                task_name
+            )
+        }
+    }
+}
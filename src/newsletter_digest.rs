@@ -0,0 +1,145 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+use crate::domain::{AttributeSchema, DeliveryFrequency, SubscriberEmail, SubscriptionStatus};
+use crate::email_client::{EmailMessage, InvalidMessage};
+use crate::newsletter_template::{NewsletterTemplate, Recipient};
+
+/// An issue going out in a digest, as it was published.
+pub struct DigestIssue {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub text_content: String,
+    pub html_content: String,
+}
+
+/// Queues a digest for every weekly or monthly subscriber whose last one
+/// covered up to a week or a month ago. One that is already waiting is not
+/// queued twice.
+#[tracing::instrument(name = "Queue the digests that are due", skip(executor))]
+pub async fn queue_due_digests(executor: impl PgExecutor<'_>) -> Result<u64, sqlx::Error> {
+    let queued = sqlx::query!(
+        r#"
+        INSERT INTO subscriber_email_queue (subscriber_id, kind, queued_at, due_at)
+        SELECT id, 'digest', now(), now()
+        FROM subscriptions
+        WHERE status = $1
+            AND (
+                (delivery_frequency = $2 AND digest_covered_until <= now() - interval '1 week')
+                OR (delivery_frequency = $3 AND digest_covered_until <= now() - interval '1 month')
+            )
+        ON CONFLICT (subscriber_id, kind) DO NOTHING
+        "#,
+        SubscriptionStatus::Confirmed.as_str(),
+        DeliveryFrequency::Weekly.as_str(),
+        DeliveryFrequency::Monthly.as_str(),
+    )
+    .execute(executor)
+    .await?;
+    Ok(queued.rows_affected())
+}
+
+/// The issues published since the last digest of the subscriber, up to
+/// `until`, that they would have got straight away: about one of their
+/// topics, or all of them if they picked none, and within the segment.
+#[tracing::instrument(name = "Gather the issues of a digest", skip(executor))]
+pub async fn get_digest_issues(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+    until: DateTime<Utc>,
+) -> Result<Vec<DigestIssue>, sqlx::Error> {
+    sqlx::query_as!(
+        DigestIssue,
+        r#"
+        SELECT i.newsletter_issue_id, i.title, i.text_content, i.html_content
+        FROM newsletter_issues i
+        JOIN subscriptions s ON s.id = $1
+        WHERE i.published_at > s.digest_covered_until
+            AND i.published_at <= $2
+            AND (i.topic IS NULL OR cardinality(s.topics) = 0 OR i.topic = ANY(s.topics))
+            AND s.attributes @> i.segment
+        ORDER BY i.published_at
+        "#,
+        subscriber_id,
+        until,
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// Whatever the next digest of the subscriber includes comes after `until`.
+#[tracing::instrument(name = "Mark a digest as covered", skip(executor))]
+pub async fn cover_digest(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+    until: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE subscriptions SET digest_covered_until = $2 WHERE id = $1",
+        subscriber_id,
+        until,
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// All the issues in one email, rendered for the recipient. An issue that no
+/// longer matches the attribute schema is left out. `None` if nothing is left.
+pub fn digest_email(
+    email: SubscriberEmail,
+    frequency: DeliveryFrequency,
+    issues: &[DigestIssue],
+    schema: &AttributeSchema,
+    recipient: &Recipient,
+    preferences_link: &str,
+) -> Result<Option<EmailMessage>, InvalidMessage> {
+    let mut html = String::new();
+    let mut text = String::new();
+    for issue in issues {
+        let parsed = NewsletterTemplate::parse(&issue.title, schema).and_then(|title| {
+            Ok((
+                title,
+                NewsletterTemplate::parse(&issue.html_content, schema)?,
+                NewsletterTemplate::parse(&issue.text_content, schema)?,
+            ))
+        });
+        let (title, issue_html, issue_text) = match parsed {
+            Ok(templates) => templates,
+            Err(e) => {
+                tracing::warn!(
+                    newsletter_issue_id = %issue.newsletter_issue_id,
+                    error = %e,
+                    "Left an issue out of a digest, it no longer matches the attributes.",
+                );
+                continue;
+            }
+        };
+        html.push_str(&format!(
+            "<h2>{}</h2>{}<hr>",
+            title.render(recipient, htmlescape::encode_minimal),
+            issue_html.render(recipient, htmlescape::encode_minimal),
+        ));
+        text.push_str(&format!(
+            "{}\n\n{}\n\n---\n\n",
+            title.render(recipient, str::to_string),
+            issue_text.render(recipient, str::to_string),
+        ));
+    }
+    if text.is_empty() {
+        return Ok(None);
+    }
+    let message = EmailMessage::builder(
+        email,
+        format!("Your {} digest", frequency.as_str()),
+        format!(
+            "{}<p><a href=\"{}\">Manage your preferences or unsubscribe</a></p>",
+            html, preferences_link,
+        ),
+        format!("{}Manage your preferences or unsubscribe: {}", text, preferences_link),
+    )
+    .tag("digest")
+    .build()?;
+    Ok(Some(message))
+}
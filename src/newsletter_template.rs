@@ -0,0 +1,142 @@
+use serde_json::{Map, Value};
+
+use crate::domain::AttributeSchema;
+
+/// What a newsletter can say about its recipient.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Variable {
+    Name,
+    Email,
+    Attribute(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Variable(Variable),
+}
+
+/// The recipient a newsletter is rendered for.
+pub struct Recipient<'a> {
+    pub name: &'a str,
+    pub email: &'a str,
+    pub attributes: &'a Map<String, Value>,
+}
+
+/// A newsletter title or body with `{{ name }}`, `{{ email }}` and
+/// `{{ attributes.<name> }}` variables, checked against the attribute schema
+/// before anything is sent.
+#[derive(Debug, Clone)]
+pub struct NewsletterTemplate(Vec<Part>);
+
+impl NewsletterTemplate {
+    pub fn parse(template: &str, schema: &AttributeSchema) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_string()));
+            }
+            let after_start = &rest[start + 2..];
+            let end = after_start
+                .find("}}")
+                .ok_or_else(|| format!("A variable is not closed: {}", truncate(&rest[start..])))?;
+            let variable = match after_start[..end].trim() {
+                "name" => Variable::Name,
+                "email" => Variable::Email,
+                other => match other.strip_prefix("attributes.") {
+                    Some(attribute) if schema.get(attribute).is_some() => Variable::Attribute(attribute.to_string()),
+                    _ => return Err(format!("{{{{ {} }}}} is not a known variable", other)),
+                },
+            };
+            parts.push(Part::Variable(variable));
+            rest = &after_start[end + 2..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+        Ok(Self(parts))
+    }
+
+    /// Variables go through `escape`, the rest of the template is left as is.
+    /// A subscriber without a value for an attribute gets an empty string.
+    pub fn render(&self, recipient: &Recipient, escape: impl Fn(&str) -> String) -> String {
+        self.0
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.clone(),
+                Part::Variable(Variable::Name) => escape(recipient.name),
+                Part::Variable(Variable::Email) => escape(recipient.email),
+                Part::Variable(Variable::Attribute(attribute)) => {
+                    escape(&display_value(recipient.attributes.get(attribute)))
+                }
+            })
+            .collect()
+    }
+}
+
+fn truncate(s: &str) -> String {
+    s.chars().take(20).collect()
+}
+
+fn display_value(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(text)) => text.clone(),
+        // Numbers from the form are stored as floats, 12 reads better than 12.0.
+        Some(Value::Number(number)) => match number.as_f64() {
+            Some(n) if n.fract() == 0.0 && n.abs() < 1e15 => format!("{}", n as i64),
+            _ => number.to_string(),
+        },
+        Some(Value::Bool(true)) => "yes".to_string(),
+        Some(Value::Bool(false)) => "no".to_string(),
+        Some(other) => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NewsletterTemplate, Recipient};
+    use crate::domain::{AttributeDefinition, AttributeKind, AttributeSchema};
+    use serde_json::json;
+
+    fn schema() -> AttributeSchema {
+        AttributeSchema::new(vec![
+            AttributeDefinition { name: "company".into(), kind: AttributeKind::Text, required: false },
+            AttributeDefinition { name: "employees".into(), kind: AttributeKind::Number, required: false },
+        ])
+    }
+
+    #[test]
+    fn variables_are_replaced_with_the_recipient_details() {
+        let template =
+            NewsletterTemplate::parse("Hi {{ name }} ({{email}}) of {{ attributes.company }}, all {{ attributes.employees }} of you!", &schema())
+                .unwrap();
+        let attributes = json!({"company": "<Roke>", "employees": 12.0});
+        let recipient = Recipient {
+            name: "Ged",
+            email: "ged@roke.org",
+            attributes: attributes.as_object().unwrap(),
+        };
+
+        let rendered = template.render(&recipient, htmlescape::encode_minimal);
+
+        assert_eq!(rendered, "Hi Ged (ged@roke.org) of &lt;Roke&gt;, all 12 of you!");
+    }
+
+    #[test]
+    fn a_missing_attribute_renders_as_nothing() {
+        let template = NewsletterTemplate::parse("[{{ attributes.company }}]", &schema()).unwrap();
+        let attributes = serde_json::Map::new();
+        let recipient = Recipient { name: "Ged", email: "ged@roke.org", attributes: &attributes };
+
+        assert_eq!(template.render(&recipient, str::to_string), "[]");
+    }
+
+    #[test]
+    fn unknown_or_unclosed_variables_are_rejected() {
+        for template in ["{{ attributes.shoe_size }}", "{{ phone }}", "Hi {{ name"] {
+            assert!(NewsletterTemplate::parse(template, &schema()).is_err(), "{} was accepted", template);
+        }
+    }
+}
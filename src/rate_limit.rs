@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Expired windows are swept once the map grows past this many keys.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// An in-memory, fixed-window rate limiter: at most `max_requests` per key in
+/// every `window`.
+///
+/// State is per process, so each replica enforces its own limit.
+#[derive(Debug)]
+pub struct RateLimiter {
+    max_requests: u32,
+    window: Duration,
+    windows: Mutex<HashMap<String, Window>>,
+}
+
+#[derive(Debug)]
+struct Window {
+    started_at: Instant,
+    requests: u32,
+}
+
+impl RateLimiter {
+    pub fn new(max_requests: u32, window: Duration) -> Self {
+        Self {
+            max_requests,
+            window,
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records a request for `key`, returning false if it goes over the limit.
+    pub fn check(&self, key: &str) -> bool {
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+        if windows.len() >= PRUNE_THRESHOLD {
+            windows.retain(|_, w| now.duration_since(w.started_at) < self.window);
+        }
+        let window = windows.entry(key.to_owned()).or_insert(Window {
+            started_at: now,
+            requests: 0,
+        });
+        if now.duration_since(window.started_at) >= self.window {
+            window.started_at = now;
+            window.requests = 0;
+        }
+        if window.requests >= self.max_requests {
+            return false;
+        }
+        window.requests += 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RateLimiter;
+    use std::time::Duration;
+
+    #[test]
+    fn requests_over_the_limit_are_rejected() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(60));
+        assert!(limiter.check("a"));
+        assert!(limiter.check("a"));
+        assert!(!limiter.check("a"));
+    }
+
+    #[test]
+    fn keys_are_limited_independently() {
+        let limiter = RateLimiter::new(1, Duration::from_secs(60));
+        assert!(limiter.check("a"));
+        assert!(limiter.check("b"));
+        assert!(!limiter.check("a"));
+    }
+
+    #[test]
+    fn the_limit_resets_once_the_window_is_over() {
+        let limiter = RateLimiter::new(1, Duration::from_millis(20));
+        assert!(limiter.check("a"));
+        assert!(!limiter.check("a"));
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(limiter.check("a"));
+    }
+}
@@ -0,0 +1,168 @@
+use anyhow::Context;
+use axum::{
+    extract::{Path, State},
+    response::{Html, IntoResponse, Redirect, Response},
+    Form,
+};
+use axum_extra::extract::cookie::SignedCookieJar;
+use axum_sessions::extractors::ReadableSession;
+use htmlescape::{encode_attribute, encode_minimal};
+use http::StatusCode;
+use uuid::Uuid;
+
+use crate::attribute_schema::{delete_attribute_definition, get_attribute_schema, insert_attribute_definition};
+use crate::domain::{AttributeDefinition, AttributeKind};
+use crate::routes::admin::dashboard::USER_ID_COOKIE;
+use crate::routes::admin::flash::{set_flash, take_flash};
+use crate::routes::error_chain_fmt;
+use crate::startup::AppState;
+
+#[derive(thiserror::Error)]
+pub enum AttributeSchemaError {
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for AttributeSchemaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for AttributeSchemaError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        }
+    }
+}
+
+pub async fn attribute_schema_page(
+    State(state): State<AppState>,
+    session: ReadableSession,
+    jar: SignedCookieJar,
+) -> Result<Response, AttributeSchemaError> {
+    if session.get::<Uuid>(USER_ID_COOKIE).is_none() {
+        return Ok(Redirect::to("/login").into_response());
+    }
+    let schema = get_attribute_schema(&state.db_pool).await?;
+    let rows: String = schema
+        .definitions()
+        .iter()
+        .map(|definition| {
+            format!(
+                r#"<tr><td>{name}</td><td>{kind}</td><td>{required}</td><td><form action="/admin/attributes/{path}/delete" method="post"><button type="submit">Delete</button></form></td></tr>"#,
+                name = encode_minimal(&definition.name),
+                kind = definition.kind.as_str(),
+                required = if definition.required { "yes" } else { "no" },
+                path = encode_attribute(&definition.name),
+            )
+        })
+        .collect();
+    let attributes_html = if rows.is_empty() {
+        "<p>No custom attributes yet.</p>".to_string()
+    } else {
+        format!(
+            r#"<table>
+        <tr><th>Name</th><th>Type</th><th>Required</th><th></th></tr>
+        {rows}
+    </table>"#
+        )
+    };
+    let kind_options: String = AttributeKind::ALL
+        .iter()
+        .map(|kind| format!(r#"<option value="{0}">{0}</option>"#, kind.as_str()))
+        .collect();
+    let (jar, flash_html) = take_flash(jar);
+    let html = Html(format!(r#"<!doctype html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Custom attributes</title>
+</head>
+<body>
+    {flash_html}
+    <p>Custom attributes are asked for on the subscription form, and can be used in newsletters as <code>{{{{ attributes.name }}}}</code>.</p>
+    {attributes_html}
+    <h2>Add an attribute</h2>
+    <form action="/admin/attributes" method="post">
+        <label>Name
+            <input type="text" name="name" placeholder="e.g. company">
+        </label>
+        <label>Type
+            <select name="kind">{kind_options}</select>
+        </label>
+        <label>
+            <input type="checkbox" name="required" value="true"> Required
+        </label>
+        <button type="submit">Add</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#));
+    Ok((jar, html).into_response())
+}
+
+#[derive(serde::Deserialize)]
+pub struct AttributeFormData {
+    name: String,
+    kind: String,
+    // Only sent when the box is ticked.
+    required: Option<String>,
+}
+
+#[tracing::instrument(name = "Add a custom attribute", skip(state, session, jar, form_data))]
+pub async fn add_attribute(
+    State(state): State<AppState>,
+    session: ReadableSession,
+    jar: SignedCookieJar,
+    Form(form_data): Form<AttributeFormData>,
+) -> Result<Response, AttributeSchemaError> {
+    if session.get::<Uuid>(USER_ID_COOKIE).is_none() {
+        return Ok(Redirect::to("/login").into_response());
+    }
+    let definition = AttributeDefinition::parse_name(form_data.name.trim()).and_then(|name| {
+        Ok(AttributeDefinition {
+            name,
+            kind: AttributeKind::parse(&form_data.kind)?,
+            required: form_data.required.is_some(),
+        })
+    });
+    let message = match definition {
+        Err(message) => message,
+        Ok(definition) => {
+            let added = insert_attribute_definition(&state.db_pool, &definition)
+                .await
+                .context("Failed to add an attribute definition.")?;
+            if added {
+                format!("The {} attribute was added.", definition.name)
+            } else {
+                format!("There already is a {} attribute.", definition.name)
+            }
+        }
+    };
+    Ok((set_flash(jar, message), Redirect::to("/admin/attributes")).into_response())
+}
+
+/// Removing an attribute also removes its value from every subscriber.
+#[tracing::instrument(name = "Delete a custom attribute", skip(state, session, jar))]
+pub async fn delete_attribute(
+    State(state): State<AppState>,
+    session: ReadableSession,
+    jar: SignedCookieJar,
+    Path(name): Path<String>,
+) -> Result<Response, AttributeSchemaError> {
+    if session.get::<Uuid>(USER_ID_COOKIE).is_none() {
+        return Ok(Redirect::to("/login").into_response());
+    }
+    let mut transaction = state.db_pool.begin().await.context("Failed to acquire a database connection.")?;
+    let deleted = delete_attribute_definition(&mut transaction, &name)
+        .await
+        .context("Failed to delete an attribute definition.")?;
+    if !deleted {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+    transaction.commit().await.context("Failed to commit SQL transaction to delete an attribute.")?;
+    let message = format!("The {} attribute was deleted.", name);
+    Ok((set_flash(jar, message), Redirect::to("/admin/attributes")).into_response())
+}
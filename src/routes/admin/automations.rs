@@ -0,0 +1,364 @@
+use anyhow::Context;
+use axum::{
+    extract::{Path, State},
+    response::{Html, IntoResponse, Redirect, Response},
+    Form,
+};
+use axum_extra::extract::cookie::SignedCookieJar;
+use axum_sessions::extractors::ReadableSession;
+use htmlescape::encode_minimal;
+use http::StatusCode;
+use uuid::Uuid;
+
+use crate::attribute_schema::get_attribute_schema;
+use crate::automation::{
+    delete_automation_step, get_automation, get_automation_steps, get_automations, insert_automation,
+    insert_automation_step, set_automation_active, NewAutomationStep, MAX_STEP_DELAY_HOURS,
+};
+use crate::newsletter_template::NewsletterTemplate;
+use crate::routes::admin::dashboard::USER_ID_COOKIE;
+use crate::routes::admin::flash::{set_flash, take_flash};
+use crate::routes::error_chain_fmt;
+use crate::startup::AppState;
+
+#[derive(thiserror::Error)]
+pub enum AutomationError {
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for AutomationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for AutomationError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        }
+    }
+}
+
+pub async fn automations_page(
+    State(state): State<AppState>,
+    session: ReadableSession,
+    jar: SignedCookieJar,
+) -> Result<Response, AutomationError> {
+    if session.get::<Uuid>(USER_ID_COOKIE).is_none() {
+        return Ok(Redirect::to("/login").into_response());
+    }
+    let automations = get_automations(&state.db_pool).await.context("Failed to fetch the automations.")?;
+    let rows: String = automations
+        .iter()
+        .map(|automation| {
+            format!(
+                r#"<tr><td><a href="/admin/automations/{id}">{name}</a></td><td>{active}</td><td>{steps}</td><td>{enrollments}</td></tr>"#,
+                id = automation.id,
+                name = encode_minimal(&automation.name),
+                active = if automation.active { "active" } else { "inactive" },
+                steps = automation.steps,
+                enrollments = automation.enrollments,
+            )
+        })
+        .collect();
+    let automations_html = if rows.is_empty() {
+        "<p>No automations yet.</p>".to_string()
+    } else {
+        format!(
+            r#"<table>
+        <tr><th>Name</th><th>Status</th><th>Steps</th><th>Enrolled</th></tr>
+        {rows}
+    </table>"#
+        )
+    };
+    let (jar, flash_html) = take_flash(jar);
+    let html = Html(format!(r#"<!doctype html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Automations</title>
+</head>
+<body>
+    {flash_html}
+    <p>Active automations send their steps to every subscriber who confirms, each step a set time after they confirmed.</p>
+    {automations_html}
+    <h2>Add an automation</h2>
+    <form action="/admin/automations" method="post">
+        <label>Name
+            <input type="text" name="name" placeholder="e.g. Welcome series">
+        </label>
+        <button type="submit">Add</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#));
+    Ok((jar, html).into_response())
+}
+
+#[derive(serde::Deserialize)]
+pub struct AutomationFormData {
+    name: String,
+}
+
+#[tracing::instrument(name = "Add an automation", skip(state, session, jar, form_data))]
+pub async fn add_automation(
+    State(state): State<AppState>,
+    session: ReadableSession,
+    jar: SignedCookieJar,
+    Form(form_data): Form<AutomationFormData>,
+) -> Result<Response, AutomationError> {
+    if session.get::<Uuid>(USER_ID_COOKIE).is_none() {
+        return Ok(Redirect::to("/login").into_response());
+    }
+    let name = form_data.name.trim();
+    if name.is_empty() {
+        let message = "An automation needs a name.".to_string();
+        return Ok((set_flash(jar, message), Redirect::to("/admin/automations")).into_response());
+    }
+    let automation_id = insert_automation(&state.db_pool, name)
+        .await
+        .context("Failed to add an automation.")?;
+    let message = format!("The {} automation was added. Activate it once its steps are written.", name);
+    let detail_page = format!("/admin/automations/{}", automation_id);
+    Ok((set_flash(jar, message), Redirect::to(&detail_page)).into_response())
+}
+
+/// The steps of an automation, with how many were sent, are still to come,
+/// failed or were skipped because the subscriber left.
+pub async fn automation_detail(
+    State(state): State<AppState>,
+    session: ReadableSession,
+    jar: SignedCookieJar,
+    Path(automation_id): Path<Uuid>,
+) -> Result<Response, AutomationError> {
+    if session.get::<Uuid>(USER_ID_COOKIE).is_none() {
+        return Ok(Redirect::to("/login").into_response());
+    }
+    let Some(automation) = get_automation(&state.db_pool, automation_id)
+        .await
+        .context("Failed to fetch the automation.")?
+    else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let steps = get_automation_steps(&state.db_pool, automation_id)
+        .await
+        .context("Failed to fetch the steps of an automation.")?;
+    let rows: String = steps
+        .iter()
+        .map(|step| {
+            format!(
+                r#"<tr><td>{delay}</td><td>{subject}</td><td>{scheduled}</td><td>{sent}</td><td>{failed}</td><td>{skipped}</td><td><form action="/admin/automations/{automation_id}/steps/{step_id}/delete" method="post"><button type="submit">Delete</button></form></td></tr>"#,
+                delay = display_delay(step.delay_hours),
+                subject = encode_minimal(&step.subject),
+                scheduled = step.scheduled,
+                sent = step.sent,
+                failed = step.failed,
+                skipped = step.skipped,
+                step_id = step.id,
+            )
+        })
+        .collect();
+    let steps_html = if rows.is_empty() {
+        "<p>No steps yet.</p>".to_string()
+    } else {
+        format!(
+            r#"<table>
+        <tr><th>After</th><th>Subject</th><th>Scheduled</th><th>Sent</th><th>Failed</th><th>Skipped</th><th></th></tr>
+        {rows}
+    </table>"#
+        )
+    };
+    let (status, toggle_action, toggle_label) = if automation.active {
+        ("Active", "deactivate", "Deactivate")
+    } else {
+        ("Inactive", "activate", "Activate")
+    };
+    let (jar, flash_html) = take_flash(jar);
+    let html = Html(format!(r#"<!doctype html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Automation: {name}</title>
+</head>
+<body>
+    {flash_html}
+    <h1>{name}</h1>
+    <p>{status}, created {created_at}.</p>
+    <form action="/admin/automations/{automation_id}/{toggle_action}" method="post">
+        <button type="submit">{toggle_label}</button>
+    </form>
+    {steps_html}
+    <h2>Add a step</h2>
+    <p>Sent this long after the subscriber confirmed. The subject and content can use <code>{{{{ name }}}}</code>, <code>{{{{ email }}}}</code> and <code>{{{{ attributes.name }}}}</code>.</p>
+    <form action="/admin/automations/{automation_id}/steps" method="post">
+        <label>Days
+            <input type="number" name="delay_days" min="0" value="0">
+        </label>
+        <label>Hours
+            <input type="number" name="delay_hours" min="0" value="0">
+        </label>
+        <br>
+        <label>Subject
+            <input type="text" name="subject">
+        </label>
+        <br>
+        <label>Text content
+            <textarea name="text_content" rows="10" cols="60"></textarea>
+        </label>
+        <br>
+        <label>HTML content
+            <textarea name="html_content" rows="10" cols="60"></textarea>
+        </label>
+        <br>
+        <button type="submit">Add step</button>
+    </form>
+    <p><a href="/admin/automations">&lt;- Back</a></p>
+</body>
+</html>"#,
+        name = encode_minimal(&automation.name),
+        created_at = automation.created_at.format("%Y-%m-%d %H:%M UTC"),
+    ));
+    Ok((jar, html).into_response())
+}
+
+#[derive(serde::Deserialize)]
+pub struct AutomationStepFormData {
+    // Text, so that an empty field means zero.
+    delay_days: String,
+    delay_hours: String,
+    subject: String,
+    text_content: String,
+    html_content: String,
+}
+
+impl AutomationStepFormData {
+    fn delay_hours(&self) -> Result<i32, String> {
+        let parse = |value: &str, unit: &str| -> Result<i32, String> {
+            match value.trim() {
+                "" => Ok(0),
+                value => value
+                    .parse::<u16>()
+                    .map(i32::from)
+                    .map_err(|_| format!("{} is not a number of {}.", value, unit)),
+            }
+        };
+        let hours = parse(&self.delay_days, "days")? * 24 + parse(&self.delay_hours, "hours")?;
+        if hours > MAX_STEP_DELAY_HOURS {
+            return Err("A step can't be sent more than a year after confirming.".to_string());
+        }
+        Ok(hours)
+    }
+}
+
+#[tracing::instrument(name = "Add an automation step", skip(state, session, jar, form_data))]
+pub async fn add_automation_step(
+    State(state): State<AppState>,
+    session: ReadableSession,
+    jar: SignedCookieJar,
+    Path(automation_id): Path<Uuid>,
+    Form(form_data): Form<AutomationStepFormData>,
+) -> Result<Response, AutomationError> {
+    if session.get::<Uuid>(USER_ID_COOKIE).is_none() {
+        return Ok(Redirect::to("/login").into_response());
+    }
+    if get_automation(&state.db_pool, automation_id)
+        .await
+        .context("Failed to fetch the automation.")?
+        .is_none()
+    {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+    let schema = get_attribute_schema(&state.db_pool).await?;
+    let step = form_data.delay_hours().and_then(|delay_hours| {
+        let fields = [
+            ("subject", &form_data.subject),
+            ("text content", &form_data.text_content),
+            ("HTML content", &form_data.html_content),
+        ];
+        for (field, value) in fields {
+            if value.trim().is_empty() {
+                return Err(format!("The {} is missing.", field));
+            }
+            NewsletterTemplate::parse(value, &schema).map_err(|e| format!("The {} is invalid: {}", field, e))?;
+        }
+        Ok(NewAutomationStep {
+            delay_hours,
+            subject: form_data.subject.trim().to_string(),
+            text_content: form_data.text_content,
+            html_content: form_data.html_content,
+        })
+    });
+    let message = match step {
+        Err(message) => message,
+        Ok(step) => {
+            insert_automation_step(&state.db_pool, automation_id, &step)
+                .await
+                .context("Failed to add an automation step.")?;
+            format!("The {} step was added.", step.subject)
+        }
+    };
+    let detail_page = format!("/admin/automations/{}", automation_id);
+    Ok((set_flash(jar, message), Redirect::to(&detail_page)).into_response())
+}
+
+/// Subscribers already enrolled won't get the step either.
+#[tracing::instrument(name = "Delete an automation step", skip(state, session, jar))]
+pub async fn delete_step(
+    State(state): State<AppState>,
+    session: ReadableSession,
+    jar: SignedCookieJar,
+    Path((automation_id, step_id)): Path<(Uuid, Uuid)>,
+) -> Result<Response, AutomationError> {
+    if session.get::<Uuid>(USER_ID_COOKIE).is_none() {
+        return Ok(Redirect::to("/login").into_response());
+    }
+    let deleted = delete_automation_step(&state.db_pool, automation_id, step_id)
+        .await
+        .context("Failed to delete an automation step.")?;
+    if !deleted {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+    let detail_page = format!("/admin/automations/{}", automation_id);
+    Ok((set_flash(jar, "The step was deleted.".to_string()), Redirect::to(&detail_page)).into_response())
+}
+
+#[tracing::instrument(name = "Activate or deactivate an automation", skip(state, session, jar))]
+pub async fn toggle_automation(
+    State(state): State<AppState>,
+    session: ReadableSession,
+    jar: SignedCookieJar,
+    Path((automation_id, action)): Path<(Uuid, String)>,
+) -> Result<Response, AutomationError> {
+    if session.get::<Uuid>(USER_ID_COOKIE).is_none() {
+        return Ok(Redirect::to("/login").into_response());
+    }
+    let active = match action.as_str() {
+        "activate" => true,
+        "deactivate" => false,
+        _ => return Ok(StatusCode::NOT_FOUND.into_response()),
+    };
+    let updated = set_automation_active(&state.db_pool, automation_id, active)
+        .await
+        .context("Failed to activate or deactivate an automation.")?;
+    if !updated {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+    let message = if active {
+        "The automation is active: subscribers who confirm from now on are enrolled."
+    } else {
+        "The automation is inactive: nobody new is enrolled, those already enrolled still get their steps."
+    };
+    let detail_page = format!("/admin/automations/{}", automation_id);
+    Ok((set_flash(jar, message.to_string()), Redirect::to(&detail_page)).into_response())
+}
+
+fn display_delay(delay_hours: i32) -> String {
+    match (delay_hours / 24, delay_hours % 24) {
+        (0, 0) => "right away".to_string(),
+        (0, hours) => format!("{}h", hours),
+        (days, 0) => format!("{}d", days),
+        (days, hours) => format!("{}d {}h", days, hours),
+    }
+}
@@ -0,0 +1,147 @@
+use anyhow::Context;
+use axum::{
+    extract::{Query, State},
+    http::header::CONTENT_DISPOSITION,
+    response::{Html, IntoResponse, Redirect, Response},
+    Json,
+};
+use axum_sessions::extractors::ReadableSession;
+use htmlescape::{encode_attribute, encode_minimal};
+use http::StatusCode;
+
+use crate::consent::{get_consent_records, ConsentRecord};
+use crate::domain::SubscriberEmail;
+use crate::routes::admin::dashboard::USER_ID_COOKIE;
+use crate::routes::{error_chain_fmt, get_subscriber_id};
+use crate::startup::AppState;
+
+#[derive(thiserror::Error)]
+pub enum ConsentLogError {
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ConsentLogError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for ConsentLogError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct ConsentLogParameters {
+    email: Option<String>,
+}
+
+// None when there is no subscriber with this address, valid or not.
+async fn find_consent_records(
+    state: &AppState,
+    email: &str,
+) -> Result<Option<Vec<ConsentRecord>>, anyhow::Error> {
+    let Ok(email) = SubscriberEmail::parse(email.to_string()) else {
+        return Ok(None);
+    };
+    let Some(subscriber_id) = get_subscriber_id(&state.db_pool, &email)
+        .await
+        .context("Failed to fetch the subscriber.")?
+    else {
+        return Ok(None);
+    };
+    let records = get_consent_records(&state.db_pool, subscriber_id)
+        .await
+        .context("Failed to fetch the consent records.")?;
+    Ok(Some(records))
+}
+
+pub async fn consent_log(
+    State(state): State<AppState>,
+    session: ReadableSession,
+    Query(parameters): Query<ConsentLogParameters>,
+) -> Result<Response, ConsentLogError> {
+    if session.get::<uuid::Uuid>(USER_ID_COOKIE).is_none() {
+        return Ok(Redirect::to("/login").into_response());
+    }
+    let email = parameters.email.unwrap_or_default();
+    let results_html = if email.is_empty() {
+        String::new()
+    } else {
+        match find_consent_records(&state, &email).await? {
+            None => format!("<p>There is no subscriber with the address {}.</p>", encode_minimal(&email)),
+            Some(records) => consent_records_html(&email, &records),
+        }
+    };
+    let html = Html(format!(r#"<!doctype html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Consent log</title>
+</head>
+<body>
+    <form action="/admin/consent" method="get">
+        <label>Subscriber email
+            <input type="email" name="email" value="{email}">
+        </label>
+        <button type="submit">Show</button>
+    </form>
+    {results_html}
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        email = encode_attribute(&email),
+    ));
+    Ok(html.into_response())
+}
+
+fn consent_records_html(email: &str, records: &[ConsentRecord]) -> String {
+    let rows: String = records
+        .iter()
+        .map(|record| {
+            format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                record.recorded_at.to_rfc3339(),
+                encode_minimal(&record.event),
+                encode_minimal(&record.email),
+                encode_minimal(record.ip_address.as_deref().unwrap_or("")),
+                encode_minimal(record.user_agent.as_deref().unwrap_or("")),
+                encode_minimal(record.form_source.as_deref().unwrap_or("")),
+                encode_minimal(&record.privacy_policy_version),
+                encode_minimal(record.attested_by.as_deref().unwrap_or("")),
+                encode_minimal(record.attestation.as_deref().unwrap_or("")),
+            )
+        })
+        .collect();
+    format!(
+        r#"<table>
+        <tr><th>When</th><th>Event</th><th>Email</th><th>IP address</th><th>User agent</th><th>Form</th><th>Privacy policy</th><th>Attested by</th><th>Attestation</th></tr>
+        {rows}
+    </table>
+    <p><a href="/admin/consent/export?email={email}">Export as JSON</a></p>"#,
+        email = encode_attribute(&urlencoding::encode(email)),
+    )
+}
+
+pub async fn export_consent_log(
+    State(state): State<AppState>,
+    session: ReadableSession,
+    Query(parameters): Query<ConsentLogParameters>,
+) -> Result<Response, ConsentLogError> {
+    if session.get::<uuid::Uuid>(USER_ID_COOKIE).is_none() {
+        return Ok(Redirect::to("/login").into_response());
+    }
+    let email = parameters.email.unwrap_or_default();
+    let Some(records) = find_consent_records(&state, &email).await? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    Ok((
+        [(CONTENT_DISPOSITION, r#"attachment; filename="consent-log.json""#)],
+        Json(records),
+    )
+        .into_response())
+}
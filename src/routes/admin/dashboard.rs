@@ -49,6 +49,13 @@ pub async fn admin_dashboard(
     <p>Available actions:</p>
     <ol>
         <li><a href="/admin/password">Change password</a></li>
+        <li><a href="/admin/subscribers">Subscribers</a></li>
+        <li><a href="/admin/consent">Consent log</a></li>
+        <li><a href="/admin/subscribers/import">Import subscribers</a></li>
+        <li><a href="/admin/subscribers/export">Export subscribers</a></li>
+        <li><a href="/admin/attributes">Custom attributes</a></li>
+        <li><a href="/admin/signups">Signups by source</a></li>
+        <li><a href="/admin/automations">Automations</a></li>
         <li>
             <form name="logoutForm" action="/admin/logout" method="post">
                 <input type="submit" value="Logout">
@@ -0,0 +1,24 @@
+use axum_extra::extract::cookie::{Cookie, SignedCookieJar};
+use htmlescape::encode_minimal;
+
+const FLASH_COOKIE: &str = "_flash";
+
+// Scoped to the admin pages, whichever page set it.
+fn flash_cookie(message: String) -> Cookie<'static> {
+    Cookie::build(FLASH_COOKIE, message).path("/admin").finish()
+}
+
+pub(crate) fn set_flash(jar: SignedCookieJar, message: String) -> SignedCookieJar {
+    jar.add(flash_cookie(message))
+}
+
+/// The feedback left by the last action, as HTML, and the jar without it.
+pub(crate) fn take_flash(jar: SignedCookieJar) -> (SignedCookieJar, String) {
+    match jar.get(FLASH_COOKIE) {
+        Some(cookie) => {
+            let html = format!("<p><i>{}</i></p>", encode_minimal(cookie.value()));
+            (jar.remove(flash_cookie(String::new())), html)
+        }
+        None => (jar, String::new()),
+    }
+}
@@ -1,7 +1,18 @@
+mod attributes;
+mod automations;
 mod dashboard;
+mod flash;
 mod password;
 mod logout;
+mod consent;
+mod signups;
+mod subscribers;
 
+pub use attributes::*;
+pub use automations::*;
 pub use dashboard::admin_dashboard;
 pub use password::*;
 pub use logout::logout;
+pub use consent::*;
+pub use signups::*;
+pub use subscribers::*;
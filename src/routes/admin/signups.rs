@@ -0,0 +1,267 @@
+use anyhow::Context;
+use axum::{
+    extract::{Query, State},
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use axum_sessions::extractors::ReadableSession;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use http::StatusCode;
+use sqlx::PgPool;
+
+use crate::domain::SubscriptionStatus;
+use crate::routes::admin::dashboard::USER_ID_COOKIE;
+use crate::routes::error_chain_fmt;
+use crate::startup::AppState;
+
+#[derive(thiserror::Error)]
+pub enum SignupReportError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for SignupReportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for SignupReportError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::ValidationError(message) => (StatusCode::BAD_REQUEST, message).into_response(),
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Period {
+    Day,
+    Week,
+    Month,
+}
+
+impl Period {
+    const ALL: [Self; 3] = [Self::Day, Self::Week, Self::Month];
+
+    fn parse(s: Option<&str>) -> Result<Self, String> {
+        match s {
+            None | Some("") => Ok(Self::Week),
+            Some(s) => Self::ALL
+                .into_iter()
+                .find(|period| period.as_str() == s)
+                .ok_or_else(|| format!("{} is not a supported period", s)),
+        }
+    }
+
+    /// As understood by Postgres' `date_trunc`.
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Day => "day",
+            Self::Week => "week",
+            Self::Month => "month",
+        }
+    }
+
+    // How far back the report goes, counting the current period.
+    fn count(&self) -> i32 {
+        match self {
+            Self::Day => 30,
+            Self::Week => 12,
+            Self::Month => 12,
+        }
+    }
+
+    fn label(&self, start: DateTime<Utc>) -> String {
+        match self {
+            Self::Day | Self::Week => start.format("%Y-%m-%d").to_string(),
+            Self::Month => start.format("%Y-%m").to_string(),
+        }
+    }
+}
+
+/// What the signups are broken down by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Breakdown {
+    Source,
+    UtmSource,
+    UtmMedium,
+    UtmCampaign,
+    /// The host of the referring page.
+    Referrer,
+}
+
+impl Breakdown {
+    const ALL: [Self; 5] = [Self::Source, Self::UtmSource, Self::UtmMedium, Self::UtmCampaign, Self::Referrer];
+
+    fn parse(s: Option<&str>) -> Result<Self, String> {
+        match s {
+            None | Some("") => Ok(Self::Source),
+            Some(s) => Self::ALL
+                .into_iter()
+                .find(|breakdown| breakdown.as_str() == s)
+                .ok_or_else(|| format!("{} is not something signups can be broken down by", s)),
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Source => "source",
+            Self::UtmSource => "utm_source",
+            Self::UtmMedium => "utm_medium",
+            Self::UtmCampaign => "utm_campaign",
+            Self::Referrer => "referrer",
+        }
+    }
+}
+
+// Everything comes in as text: empty form fields mean the default.
+#[derive(Debug, serde::Deserialize)]
+pub struct SignupReportParameters {
+    period: Option<String>,
+    by: Option<String>,
+}
+
+struct SignupCount {
+    period: DateTime<Utc>,
+    value: String,
+    count: i64,
+}
+
+#[tracing::instrument(name = "Count confirmed signups by source", skip(pool))]
+async fn count_signups(pool: &PgPool, period: Period, breakdown: Breakdown) -> Result<Vec<SignupCount>, sqlx::Error> {
+    sqlx::query_as!(
+        SignupCount,
+        r#"
+        SELECT
+            date_trunc($1, subscribed_at) AS "period!",
+            COALESCE(
+                CASE $2
+                    WHEN 'source' THEN signup_source
+                    WHEN 'utm_source' THEN utm_source
+                    WHEN 'utm_medium' THEN utm_medium
+                    WHEN 'utm_campaign' THEN utm_campaign
+                    WHEN 'referrer' THEN substring(referrer FROM '^[A-Za-z][A-Za-z0-9+.-]*://([^/?#:]+)')
+                END,
+                ''
+            ) AS "value!",
+            count(*) AS "count!"
+        FROM subscriptions
+        WHERE status = $4
+            AND subscribed_at >= date_trunc($1, now()) - ($3 - 1) * ('1 ' || $1)::interval
+        GROUP BY 1, 2
+        ORDER BY 1 DESC
+        "#,
+        period.as_str(),
+        breakdown.as_str(),
+        period.count(),
+        SubscriptionStatus::Confirmed.as_str(),
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Confirmed signups per period, one column per source, the busiest first.
+pub async fn signup_report(
+    State(state): State<AppState>,
+    session: ReadableSession,
+    Query(parameters): Query<SignupReportParameters>,
+) -> Result<Response, SignupReportError> {
+    if session.get::<uuid::Uuid>(USER_ID_COOKIE).is_none() {
+        return Ok(Redirect::to("/login").into_response());
+    }
+    let period = Period::parse(parameters.period.as_deref()).map_err(SignupReportError::ValidationError)?;
+    let breakdown = Breakdown::parse(parameters.by.as_deref()).map_err(SignupReportError::ValidationError)?;
+    let counts = count_signups(&state.db_pool, period, breakdown)
+        .await
+        .context("Failed to count the signups.")?;
+
+    let mut values: Vec<(&str, i64)> = Vec::new();
+    for count in &counts {
+        match values.iter_mut().find(|(value, _)| *value == count.value) {
+            Some((_, total)) => *total += count.count,
+            None => values.push((&count.value, count.count)),
+        }
+    }
+    values.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    let mut periods: Vec<DateTime<Utc>> = counts.iter().map(|count| count.period).collect();
+    periods.dedup();
+
+    let report_html = if values.is_empty() {
+        "<p>No confirmed signups in this time.</p>".to_string()
+    } else {
+        let header: String = values
+            .iter()
+            .map(|(value, _)| format!("<th>{}</th>", encode_minimal(display_value(value))))
+            .collect();
+        let rows: String = periods
+            .iter()
+            .map(|period_start| {
+                let cells: String = values
+                    .iter()
+                    .map(|(value, _)| {
+                        let count = counts
+                            .iter()
+                            .find(|count| count.period == *period_start && count.value == *value)
+                            .map_or(0, |count| count.count);
+                        format!("<td>{}</td>", count)
+                    })
+                    .collect();
+                format!("<tr><td>{}</td>{}</tr>", period.label(*period_start), cells)
+            })
+            .collect::<Vec<_>>()
+            .join("\n        ");
+        let totals: String = values.iter().map(|(_, total)| format!("<td>{}</td>", total)).collect();
+        format!(
+            r#"<table>
+        <tr><th>{period}</th>{header}</tr>
+        {rows}
+        <tr><th>Total</th>{totals}</tr>
+    </table>"#,
+            period = period.as_str(),
+        )
+    };
+    let options = |all: &[&'static str], selected: &str| -> String {
+        all.iter()
+            .map(|option| {
+                let selected = if *option == selected { " selected" } else { "" };
+                format!(r#"<option value="{0}"{1}>{0}</option>"#, option, selected)
+            })
+            .collect()
+    };
+    let period_options = options(&Period::ALL.map(|p| p.as_str()), period.as_str());
+    let breakdown_options = options(&Breakdown::ALL.map(|b| b.as_str()), breakdown.as_str());
+    let html = Html(format!(r#"<!doctype html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Signups by source</title>
+</head>
+<body>
+    <form action="/admin/signups" method="get">
+        <label>Per
+            <select name="period">{period_options}</select>
+        </label>
+        <label>By
+            <select name="by">{breakdown_options}</select>
+        </label>
+        <button type="submit">Show</button>
+    </form>
+    <p>Subscribers who confirmed, by when they signed up.</p>
+    {report_html}
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#));
+    Ok(html.into_response())
+}
+
+fn display_value(value: &str) -> &str {
+    if value.is_empty() {
+        "(none)"
+    } else {
+        value
+    }
+}
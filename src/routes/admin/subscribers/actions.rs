@@ -0,0 +1,238 @@
+use anyhow::Context;
+use axum::{
+    extract::{Path, State},
+    Form,
+    response::{IntoResponse, Redirect, Response},
+};
+use axum_extra::extract::cookie::SignedCookieJar;
+use axum_sessions::extractors::ReadableSession;
+use http::StatusCode;
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::audit_log::{record_admin_action, AdminAction};
+use crate::consent::{record_attested_consent, ConsentEvent};
+use crate::automation::{enroll_subscriber, stop_enrollments};
+use crate::domain::SubscriptionStatus;
+use crate::routes::admin::dashboard::{get_username, USER_ID_COOKIE};
+use crate::routes::admin::flash::set_flash;
+use crate::routes::{
+    decode_status, delete_subscription_tokens, erase_subscriber, mark_as_unsubscribed, mark_subscription_tokens_used,
+    set_subscription_status, ClientInfo, SubscriberBrowserError,
+};
+use crate::startup::AppState;
+use crate::subscriber_email_queue::{queue_emails, QueuedEmail};
+
+/// What an admin can do to a subscriber with a button on their page, as
+/// posted to `/admin/subscribers/:subscriber_id/:action`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubscriberAction {
+    Confirm,
+    ResendConfirmation,
+    Unsubscribe,
+    Suppress,
+    Delete,
+}
+
+impl SubscriberAction {
+    pub const ALL: [SubscriberAction; 5] = [
+        SubscriberAction::Confirm,
+        SubscriberAction::ResendConfirmation,
+        SubscriberAction::Unsubscribe,
+        SubscriberAction::Suppress,
+        SubscriberAction::Delete,
+    ];
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|action| action.as_str() == s)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        AdminAction::from(*self).as_str()
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SubscriberAction::Confirm => "Confirm",
+            SubscriberAction::ResendConfirmation => "Resend confirmation",
+            SubscriberAction::Unsubscribe => "Unsubscribe",
+            SubscriberAction::Suppress => "Suppress",
+            SubscriberAction::Delete => "Delete",
+        }
+    }
+}
+
+impl From<SubscriberAction> for AdminAction {
+    fn from(action: SubscriberAction) -> Self {
+        match action {
+            SubscriberAction::Confirm => AdminAction::Confirm,
+            SubscriberAction::ResendConfirmation => AdminAction::ResendConfirmation,
+            SubscriberAction::Unsubscribe => AdminAction::Unsubscribe,
+            SubscriberAction::Suppress => AdminAction::Suppress,
+            SubscriberAction::Delete => AdminAction::Delete,
+        }
+    }
+}
+
+/// The actions that make sense for a subscriber with this status.
+pub(crate) fn available_actions(status: SubscriptionStatus) -> Vec<SubscriberAction> {
+    SubscriberAction::ALL
+        .into_iter()
+        .filter(|action| match action {
+            SubscriberAction::Confirm => status.transition(SubscriptionStatus::Confirmed).is_ok(),
+            SubscriberAction::ResendConfirmation => status == SubscriptionStatus::PendingConfirmation,
+            SubscriberAction::Unsubscribe => status.transition(SubscriptionStatus::Unsubscribed).is_ok(),
+            SubscriberAction::Suppress => status.transition(SubscriptionStatus::Suppressed).is_ok(),
+            SubscriberAction::Delete => true,
+        })
+        .collect()
+}
+
+#[derive(serde::Deserialize)]
+pub struct ActionForm {
+    /// How the subscriber consented, required to confirm them by hand.
+    #[serde(default)]
+    attestation: String,
+}
+
+struct ActedOnSubscriber {
+    email: String,
+    status: SubscriptionStatus,
+}
+
+#[tracing::instrument(name = "Act on a subscriber", skip(state, session, jar, client, form))]
+pub async fn subscriber_action(
+    State(state): State<AppState>,
+    session: ReadableSession,
+    jar: SignedCookieJar,
+    client: ClientInfo,
+    Path((subscriber_id, action)): Path<(Uuid, String)>,
+    Form(form): Form<ActionForm>,
+) -> Result<Response, SubscriberBrowserError> {
+    let Some(user_id) = session.get::<Uuid>(USER_ID_COOKIE) else {
+        return Ok(Redirect::to("/login").into_response());
+    };
+    let Some(action) = SubscriberAction::parse(&action) else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let mut transaction = state.db_pool.begin().await.context("Failed to acquire a database connection.")?;
+    let Some(subscriber) = lock_subscriber(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to fetch the subscriber.")?
+    else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let detail_page = format!("/admin/subscribers/{}", subscriber_id);
+    let status = subscriber.status;
+    if !available_actions(status).contains(&action) {
+        let message = format!(
+            "{} can't be applied to {}, whose status is {}.",
+            action.label(),
+            subscriber.email,
+            status,
+        );
+        return Ok((set_flash(jar, message), Redirect::to(&detail_page)).into_response());
+    }
+    let attestation = form.attestation.trim();
+    if action == SubscriberAction::Confirm && attestation.is_empty() {
+        let message = "Confirming a subscriber by hand needs a statement of how they consented.".to_string();
+        return Ok((set_flash(jar, message), Redirect::to(&detail_page)).into_response());
+    }
+
+    let new_status = match action {
+        SubscriberAction::Confirm => {
+            set_status(&mut transaction, subscriber_id, SubscriptionStatus::Confirmed).await?;
+            let username = get_username(user_id, &state.db_pool).await?;
+            record_attested_consent(
+                &mut transaction,
+                subscriber_id,
+                ConsentEvent::ConfirmedByAdmin,
+                &client,
+                &username,
+                attestation,
+                &state.privacy_policy_version,
+            )
+            .await
+            .context("Failed to record the consent of a subscriber.")?;
+            mark_subscription_tokens_used(&mut transaction, subscriber_id)
+                .await
+                .context("Failed to mark the tokens of a subscriber as used.")?;
+            enroll_subscriber(&mut transaction, subscriber_id)
+                .await
+                .context("Failed to enroll a subscriber in automations.")?;
+            Some(SubscriptionStatus::Confirmed)
+        }
+        SubscriberAction::ResendConfirmation => {
+            // The worker issues the new link, next to the ones they have.
+            queue_emails(&mut transaction, &[subscriber_id], QueuedEmail::Confirmation)
+                .await
+                .context("Failed to queue a confirmation email.")?;
+            None
+        }
+        SubscriberAction::Unsubscribe => {
+            mark_as_unsubscribed(&mut transaction, subscriber_id)
+                .await
+                .context("Failed to unsubscribe a subscriber.")?;
+            Some(SubscriptionStatus::Unsubscribed)
+        }
+        SubscriberAction::Suppress => {
+            set_status(&mut transaction, subscriber_id, SubscriptionStatus::Suppressed).await?;
+            delete_subscription_tokens(&mut transaction, subscriber_id)
+                .await
+                .context("Failed to delete the tokens of a subscriber.")?;
+            stop_enrollments(&mut transaction, subscriber_id)
+                .await
+                .context("Failed to stop the automations of a subscriber.")?;
+            Some(SubscriptionStatus::Suppressed)
+        }
+        SubscriberAction::Delete => {
+            erase_subscriber(&mut transaction, subscriber_id)
+                .await
+                .context("Failed to delete a subscriber.")?;
+            None
+        }
+    };
+    let detail = new_status.map(|new_status| format!("{} -> {}", status, new_status));
+    record_admin_action(&mut transaction, user_id, action.into(), subscriber_id, detail.as_deref())
+        .await
+        .context("Failed to record an admin action.")?;
+    transaction.commit().await.context("Failed to commit SQL transaction for an admin action.")?;
+    tracing::info!(action = action.as_str(), %subscriber_id, "An admin acted on a subscriber.");
+
+    let message = match action {
+        SubscriberAction::Confirm => format!("{} was confirmed.", subscriber.email),
+        SubscriberAction::ResendConfirmation => format!("A new confirmation email to {} is on its way.", subscriber.email),
+        SubscriberAction::Unsubscribe => format!("{} was unsubscribed.", subscriber.email),
+        SubscriberAction::Suppress => format!("{} was suppressed, they won't get any more email.", subscriber.email),
+        SubscriberAction::Delete => format!("{} was deleted.", subscriber.email),
+    };
+    let next_page = if action == SubscriberAction::Delete { "/admin/subscribers" } else { &detail_page };
+    Ok((set_flash(jar, message), Redirect::to(next_page)).into_response())
+}
+
+// Locked, so that two admins can't act on the same subscriber at once.
+#[tracing::instrument(name = "Lock a subscriber", skip(transaction))]
+async fn lock_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<Option<ActedOnSubscriber>, sqlx::Error> {
+    let row = sqlx::query!(r#"SELECT email, status FROM subscriptions WHERE id = $1 FOR UPDATE"#, subscriber_id)
+        .fetch_optional(transaction)
+        .await?;
+    row.map(|row| Ok(ActedOnSubscriber { email: row.email, status: decode_status(&row.status)? }))
+        .transpose()
+}
+
+// The row is locked and the action was checked against its status, so the
+// change can't be refused.
+async fn set_status(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    status: SubscriptionStatus,
+) -> Result<(), anyhow::Error> {
+    let changed = set_subscription_status(&mut *transaction, subscriber_id, status)
+        .await
+        .context("Failed to update the status of a subscriber.")?;
+    anyhow::ensure!(changed, "The subscriber could not become {}.", status);
+    Ok(())
+}
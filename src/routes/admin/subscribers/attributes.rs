@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Redirect, Response},
+    Form,
+};
+use axum_extra::extract::cookie::SignedCookieJar;
+use axum_sessions::extractors::ReadableSession;
+use http::StatusCode;
+use serde_json::Value;
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::attribute_schema::get_attribute_schema;
+use crate::audit_log::{record_admin_action, AdminAction};
+use crate::routes::admin::dashboard::USER_ID_COOKIE;
+use crate::routes::admin::flash::set_flash;
+use crate::routes::SubscriberBrowserError;
+use crate::startup::AppState;
+
+/// Replaces the custom attributes of a subscriber with the ones in the form,
+/// checked the same way as on the subscription form.
+#[tracing::instrument(name = "Edit the attributes of a subscriber", skip(state, session, jar, form))]
+pub async fn update_subscriber_attributes(
+    State(state): State<AppState>,
+    session: ReadableSession,
+    jar: SignedCookieJar,
+    Path(subscriber_id): Path<Uuid>,
+    Form(form): Form<HashMap<String, String>>,
+) -> Result<Response, SubscriberBrowserError> {
+    let Some(user_id) = session.get::<Uuid>(USER_ID_COOKIE) else {
+        return Ok(Redirect::to("/login").into_response());
+    };
+    let detail_page = format!("/admin/subscribers/{}", subscriber_id);
+    let mut transaction = state.db_pool.begin().await.context("Failed to acquire a database connection.")?;
+    let Some(old_attributes) = lock_attributes(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to fetch the attributes of a subscriber.")?
+    else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let schema = get_attribute_schema(&mut transaction).await?;
+    let values = form
+        .into_iter()
+        .filter_map(|(field, value)| Some((field.strip_prefix("attributes.")?.to_string(), value)))
+        .collect();
+    let attributes = match schema.parse_form(&values) {
+        Ok(attributes) => attributes.to_json(),
+        Err(errors) => {
+            let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            let message = format!("The attributes were not saved: {}.", errors.join(", "));
+            return Ok((set_flash(jar, message), Redirect::to(&detail_page)).into_response());
+        }
+    };
+    let changed = changed_attributes(&old_attributes, &attributes);
+    if changed.is_empty() {
+        return Ok((set_flash(jar, "Nothing changed.".into()), Redirect::to(&detail_page)).into_response());
+    }
+    sqlx::query!(
+        r#"UPDATE subscriptions SET attributes = $2 WHERE id = $1"#,
+        subscriber_id,
+        attributes,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to update the attributes of a subscriber.")?;
+    let detail = format!("changed {}", changed.join(", "));
+    record_admin_action(&mut transaction, user_id, AdminAction::EditAttributes, subscriber_id, Some(&detail))
+        .await
+        .context("Failed to record an admin action.")?;
+    transaction.commit().await.context("Failed to commit SQL transaction to edit attributes.")?;
+    Ok((set_flash(jar, "The attributes were saved.".into()), Redirect::to(&detail_page)).into_response())
+}
+
+// The names of the attributes that were added, changed or removed.
+fn changed_attributes(old: &Value, new: &Value) -> Vec<String> {
+    let empty = serde_json::Map::new();
+    let old = old.as_object().unwrap_or(&empty);
+    let new = new.as_object().unwrap_or(&empty);
+    let mut changed: Vec<String> = old
+        .keys()
+        .chain(new.keys())
+        .filter(|name| old.get(*name) != new.get(*name))
+        .cloned()
+        .collect();
+    changed.sort();
+    changed.dedup();
+    changed
+}
+
+#[tracing::instrument(name = "Lock the attributes of a subscriber", skip(transaction))]
+async fn lock_attributes(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<Option<Value>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT attributes FROM subscriptions WHERE id = $1 FOR UPDATE"#,
+        subscriber_id,
+    )
+    .fetch_optional(transaction)
+    .await?;
+    Ok(row.map(|r| r.attributes))
+}
@@ -0,0 +1,227 @@
+use anyhow::Context;
+use axum::{
+    extract::{Path, State},
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use axum_extra::extract::cookie::SignedCookieJar;
+use axum_sessions::extractors::ReadableSession;
+use chrono::Utc;
+use htmlescape::encode_minimal;
+use http::StatusCode;
+use uuid::Uuid;
+
+use crate::attribute_schema::get_attribute_schema;
+use crate::audit_log::{get_admin_actions, AuditLogEntry};
+use crate::domain::AttributeSchema;
+use crate::routes::admin::dashboard::USER_ID_COOKIE;
+use crate::routes::{attribute_fields, export_subscriber_data, SubscriberBrowserError, SubscriberDataExport};
+use crate::startup::AppState;
+use crate::routes::admin::flash::take_flash;
+use super::actions::{available_actions, SubscriberAction};
+
+pub async fn subscriber_detail(
+    State(state): State<AppState>,
+    session: ReadableSession,
+    jar: SignedCookieJar,
+    Path(subscriber_id): Path<Uuid>,
+) -> Result<Response, SubscriberBrowserError> {
+    if session.get::<Uuid>(USER_ID_COOKIE).is_none() {
+        return Ok(Redirect::to("/login").into_response());
+    }
+    let Some(data) = export_subscriber_data(&state.db_pool, subscriber_id)
+        .await
+        .context("Failed to fetch the subscriber.")?
+    else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    let admin_actions = get_admin_actions(&state.db_pool, subscriber_id)
+        .await
+        .context("Failed to fetch the admin actions on the subscriber.")?;
+    let schema = get_attribute_schema(&state.db_pool).await?;
+    let (jar, flash_html) = take_flash(jar);
+    Ok((jar, Html(subscriber_page(&data, &schema, &admin_actions, &flash_html))).into_response())
+}
+
+// Table rows, or a line saying there is nothing to show.
+fn table(headers: &[&str], rows: Vec<String>) -> String {
+    if rows.is_empty() {
+        return "<p>None.</p>".to_string();
+    }
+    let headers: String = headers.iter().map(|header| format!("<th>{}</th>", header)).collect();
+    format!("<table>\n        <tr>{}</tr>\n        {}\n    </table>", headers, rows.join("\n        "))
+}
+
+fn subscriber_page(
+    data: &SubscriberDataExport,
+    schema: &AttributeSchema,
+    admin_actions: &[AuditLogEntry],
+    flash_html: &str,
+) -> String {
+    let subscription = &data.subscription;
+    let campaign = [
+        ("utm_source", &subscription.utm_source),
+        ("utm_medium", &subscription.utm_medium),
+        ("utm_campaign", &subscription.utm_campaign),
+        ("utm_term", &subscription.utm_term),
+        ("utm_content", &subscription.utm_content),
+    ]
+    .iter()
+    .filter_map(|(name, value)| Some(format!("{}={}", name, value.as_deref()?)))
+    .collect::<Vec<_>>()
+    .join(", ");
+    let attributes = if schema.definitions().is_empty() {
+        r#"<p>No <a href="/admin/attributes">custom attributes</a> are defined.</p>"#.to_string()
+    } else {
+        let empty = serde_json::Map::new();
+        format!(
+            r#"<form action="/admin/subscribers/{}/attributes" method="post">
+        {}
+        <button type="submit">Save</button>
+    </form>"#,
+            subscription.id,
+            attribute_fields(schema, subscription.attributes.as_object().unwrap_or(&empty)),
+        )
+    };
+    let actions: String = available_actions(subscription.status)
+        .into_iter()
+        .map(|action| {
+            // Like an import as confirmed, it stands in for the subscriber's
+            // own confirmation.
+            let attestation = if action == SubscriberAction::Confirm {
+                r#"<label>How did they consent? <input type="text" name="attestation" required></label> "#
+            } else {
+                ""
+            };
+            format!(
+                r#"<form action="/admin/subscribers/{}/{}" method="post">{}<button type="submit">{}</button></form>"#,
+                subscription.id,
+                action.as_str(),
+                attestation,
+                action.label(),
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n    ");
+    let now = Utc::now();
+    let tokens = table(
+        &["Kind", "Issued at", "Expires at", ""],
+        data.subscription_tokens
+            .iter()
+            .map(|token| ("Confirmation", token.issued_at, token.expires_at, token.used_at, None))
+            .chain(data.email_change_requests.iter().map(|request| {
+                ("Email change", request.issued_at, request.expires_at, None, Some(request.new_email.as_str()))
+            }))
+            .map(|(kind, issued_at, expires_at, used_at, new_email)| {
+                let kind = match new_email {
+                    Some(new_email) => format!("{} to {}", kind, encode_minimal(new_email)),
+                    None => kind.to_string(),
+                };
+                format!(
+                    "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                    kind,
+                    issued_at.to_rfc3339(),
+                    expires_at.to_rfc3339(),
+                    if used_at.is_some() {
+                        "used"
+                    } else if expires_at <= now {
+                        "expired"
+                    } else {
+                        ""
+                    },
+                )
+            })
+            .collect(),
+    );
+    let deliveries = table(
+        &["Sent at", "Kind", "Newsletter", "Message id"],
+        data.email_deliveries
+            .iter()
+            .map(|delivery| {
+                format!(
+                    "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                    delivery.sent_at.to_rfc3339(),
+                    encode_minimal(&delivery.kind),
+                    encode_minimal(delivery.newsletter_title.as_deref().unwrap_or("")),
+                    encode_minimal(delivery.message_id.as_deref().unwrap_or("")),
+                )
+            })
+            .collect(),
+    );
+    let consent = table(
+        &["When", "Event", "Email", "IP address", "User agent", "Form", "Privacy policy"],
+        data.consent_records
+            .iter()
+            .map(|record| {
+                format!(
+                    "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                    record.recorded_at.to_rfc3339(),
+                    encode_minimal(&record.event),
+                    encode_minimal(&record.email),
+                    encode_minimal(record.ip_address.as_deref().unwrap_or("")),
+                    encode_minimal(record.user_agent.as_deref().unwrap_or("")),
+                    encode_minimal(record.form_source.as_deref().unwrap_or("")),
+                    encode_minimal(&record.privacy_policy_version),
+                )
+            })
+            .collect(),
+    );
+    let audit_log = table(
+        &["When", "Admin", "Action", "Detail"],
+        admin_actions
+            .iter()
+            .map(|entry| {
+                format!(
+                    "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                    entry.performed_at.to_rfc3339(),
+                    encode_minimal(&entry.username),
+                    encode_minimal(&entry.action),
+                    encode_minimal(entry.detail.as_deref().unwrap_or("")),
+                )
+            })
+            .collect(),
+    );
+    format!(r#"<!doctype html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Subscriber {email}</title>
+</head>
+<body>
+    {flash_html}
+    <h1>{email}</h1>
+    <dl>
+        <dt>Name</dt><dd>{name}</dd>
+        <dt>Status</dt><dd>{status}</dd>
+        <dt>Subscribed at</dt><dd>{subscribed_at}</dd>
+        <dt>Delivery frequency</dt><dd>{delivery_frequency}</dd>
+        <dt>Topics</dt><dd>{topics}</dd>
+        <dt>Signup source</dt><dd>{signup_source}</dd>
+        <dt>Referrer</dt><dd>{referrer}</dd>
+        <dt>Campaign</dt><dd>{campaign}</dd>
+    </dl>
+    <h2>Attributes</h2>
+    {attributes}
+    <h2>Actions</h2>
+    {actions}
+    <h2>Tokens</h2>
+    {tokens}
+    <h2>Delivery history</h2>
+    {deliveries}
+    <h2>Consent records</h2>
+    {consent}
+    <h2>Admin actions</h2>
+    {audit_log}
+    <p><a href="/admin/subscribers">&lt;- Back</a></p>
+</body>
+</html>"#,
+        email = encode_minimal(&subscription.email),
+        name = encode_minimal(&subscription.name),
+        status = subscription.status,
+        subscribed_at = subscription.subscribed_at.to_rfc3339(),
+        delivery_frequency = encode_minimal(&subscription.delivery_frequency),
+        topics = encode_minimal(&subscription.topics.join(", ")),
+        signup_source = encode_minimal(subscription.signup_source.as_deref().unwrap_or("")),
+        referrer = encode_minimal(subscription.referrer.as_deref().unwrap_or("")),
+        campaign = encode_minimal(&campaign),
+    )
+}
@@ -0,0 +1,412 @@
+use std::collections::HashSet;
+
+use anyhow::Context;
+use axum::{
+    extract::{multipart::MultipartError, Multipart, Path, State},
+    http::header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use axum_sessions::extractors::ReadableSession;
+use htmlescape::encode_minimal;
+use http::StatusCode;
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::attribute_schema::get_attribute_schema;
+use crate::consent::{record_attested_consent_batch, ConsentEvent};
+use crate::domain::{AttributeSchema, NewSubscriber};
+use crate::routes::admin::dashboard::{get_username, USER_ID_COOKIE};
+use crate::routes::{error_chain_fmt, ClientInfo};
+use crate::startup::AppState;
+use crate::subscriber_email_queue::{queue_emails, QueuedEmail};
+use crate::subscriber_import::{error_report, CsvRecordReader, ImportColumns, ImportMode, RowError};
+
+/// Uploads are streamed, this only bounds how long one import can keep a
+/// connection busy.
+pub const MAX_IMPORT_BYTES: usize = 64 * 1024 * 1024;
+
+// Rows inserted per transaction.
+const IMPORT_BATCH_SIZE: usize = 500;
+
+#[derive(thiserror::Error)]
+pub enum ImportError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl From<MultipartError> for ImportError {
+    fn from(e: MultipartError) -> Self {
+        Self::ValidationError(format!("The upload could not be read: {}", e))
+    }
+}
+
+impl IntoResponse for ImportError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::ValidationError(ref message) => (
+                StatusCode::BAD_REQUEST,
+                import_page(&format!("<p><i>{}</i></p>", encode_minimal(message))),
+            )
+                .into_response(),
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        }
+    }
+}
+
+fn import_page(message_html: &str) -> Html<String> {
+    Html(format!(r#"<!doctype html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Import subscribers</title>
+</head>
+<body>
+    {message_html}
+    <p>Upload a CSV file with a header row that has a <code>name</code> and an <code>email</code> column. Columns named after a <a href="/admin/attributes">custom attribute</a> are imported too.</p>
+    <form action="/admin/subscribers/import" method="post" enctype="multipart/form-data">
+        <fieldset>
+            <legend>The imported subscribers</legend>
+            <label>
+                <input type="radio" name="mode" value="send_confirmation" checked>
+                get a confirmation email, and join the list once they confirm
+            </label>
+            <br>
+            <label>
+                <input type="radio" name="mode" value="confirmed">
+                already confirmed their subscription elsewhere
+            </label>
+            <br>
+            <label>How and when did they consent? Required when importing them as confirmed.
+                <textarea name="attestation" placeholder="e.g. Signed up through our previous newsletter platform, double opt-in, 2019-2026"></textarea>
+            </label>
+        </fieldset>
+        <!-- The file has to come last, it's read as it arrives. -->
+        <label>CSV file
+            <input type="file" name="file" accept=".csv,text/csv">
+        </label>
+        <button type="submit">Import</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#))
+}
+
+pub async fn import_subscribers_form(session: ReadableSession) -> Response {
+    if session.get::<Uuid>(USER_ID_COOKIE).is_none() {
+        return Redirect::to("/login").into_response();
+    }
+    import_page("").into_response()
+}
+
+/// Tallies an import as it goes.
+struct Import {
+    id: Uuid,
+    mode: ImportMode,
+    // Who vouched for the consent of the subscribers and how, when they are
+    // imported as confirmed.
+    attestation: Option<(String, String)>,
+    schema: AttributeSchema,
+    columns: Option<ImportColumns>,
+    // Canonical addresses seen so far, to catch duplicates within the file.
+    seen: HashSet<String>,
+    batch: Vec<(u64, NewSubscriber)>,
+    imported_rows: usize,
+    errors: Vec<RowError>,
+}
+
+#[tracing::instrument(name = "Importing subscribers", skip(state, session, client, multipart))]
+pub async fn import_subscribers(
+    State(state): State<AppState>,
+    session: ReadableSession,
+    client: ClientInfo,
+    mut multipart: Multipart,
+) -> Result<Response, ImportError> {
+    let Some(user_id) = session.get::<Uuid>(USER_ID_COOKIE) else {
+        return Ok(Redirect::to("/login").into_response());
+    };
+    let mut mode = None;
+    let mut attestation = String::new();
+    let mut import = None;
+    while let Some(mut field) = multipart.next_field().await? {
+        match field.name() {
+            Some("mode") => {
+                mode = Some(ImportMode::parse(&field.text().await?).map_err(ImportError::ValidationError)?)
+            }
+            Some("attestation") => attestation = field.text().await?.trim().to_string(),
+            Some("file") => {
+                let mode = mode.ok_or_else(|| {
+                    ImportError::ValidationError("Choose how to import the subscribers before the file.".into())
+                })?;
+                if mode == ImportMode::Confirmed && attestation.is_empty() {
+                    return Err(ImportError::ValidationError(
+                        "Importing subscribers as confirmed needs a statement of how they consented.".into(),
+                    ));
+                }
+                let attestation = match mode {
+                    ImportMode::Confirmed => Some((get_username(user_id, &state.db_pool).await?, attestation.clone())),
+                    ImportMode::SendConfirmation => None,
+                };
+                let import_id = start_import(
+                    &state.db_pool,
+                    user_id,
+                    mode,
+                    attestation.as_ref().map(|(_, statement)| statement.as_str()),
+                )
+                .await
+                .context("Failed to record the start of an import.")?;
+                let schema = get_attribute_schema(&state.db_pool)
+                    .await
+                    .context("Failed to fetch the attribute schema.")?;
+                let mut current = Import {
+                    id: import_id,
+                    mode,
+                    attestation,
+                    schema,
+                    columns: None,
+                    seen: HashSet::new(),
+                    batch: Vec::with_capacity(IMPORT_BATCH_SIZE),
+                    imported_rows: 0,
+                    errors: Vec::new(),
+                };
+                let mut reader = CsvRecordReader::default();
+                while let Some(chunk) = field.chunk().await? {
+                    for record in reader.read(&chunk) {
+                        add_record(&state, &client, &mut current, record).await?;
+                    }
+                }
+                for record in reader.finish() {
+                    add_record(&state, &client, &mut current, record).await?;
+                }
+                if current.columns.is_none() {
+                    return Err(ImportError::ValidationError("The file is empty.".into()));
+                }
+                flush_batch(&state, &client, &mut current).await?;
+                import = Some(current);
+            }
+            _ => {}
+        }
+    }
+    let mut import = import.ok_or_else(|| ImportError::ValidationError("Choose a CSV file to import.".into()))?;
+    // Rows that were already on the list only show up once their batch is in.
+    import.errors.sort_by_key(|error| error.row);
+    let report = error_report(&import.errors).context("Failed to write the error report of an import.")?;
+    finish_import(&state.db_pool, &import, &report)
+        .await
+        .context("Failed to record the end of an import.")?;
+    tracing::info!(
+        import_id = %import.id,
+        imported_rows = import.imported_rows,
+        failed_rows = import.errors.len(),
+        "Imported subscribers."
+    );
+
+    let report_html = if import.errors.is_empty() {
+        String::new()
+    } else {
+        format!(
+            r#"<p>{} rows were not imported. <a href="/admin/subscribers/imports/{}/errors">Download the error report</a> to see which rows of your file they are, and why.</p>"#,
+            import.errors.len(),
+            import.id,
+        )
+    };
+    let message_html = format!("<p><i>Imported {} subscribers.</i></p>{}", import.imported_rows, report_html);
+    Ok(import_page(&message_html).into_response())
+}
+
+async fn add_record(
+    state: &AppState,
+    client: &ClientInfo,
+    import: &mut Import,
+    record: crate::subscriber_import::CsvRecord,
+) -> Result<(), ImportError> {
+    let fields = match (record.fields, &import.columns) {
+        (Ok(fields), Some(_)) => fields,
+        (Ok(header), None) => {
+            import.columns = Some(ImportColumns::from_header(&header, &import.schema).map_err(ImportError::ValidationError)?);
+            return Ok(());
+        }
+        (Err(_), None) => {
+            return Err(ImportError::ValidationError("The header row is not valid UTF-8.".into()));
+        }
+        (Err(reason), Some(_)) => {
+            import.errors.push(RowError { row: record.row, reason });
+            return Ok(());
+        }
+    };
+    // Blank lines, e.g. at the end of the file.
+    if fields.iter().all(|field| field.trim().is_empty()) {
+        return Ok(());
+    }
+    let columns = import.columns.as_ref().expect("The header has been read");
+    let error = |reason: String| RowError { row: record.row, reason };
+    match columns.parse(&fields, &state.domain_blocklist, &import.schema) {
+        Err(reason) => import.errors.push(error(reason)),
+        Ok(subscriber) if !import.seen.insert(subscriber.email.as_ref().to_lowercase()) => {
+            import.errors.push(error("The address is already in the file".into()));
+        }
+        Ok(subscriber) => {
+            import.batch.push((record.row, subscriber));
+            if import.batch.len() == IMPORT_BATCH_SIZE {
+                flush_batch(state, client, import).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Inserts the batch in a single transaction. Confirmation emails are queued
+// in it too, the upload doesn't wait for them to be sent.
+async fn flush_batch(state: &AppState, client: &ClientInfo, import: &mut Import) -> Result<(), anyhow::Error> {
+    if import.batch.is_empty() {
+        return Ok(());
+    }
+    let batch = std::mem::take(&mut import.batch);
+    let mut transaction = state.db_pool.begin().await.context("Failed to acquire a database connection.")?;
+    let inserted = insert_subscribers(&mut transaction, &batch, import.mode)
+        .await
+        .context("Failed to insert a batch of imported subscribers.")?;
+    let inserted_ids: Vec<Uuid> = inserted.iter().map(|(_, id)| *id).collect();
+    match import.mode {
+        ImportMode::Confirmed => {
+            let (attested_by, attestation) =
+                import.attestation.as_ref().context("An import as confirmed has an attestation.")?;
+            record_attested_consent_batch(
+                &mut transaction,
+                &inserted_ids,
+                ConsentEvent::Imported,
+                client,
+                Some(&format!("import {}", import.id)),
+                attested_by,
+                attestation,
+                &state.privacy_policy_version,
+            )
+            .await
+            .context("Failed to record the consent of imported subscribers.")?;
+        }
+        ImportMode::SendConfirmation => {
+            queue_emails(&mut transaction, &inserted_ids, QueuedEmail::Confirmation)
+                .await
+                .context("Failed to queue the confirmation emails of imported subscribers.")?;
+        }
+    }
+    transaction.commit().await.context("Failed to commit a batch of imported subscribers.")?;
+    import.imported_rows += inserted.len();
+
+    let mut inserted = inserted.into_iter().peekable();
+    for (index, (row, _)) in batch.into_iter().enumerate() {
+        if inserted.next_if(|(inserted_index, _)| *inserted_index == index).is_none() {
+            import.errors.push(RowError { row, reason: "The address is already on the list".into() });
+        }
+    }
+    Ok(())
+}
+
+/// Returns the position in the batch and the id of every subscriber that was
+/// inserted, in batch order. Addresses already on the list are left alone.
+#[tracing::instrument(name = "Saving a batch of imported subscribers", skip(transaction, batch))]
+async fn insert_subscribers(
+    transaction: &mut Transaction<'_, Postgres>,
+    batch: &[(u64, NewSubscriber)],
+    mode: ImportMode,
+) -> Result<Vec<(usize, Uuid)>, sqlx::Error> {
+    let ids: Vec<Uuid> = batch.iter().map(|_| Uuid::new_v4()).collect();
+    let emails: Vec<String> = batch.iter().map(|(_, s)| s.email.as_ref().to_string()).collect();
+    let names: Vec<String> = batch.iter().map(|(_, s)| s.name.as_ref().to_string()).collect();
+    let attributes: Vec<serde_json::Value> = batch.iter().map(|(_, s)| s.attributes.to_json()).collect();
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status, attributes, signup_source)
+        SELECT id, email, name, now(), $4, attributes, 'import'
+        FROM UNNEST($1::uuid[], $2::text[], $3::text[], $5::jsonb[]) AS batch(id, email, name, attributes)
+        ON CONFLICT (email_canonical) DO NOTHING
+        RETURNING id
+        "#,
+        &ids,
+        &emails,
+        &names,
+        mode.subscription_status().as_str(),
+        &attributes,
+    )
+    .fetch_all(transaction)
+    .await?;
+    let inserted: HashSet<Uuid> = inserted.into_iter().map(|r| r.id).collect();
+    Ok(ids
+        .into_iter()
+        .enumerate()
+        .filter(|(_, id)| inserted.contains(id))
+        .collect())
+}
+
+#[tracing::instrument(name = "Record the start of an import", skip(pool, attestation))]
+async fn start_import(
+    pool: &sqlx::PgPool,
+    user_id: Uuid,
+    mode: ImportMode,
+    attestation: Option<&str>,
+) -> Result<Uuid, sqlx::Error> {
+    let import_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_imports (id, imported_by, mode, attestation, started_at)
+        VALUES ($1, $2, $3, $4, now())
+        "#,
+        import_id,
+        user_id,
+        mode.as_str(),
+        attestation,
+    )
+    .execute(pool)
+    .await?;
+    Ok(import_id)
+}
+
+#[tracing::instrument(name = "Record the end of an import", skip(pool, import, report), fields(import_id = %import.id))]
+async fn finish_import(pool: &sqlx::PgPool, import: &Import, report: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscriber_imports
+        SET finished_at = now(), imported_rows = $2, failed_rows = $3, error_report = $4
+        WHERE id = $1
+        "#,
+        import.id,
+        import.imported_rows as i32,
+        import.errors.len() as i32,
+        report,
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn download_import_errors(
+    State(state): State<AppState>,
+    session: ReadableSession,
+    Path(import_id): Path<Uuid>,
+) -> Result<Response, ImportError> {
+    if session.get::<Uuid>(USER_ID_COOKIE).is_none() {
+        return Ok(Redirect::to("/login").into_response());
+    }
+    let report = sqlx::query!("SELECT error_report FROM subscriber_imports WHERE id = $1", import_id)
+        .fetch_optional(&state.db_pool)
+        .await
+        .context("Failed to fetch the error report of an import.")?;
+    let Some(report) = report else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+    Ok((
+        [
+            (CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+            (CONTENT_DISPOSITION, format!(r#"attachment; filename="import-{}-errors.csv""#, import_id)),
+        ],
+        report.error_report,
+    )
+        .into_response())
+}
@@ -0,0 +1,354 @@
+use anyhow::Context;
+use axum::{
+    extract::{Query, State},
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use axum_extra::extract::cookie::SignedCookieJar;
+use axum_sessions::extractors::ReadableSession;
+use chrono::{DateTime, SecondsFormat, Utc};
+use htmlescape::{encode_attribute, encode_minimal};
+use http::StatusCode;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::SubscriptionStatus;
+use crate::routes::admin::dashboard::USER_ID_COOKIE;
+use crate::routes::{decode_status, error_chain_fmt};
+use crate::startup::AppState;
+use crate::routes::admin::flash::take_flash;
+
+const PAGE_SIZE: i64 = 50;
+
+#[derive(thiserror::Error)]
+pub enum SubscriberBrowserError {
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for SubscriberBrowserError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for SubscriberBrowserError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SortOrder {
+    NewestFirst,
+    OldestFirst,
+}
+
+impl SortOrder {
+    fn parse(s: Option<&str>) -> Self {
+        match s {
+            Some("oldest") => Self::OldestFirst,
+            _ => Self::NewestFirst,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::NewestFirst => "newest",
+            Self::OldestFirst => "oldest",
+        }
+    }
+}
+
+/// Where a page starts: right after the last subscriber of the previous one.
+#[derive(Debug, Clone, Copy)]
+struct PageCursor {
+    subscribed_at: DateTime<Utc>,
+    id: Uuid,
+}
+
+impl PageCursor {
+    fn parse(s: &str) -> Option<Self> {
+        let (subscribed_at, id) = s.split_once('_')?;
+        Some(Self {
+            subscribed_at: DateTime::parse_from_rfc3339(subscribed_at).ok()?.with_timezone(&Utc),
+            id: Uuid::parse_str(id).ok()?,
+        })
+    }
+
+    // Postgres keeps microseconds, so does the cursor.
+    fn encode(&self) -> String {
+        format!("{}_{}", self.subscribed_at.to_rfc3339_opts(SecondsFormat::Micros, true), self.id)
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct SubscriberBrowserParameters {
+    q: Option<String>,
+    status: Option<String>,
+    sort: Option<String>,
+    after: Option<String>,
+}
+
+struct SubscriberSummary {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: SubscriptionStatus,
+    subscribed_at: DateTime<Utc>,
+}
+
+// A row as it comes out of the database, before its status is decoded.
+struct SubscriberSummaryRow {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+impl TryFrom<SubscriberSummaryRow> for SubscriberSummary {
+    type Error = sqlx::Error;
+
+    fn try_from(row: SubscriberSummaryRow) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: row.id,
+            email: row.email,
+            name: row.name,
+            status: decode_status(&row.status)?,
+            subscribed_at: row.subscribed_at,
+        })
+    }
+}
+
+pub async fn browse_subscribers(
+    State(state): State<AppState>,
+    session: ReadableSession,
+    jar: SignedCookieJar,
+    Query(parameters): Query<SubscriberBrowserParameters>,
+) -> Result<Response, SubscriberBrowserError> {
+    if session.get::<Uuid>(USER_ID_COOKIE).is_none() {
+        return Ok(Redirect::to("/login").into_response());
+    }
+    let search = parameters.q.unwrap_or_default().trim().to_string();
+    let status = parameters.status.and_then(|s| SubscriptionStatus::parse(&s).ok());
+    let sort = SortOrder::parse(parameters.sort.as_deref());
+    // A mangled cursor starts over from the first page.
+    let after = parameters.after.as_deref().and_then(PageCursor::parse);
+
+    let mut subscribers = get_subscribers_page(&state.db_pool, &search, status, sort, after)
+        .await
+        .context("Failed to fetch a page of subscribers.")?;
+    let next_page = if subscribers.len() as i64 > PAGE_SIZE {
+        subscribers.truncate(PAGE_SIZE as usize);
+        subscribers.last().map(|last| PageCursor { subscribed_at: last.subscribed_at, id: last.id })
+    } else {
+        None
+    };
+
+    let rows: String = subscribers
+        .iter()
+        .map(|s| {
+            format!(
+                r#"<tr><td><a href="/admin/subscribers/{}">{}</a></td><td>{}</td><td>{}</td><td>{}</td></tr>"#,
+                s.id,
+                encode_minimal(&s.email),
+                encode_minimal(&s.name),
+                s.status,
+                s.subscribed_at.to_rfc3339(),
+            )
+        })
+        .collect();
+    let results_html = if subscribers.is_empty() {
+        "<p>No subscribers match.</p>".to_string()
+    } else {
+        format!(
+            r#"<table>
+        <tr><th>Email</th><th>Name</th><th>Status</th><th>Subscribed at</th></tr>
+        {rows}
+    </table>"#
+        )
+    };
+    let filters = |after: Option<PageCursor>| {
+        let mut query = vec![("q", search.clone()), ("sort", sort.as_str().to_string())];
+        if let Some(status) = &status {
+            query.push(("status", status.as_str().to_string()));
+        }
+        if let Some(after) = after {
+            query.push(("after", after.encode()));
+        }
+        query
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, urlencoding::encode(value)))
+            .collect::<Vec<_>>()
+            .join("&")
+    };
+    let mut pagination_html = String::new();
+    if after.is_some() {
+        pagination_html.push_str(&format!(
+            r#"<a href="/admin/subscribers?{}">First page</a> "#,
+            encode_attribute(&filters(None)),
+        ));
+    }
+    if let Some(next_page) = next_page {
+        pagination_html.push_str(&format!(
+            r#"<a href="/admin/subscribers?{}">Next page</a>"#,
+            encode_attribute(&filters(Some(next_page))),
+        ));
+    }
+    let status_options: String = std::iter::once("")
+        .chain(SubscriptionStatus::ALL.map(|status| status.as_str()))
+        .map(|option| {
+            format!(
+                r#"<option value="{value}"{selected}>{label}</option>"#,
+                value = option,
+                selected = if status.map_or("", |status| status.as_str()) == option { " selected" } else { "" },
+                label = if option.is_empty() { "any" } else { option },
+            )
+        })
+        .collect();
+    let sort_options: String = [SortOrder::NewestFirst, SortOrder::OldestFirst]
+        .iter()
+        .map(|option| {
+            format!(
+                r#"<option value="{value}"{selected}>{label}</option>"#,
+                value = option.as_str(),
+                selected = if *option == sort { " selected" } else { "" },
+                label = match option {
+                    SortOrder::NewestFirst => "newest first",
+                    SortOrder::OldestFirst => "oldest first",
+                },
+            )
+        })
+        .collect();
+
+    let (jar, flash_html) = take_flash(jar);
+    let html = Html(format!(r#"<!doctype html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Subscribers</title>
+</head>
+<body>
+    {flash_html}
+    <form action="/admin/subscribers" method="get">
+        <label>Email or name starts with
+            <input type="search" name="q" value="{search}">
+        </label>
+        <label>Status
+            <select name="status">{status_options}</select>
+        </label>
+        <label>Sort
+            <select name="sort">{sort_options}</select>
+        </label>
+        <button type="submit">Search</button>
+    </form>
+    {results_html}
+    <p>{pagination_html}</p>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        search = encode_attribute(&search),
+    ));
+    Ok((jar, html).into_response())
+}
+
+// `%` and `_` in what was typed are matched literally.
+fn prefix_pattern(search: &str) -> String {
+    let escaped = search
+        .to_lowercase()
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("{}%", escaped)
+}
+
+/// One more than a page, to know whether there is a next one.
+#[tracing::instrument(name = "Fetch a page of subscribers", skip(pool))]
+async fn get_subscribers_page(
+    pool: &PgPool,
+    search: &str,
+    status: Option<SubscriptionStatus>,
+    sort: SortOrder,
+    after: Option<PageCursor>,
+) -> Result<Vec<SubscriberSummary>, sqlx::Error> {
+    let pattern = (!search.is_empty()).then(|| prefix_pattern(search));
+    let after_subscribed_at = after.map(|cursor| cursor.subscribed_at);
+    let after_id = after.map(|cursor| cursor.id);
+    let status = status.map(|status| status.as_str());
+    // One query per direction, so both walk the (subscribed_at, id) index.
+    let rows = match sort {
+        SortOrder::NewestFirst => {
+            sqlx::query_as!(
+                SubscriberSummaryRow,
+                r#"
+                SELECT id, email, name, status, subscribed_at
+                FROM subscriptions
+                WHERE ($1::text IS NULL OR email_canonical LIKE $1 OR lower(name) LIKE $1)
+                    AND ($2::text IS NULL OR status = $2)
+                    AND ($3::timestamptz IS NULL OR (subscribed_at, id) < ($3, $4::uuid))
+                ORDER BY subscribed_at DESC, id DESC
+                LIMIT $5
+                "#,
+                pattern,
+                status,
+                after_subscribed_at,
+                after_id,
+                PAGE_SIZE + 1,
+            )
+            .fetch_all(pool)
+            .await?
+        }
+        SortOrder::OldestFirst => {
+            sqlx::query_as!(
+                SubscriberSummaryRow,
+                r#"
+                SELECT id, email, name, status, subscribed_at
+                FROM subscriptions
+                WHERE ($1::text IS NULL OR email_canonical LIKE $1 OR lower(name) LIKE $1)
+                    AND ($2::text IS NULL OR status = $2)
+                    AND ($3::timestamptz IS NULL OR (subscribed_at, id) > ($3, $4::uuid))
+                ORDER BY subscribed_at, id
+                LIMIT $5
+                "#,
+                pattern,
+                status,
+                after_subscribed_at,
+                after_id,
+                PAGE_SIZE + 1,
+            )
+            .fetch_all(pool)
+            .await?
+        }
+    };
+    rows.into_iter().map(SubscriberSummary::try_from).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{prefix_pattern, PageCursor};
+
+    #[test]
+    fn a_page_cursor_survives_a_round_trip() {
+        let cursor = PageCursor {
+            subscribed_at: "2026-10-18T12:34:56.123456Z".parse().unwrap(),
+            id: uuid::Uuid::new_v4(),
+        };
+
+        let parsed = PageCursor::parse(&cursor.encode()).unwrap();
+
+        assert_eq!(parsed.subscribed_at, cursor.subscribed_at);
+        assert_eq!(parsed.id, cursor.id);
+    }
+
+    #[test]
+    fn a_mangled_page_cursor_is_ignored() {
+        assert!(PageCursor::parse("yesterday_42").is_none());
+    }
+
+    #[test]
+    fn like_wildcards_in_a_search_are_matched_literally() {
+        assert_eq!(prefix_pattern("Le_Guin%"), "le\\_guin\\%%");
+    }
+}
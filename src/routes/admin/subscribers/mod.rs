@@ -0,0 +1,13 @@
+mod actions;
+mod attributes;
+mod detail;
+mod export;
+mod import;
+mod list;
+
+pub use actions::subscriber_action;
+pub use attributes::update_subscriber_attributes;
+pub use detail::*;
+pub use export::*;
+pub use import::*;
+pub use list::*;
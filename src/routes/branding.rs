@@ -0,0 +1,84 @@
+use htmlescape::{encode_attribute, encode_minimal};
+
+use crate::configuration::BrandingSettings;
+
+/// Wraps `content`, which must already be valid HTML, in a page carrying the
+/// configured name, logo and colors.
+pub fn branded_page(branding: &BrandingSettings, title: &str, content: &str) -> String {
+    let logo = match &branding.logo_url {
+        Some(logo_url) => format!(
+            r#"<img src="{}" alt="{}" height="48">"#,
+            encode_attribute(logo_url),
+            encode_attribute(&branding.name),
+        ),
+        None => String::new(),
+    };
+    let support = match &branding.support_email {
+        Some(email) => format!(
+            r#"<footer><p>Questions? Write to <a href="mailto:{}">{}</a>.</p></footer>"#,
+            encode_attribute(email),
+            encode_minimal(email),
+        ),
+        None => String::new(),
+    };
+    format!(
+        r#"<!doctype html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <title>{title} - {name}</title>
+    <style>
+        body {{ font-family: sans-serif; max-width: 36em; margin: 3em auto; padding: 0 1em; }}
+        h1 {{ color: {accent_color}; }}
+        button {{ background: {accent_color}; color: white; border: 0; padding: 0.5em 1em; }}
+    </style>
+</head>
+<body>
+    <header>{logo}<p>{name}</p></header>
+    <h1>{title}</h1>
+    {content}
+    {support}
+</body>
+</html>"#,
+        title = encode_minimal(title),
+        name = encode_minimal(&branding.name),
+        // Attribute encoding keeps the value from closing the style block.
+        accent_color = encode_attribute(&branding.accent_color),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::branded_page;
+    use crate::configuration::BrandingSettings;
+
+    fn branding() -> BrandingSettings {
+        BrandingSettings {
+            name: "Ursula's <Letters>".into(),
+            accent_color: "red}</style><script>".into(),
+            logo_url: Some("https://example.com/logo.png".into()),
+            support_email: Some("help@example.com".into()),
+        }
+    }
+
+    #[test]
+    fn configured_values_are_escaped() {
+        let page = branded_page(&branding(), "Hi", "<p>content</p>");
+        assert!(page.contains("Ursula&#x27;s &lt;Letters&gt;"));
+        assert!(!page.contains("</style><script>"));
+        assert!(page.contains("<p>content</p>"));
+    }
+
+    #[test]
+    fn the_logo_and_support_address_are_optional() {
+        let branding = BrandingSettings {
+            logo_url: None,
+            support_email: None,
+            ..branding()
+        };
+        let page = branded_page(&branding, "Hi", "");
+        assert!(!page.contains("<img"));
+        assert!(!page.contains("mailto:"));
+    }
+}
@@ -0,0 +1,116 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use htmlescape::{encode_attribute, encode_minimal};
+
+use crate::startup::AppState;
+
+// These pages are only routed when running in the `local` environment.
+
+pub async fn mailbox(State(state): State<AppState>) -> Response {
+    let Some(mailbox) = state.email_client.mailbox() else {
+        return (StatusCode::OK, Html(mailbox_page(
+            r#"<p>Email capture is disabled. Set <code>email_client.transport</code>
+            to <code>capture</code> to collect outgoing email here.</p>"#.to_string()
+        ))).into_response();
+    };
+    let messages = mailbox.messages();
+    let rows: String = messages
+        .iter()
+        .map(|message| format!(
+            r#"<tr>
+            <td>{captured_at}</td>
+            <td>{to}</td>
+            <td><a href="/dev/mailbox/{id}">{subject}</a></td>
+            <td>{tag}</td>
+        </tr>"#,
+            captured_at = message.captured_at.format("%Y-%m-%d %H:%M:%S"),
+            to = encode_minimal(&message.to),
+            id = message.id,
+            subject = encode_minimal(&message.subject),
+            tag = encode_minimal(message.tag.as_deref().unwrap_or("")),
+        ))
+        .collect();
+    let content = if messages.is_empty() {
+        "<p>No email has been sent yet.</p>".to_string()
+    } else {
+        format!(r#"<table>
+        <thead><tr><th>Sent at (UTC)</th><th>To</th><th>Subject</th><th>Tag</th></tr></thead>
+        <tbody>{rows}</tbody>
+    </table>
+    <form action="/dev/mailbox/clear" method="post">
+        <button type="submit">Empty mailbox</button>
+    </form>"#)
+    };
+    (StatusCode::OK, Html(mailbox_page(content))).into_response()
+}
+
+pub async fn mailbox_message(
+    State(state): State<AppState>,
+    Path(message_id): Path<uuid::Uuid>,
+) -> Response {
+    let Some(message) = state
+        .email_client
+        .mailbox()
+        .and_then(|mailbox| mailbox.get(message_id))
+    else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let optional_header = |name: &str, value: String| {
+        if value.is_empty() {
+            String::new()
+        } else {
+            format!("<dt>{}</dt><dd>{}</dd>", name, encode_minimal(&value))
+        }
+    };
+    // The HTML body is rendered in a frame so that its markup can't interfere
+    // with the page; links inside it still open in the main window.
+    let srcdoc = format!(r#"<base target="_top">{}"#, message.html_body);
+    let content = format!(r#"<dl>
+        <dt>From</dt><dd>{from}</dd>
+        <dt>To</dt><dd>{to}</dd>
+        {cc}{bcc}{reply_to}{tag}
+        <dt>Subject</dt><dd>{subject}</dd>
+        <dt>Sent at (UTC)</dt><dd>{captured_at}</dd>
+    </dl>
+    <h2>HTML</h2>
+    <iframe srcdoc="{srcdoc}" style="width: 100%; height: 24em;"></iframe>
+    <h2>Text</h2>
+    <pre>{text_body}</pre>
+    <p><a href="/dev/mailbox">&lt;- Back</a></p>"#,
+        from = encode_minimal(&message.from),
+        to = encode_minimal(&message.to),
+        cc = optional_header("Cc", message.cc.join(", ")),
+        bcc = optional_header("Bcc", message.bcc.join(", ")),
+        reply_to = optional_header("Reply-To", message.reply_to.unwrap_or_default()),
+        tag = optional_header("Tag", message.tag.unwrap_or_default()),
+        subject = encode_minimal(&message.subject),
+        captured_at = message.captured_at.format("%Y-%m-%d %H:%M:%S"),
+        srcdoc = encode_attribute(&srcdoc),
+        text_body = encode_minimal(&message.text_body),
+    );
+    (StatusCode::OK, Html(mailbox_page(content))).into_response()
+}
+
+pub async fn clear_mailbox(State(state): State<AppState>) -> Redirect {
+    if let Some(mailbox) = state.email_client.mailbox() {
+        mailbox.clear();
+    }
+    Redirect::to("/dev/mailbox")
+}
+
+fn mailbox_page(content: String) -> String {
+    format!(r#"<!doctype html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Mailbox</title>
+</head>
+<body>
+    <h1>Mailbox</h1>
+    {content}
+</body>
+</html>"#)
+}
@@ -1 +1,34 @@
-pub async fn health_check() {}
+use axum::{extract::State, Json};
+
+use crate::email_client::CircuitState;
+use crate::startup::AppState;
+
+#[derive(serde::Serialize)]
+pub struct HealthReport {
+    status: &'static str,
+    email_client: EmailClientHealth,
+}
+
+#[derive(serde::Serialize)]
+pub struct EmailClientHealth {
+    circuit_breaker: &'static str,
+    consecutive_failures: u32,
+}
+
+// The application keeps serving requests while the email provider is down,
+// so an open circuit degrades the report instead of failing the check.
+pub async fn health_check(State(state): State<AppState>) -> Json<HealthReport> {
+    let circuit_breaker = state.email_client.circuit_breaker();
+    let circuit_state = circuit_breaker.state();
+    let status = match circuit_state {
+        CircuitState::Closed => "ok",
+        _ => "degraded",
+    };
+    Json(HealthReport {
+        status,
+        email_client: EmailClientHealth {
+            circuit_breaker: circuit_state.as_str(),
+            consecutive_failures: circuit_breaker.consecutive_failures(),
+        },
+    })
+}
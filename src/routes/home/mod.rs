@@ -1,8 +1,98 @@
 use axum::{
+    extract::{Query, State},
     response::Html,
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
 };
+use htmlescape::{encode_attribute, encode_minimal};
+use serde_json::{Map, Value};
 
-pub async fn home() -> (StatusCode, Html<&'static str>) {
-    (StatusCode::OK, Html(include_str!("home.html")))
+use crate::attribute_schema::get_attribute_schema;
+use crate::domain::{AttributeKind, AttributeSchema};
+use crate::signup_attribution::SignupAttribution;
+use crate::startup::AppState;
+
+// Every page view gets its own form token, recording when it was served.
+// The campaign parameters and the page that linked here are passed on to
+// `subscribe` with the form.
+pub async fn home(
+    State(state): State<AppState>,
+    query: Option<Query<SignupAttribution>>,
+    headers: HeaderMap,
+) -> Result<(StatusCode, Html<String>), StatusCode> {
+    // A mangled query string is no reason to turn a visitor away.
+    let query = query.map(|Query(query)| query).unwrap_or_default();
+    let page = SignupAttribution { source: Some("home".into()), ..Default::default() };
+    let attribution = SignupAttribution::collect(page, query, &headers, &state.base_url);
+    let schema = get_attribute_schema(&state.db_pool).await.map_err(|e| {
+        tracing::error!(error.cause_chain = ?e, "Failed to fetch the attribute schema.");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let html = include_str!("home.html")
+        .replace("{form_token}", &state.bot_protection.form_token())
+        .replace("{attribution_fields}", &attribution_fields(&attribution))
+        .replace("{attribute_fields}", &attribute_fields(&schema, &serde_json::Map::new()))
+        .replace("{privacy_policy_version}", &encode_minimal(&state.privacy_policy_version));
+    Ok((StatusCode::OK, Html(html)))
+}
+
+fn attribution_fields(attribution: &SignupAttribution) -> String {
+    attribution
+        .fields()
+        .into_iter()
+        .map(|(name, value)| {
+            format!(r#"<input type="hidden" name="{}" value="{}">"#, name, encode_attribute(value))
+        })
+        .collect::<Vec<_>>()
+        .join("\n            ")
+}
+
+/// One input per custom attribute, named the way `subscribe` expects and
+/// filled in with `values`.
+pub fn attribute_fields(schema: &AttributeSchema, values: &Map<String, Value>) -> String {
+    schema
+        .definitions()
+        .iter()
+        .map(|definition| {
+            let name = format!("attributes.{}", definition.name);
+            let required = if definition.required { " required" } else { "" };
+            let value = values.get(&definition.name);
+            let input = match definition.kind {
+                // A checkbox can't tell "no" from "didn't say".
+                AttributeKind::Boolean => {
+                    let option = |option: bool, label: &str| {
+                        let selected = if value == Some(&Value::Bool(option)) { " selected" } else { "" };
+                        format!(r#"<option value="{}"{}>{}</option>"#, option, selected, label)
+                    };
+                    format!(
+                        r#"<select name="{}"{}><option value=""></option>{}{}</select>"#,
+                        encode_attribute(&name),
+                        required,
+                        option(true, "Yes"),
+                        option(false, "No"),
+                    )
+                }
+                kind => {
+                    let input_type = match kind {
+                        AttributeKind::Number => r#"number" step="any"#,
+                        AttributeKind::Date => "date",
+                        _ => "text",
+                    };
+                    let value = match value {
+                        Some(Value::String(text)) => text.clone(),
+                        Some(Value::Number(number)) => number.to_string(),
+                        _ => String::new(),
+                    };
+                    format!(
+                        r#"<input type="{}" name="{}" value="{}"{}>"#,
+                        input_type,
+                        encode_attribute(&name),
+                        encode_attribute(&value),
+                        required,
+                    )
+                }
+            };
+            format!("<label>{}\n                {}\n            </label>", encode_minimal(&definition.name), input)
+        })
+        .collect::<Vec<_>>()
+        .join("\n            ")
 }
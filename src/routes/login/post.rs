@@ -43,12 +43,12 @@ pub async fn login(
         password: login_form.password,
     };
     tracing::Span::current()
-        .record("username", &tracing::field::debug(&credentials.username));
+        .record("username", tracing::field::debug(&credentials.username));
     match validate_credentials(credentials, &state.db_pool).await {
         Ok(user_id) => {
             match insert_user_id(session, user_id) {
                 Ok(_) => {
-                    tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
+                    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
                     (
                         StatusCode::SEE_OTHER,
                         [
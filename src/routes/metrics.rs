@@ -0,0 +1,75 @@
+use axum::{extract::State, response::IntoResponse};
+use http::header::CONTENT_TYPE;
+use std::fmt::Write;
+
+use crate::bot_protection::BotRejection;
+use crate::email_client::CircuitState;
+use crate::startup::AppState;
+
+// Exposed in the Prometheus text format.
+pub async fn metrics(State(state): State<AppState>) -> impl IntoResponse {
+    let circuit_breaker = state.email_client.circuit_breaker();
+    let circuit_state = match circuit_breaker.state() {
+        CircuitState::Closed => 0,
+        CircuitState::Open => 1,
+        CircuitState::HalfOpen => 2,
+    };
+
+    let mut body = String::new();
+    write_metric(
+        &mut body,
+        "email_circuit_breaker_state",
+        "gauge",
+        "State of the email provider circuit breaker (0 = closed, 1 = open, 2 = half-open).",
+        circuit_state,
+    );
+    write_metric(
+        &mut body,
+        "email_circuit_breaker_consecutive_failures",
+        "gauge",
+        "Consecutive failed calls to the email provider.",
+        circuit_breaker.consecutive_failures(),
+    );
+    write_metric(
+        &mut body,
+        "email_circuit_breaker_opened_total",
+        "counter",
+        "Number of times the email provider circuit breaker has opened.",
+        circuit_breaker.times_opened(),
+    );
+    write_metric(
+        &mut body,
+        "email_circuit_breaker_rejected_total",
+        "counter",
+        "Email sends rejected without calling the provider because the circuit was open.",
+        circuit_breaker.rejected_calls(),
+    );
+    writeln!(
+        body,
+        "# HELP subscribe_bot_rejections_total Subscriptions rejected as automated, by reason."
+    )
+    .unwrap();
+    writeln!(body, "# TYPE subscribe_bot_rejections_total counter").unwrap();
+    for rejection in BotRejection::ALL {
+        writeln!(
+            body,
+            r#"subscribe_bot_rejections_total{{reason="{}"}} {}"#,
+            rejection.as_str(),
+            state.bot_protection.rejections(rejection),
+        )
+        .unwrap();
+    }
+    ([(CONTENT_TYPE, "text/plain; version=0.0.4")], body)
+}
+
+pub(crate) fn write_metric(
+    body: &mut String,
+    name: &str,
+    kind: &str,
+    help: &str,
+    value: impl std::fmt::Display,
+) {
+    writeln!(body, "# HELP {} {}", name, help).unwrap();
+    writeln!(body, "# TYPE {} {}", name, kind).unwrap();
+    writeln!(body, "{} {}", name, value).unwrap();
+}
@@ -1,15 +1,31 @@
+mod branding;
+mod client_info;
 mod health_check;
+mod metrics;
 mod subscriptions;
+mod subscriptions_api;
 mod subscriptions_confirm;
+mod subscriptions_resend;
+mod subscriptions_preferences;
+mod subscriptions_data;
 mod newsletters;
 mod home;
 mod login;
 mod admin;
+mod dev;
 
+pub use branding::*;
+pub use client_info::*;
 pub use health_check::*;
+pub use metrics::*;
 pub use subscriptions::*;
+pub use subscriptions_api::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_resend::*;
+pub use subscriptions_preferences::*;
+pub use subscriptions_data::*;
 pub use newsletters::*;
 pub use home::*;
 pub use login::*;
 pub use admin::*;
+pub use dev::*;
@@ -8,14 +8,31 @@ use sqlx::PgPool;
 use anyhow::Context;
 use secrecy::Secret;
 
-use crate::{routes::error_chain_fmt, domain::SubscriberEmail};
+use crate::{routes::{error_chain_fmt, preferences_link, suppress_subscriber}, domain::{DeliveryFrequency, SubscriberEmail, SubscriptionStatus}};
 use crate::startup::AppState;
+use crate::email_delivery::{record_email_delivery, EmailKind};
+use crate::email_client::EmailMessage;
 use crate::authentication::{validate_credentials, AuthError, Credentials};
+use crate::attribute_schema::get_attribute_schema;
+use crate::domain::AttributeSchema;
+use crate::newsletter_template::{NewsletterTemplate, Recipient};
 
 #[derive(serde::Deserialize)]
 pub struct BodyData {
     title: String,
     content: Content,
+    /// Only subscribers matching every condition get the issue.
+    #[serde(default)]
+    segment: Vec<SegmentCondition>,
+    /// One of the configured topics, for the subscribers who picked it. An
+    /// issue without one is for everybody.
+    topic: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct SegmentCondition {
+    attribute: String,
+    equals: serde_json::Value,
 }
 
 #[derive(serde::Deserialize)]
@@ -25,11 +42,16 @@ pub struct Content {
 }
 
 struct ConfirmedSubscriber {
+    id: uuid::Uuid,
     email: SubscriberEmail,
+    name: String,
+    attributes: serde_json::Map<String, serde_json::Value>,
 }
 
 #[derive(thiserror::Error)]
 pub enum PublishError {
+    #[error("{0}")]
+    ValidationError(String),
     #[error("Authentication failed")]
     AuthError(#[source] anyhow::Error),
     #[error(transparent)]
@@ -45,6 +67,7 @@ impl std::fmt::Debug for PublishError {
 impl IntoResponse for PublishError {
     fn into_response(self) -> Response {
         match self {
+            Self::ValidationError(message) => (StatusCode::BAD_REQUEST, message).into_response(),
             Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
             Self::AuthError(_) => {
                 (
@@ -72,7 +95,7 @@ pub async fn publish_newsletter(
     let credentials = basic_authentication(&headers)
         .map_err(PublishError::AuthError)?;
     tracing::Span::current()
-        .record("username", &tracing::field::display(&credentials.username));
+        .record("username", tracing::field::display(&credentials.username));
     let user_id = validate_credentials(credentials, &state.db_pool)
         .await
         .map_err(|e| match e {
@@ -80,20 +103,88 @@ pub async fn publish_newsletter(
             AuthError::UnexpectedError(_) => PublishError::UnexpectedError(e.into()),
         })?;
     tracing::Span::current()
-        .record("user_id", &tracing::field::display(&user_id));
-    let subscribers = get_confirmed_subscribers(&state.db_pool).await?;
+        .record("user_id", tracing::field::display(&user_id));
+    // Mistakes in the templates or the segment are caught before anything
+    // is stored or sent.
+    let schema = get_attribute_schema(&state.db_pool)
+        .await
+        .context("Failed to fetch the attribute schema.")?;
+    let parse = |template: &str| NewsletterTemplate::parse(template, &schema).map_err(PublishError::ValidationError);
+    let title = parse(&body.title)?;
+    let html = parse(&body.content.html)?;
+    let text = parse(&body.content.text)?;
+    let segment = segment_filter(&body.segment, &schema).map_err(PublishError::ValidationError)?;
+    if let Some(topic) = &body.topic {
+        if !state.topics.iter().any(|t| &t.id == topic) {
+            return Err(PublishError::ValidationError(format!("{} is not a topic", topic)));
+        }
+    }
+
+    let issue_id = insert_newsletter_issue(&state.db_pool, &body, &segment)
+        .await
+        .context("Failed to store newsletter issue details")?;
+    let subscribers = get_confirmed_subscribers(&state.db_pool, &segment, body.topic.as_deref()).await?;
     for subscriber in subscribers {
         match subscriber {
             Ok(subscriber) => {
-                state.email_client
-                    .send_email(
-                        &subscriber.email,
-                        &body.title,
-                        &body.content.text,
-                        &body.content.html,
-                    )
-                    .await
-                    .with_context(|| format!("Failed to send email to {}", &subscriber.email))?;
+                // Every issue carries the way out, personal to its recipient.
+                let preferences_link = preferences_link(&state.base_url, &state.secret, subscriber.id);
+                let recipient = Recipient {
+                    name: &subscriber.name,
+                    email: subscriber.email.as_ref(),
+                    attributes: &subscriber.attributes,
+                };
+                let message = EmailMessage::builder(
+                    subscriber.email.clone(),
+                    title.render(&recipient, str::to_string),
+                    format!(
+                        "{}<p><a href=\"{}\">Manage your preferences or unsubscribe</a></p>",
+                        html.render(&recipient, htmlescape::encode_minimal),
+                        preferences_link,
+                    ),
+                    format!(
+                        "{}\n\nManage your preferences or unsubscribe: {}",
+                        text.render(&recipient, str::to_string),
+                        preferences_link,
+                    ),
+                )
+                .tag("newsletter")
+                .metadata("newsletter_issue_id", issue_id.to_string())
+                .build()
+                .context("Failed to build the newsletter email")?;
+                let outcome = state.email_client.send(&message).await;
+                match outcome {
+                    Ok(receipt) => {
+                        if let Err(e) = record_email_delivery(
+                            &state.db_pool,
+                            subscriber.id,
+                            EmailKind::Newsletter(issue_id),
+                            &receipt,
+                        )
+                        .await
+                        {
+                            tracing::error!(
+                                error.cause_chain = ?e,
+                                "Failed to record a newsletter delivery.",
+                            );
+                        }
+                    }
+                    Err(e) if e.is_undeliverable_recipient() => {
+                        tracing::warn!(
+                            error.cause_chain = ?e,
+                            "Suppressing a confirmed subscriber. \
+                            The email provider reported them as undeliverable.",
+                        );
+                        suppress_subscriber(&state.db_pool, &subscriber.email)
+                            .await
+                            .context("Failed to suppress an undeliverable subscriber.")?;
+                    }
+                    Err(e) => {
+                        return Err(anyhow::Error::new(e)
+                            .context(format!("Failed to send email to {}", &subscriber.email))
+                            .into());
+                    }
+                }
             }
             Err(e) => {
                 tracing::warn!(
@@ -137,28 +228,94 @@ fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Erro
     })
 }
 
+/// The attribute values a subscriber must have to be in the segment, as a
+/// JSON object to match with `@>`.
+fn segment_filter(
+    conditions: &[SegmentCondition],
+    schema: &AttributeSchema,
+) -> Result<serde_json::Value, String> {
+    let mut filter = serde_json::Map::new();
+    for condition in conditions {
+        let value = schema
+            .parse_json_value(&condition.attribute, &condition.equals)
+            .map_err(|e| format!("The segment is invalid: {}", e))?;
+        if filter.insert(condition.attribute.clone(), value).is_some() {
+            return Err(format!("The segment has more than one condition on {}", condition.attribute));
+        }
+    }
+    Ok(serde_json::Value::Object(filter))
+}
+
+/// The confirmed subscribers who get the issue as soon as it is out: the
+/// weekly and monthly ones get it later in a digest. Without a topic of their
+/// own, they get issues about any topic.
 #[tracing::instrument(
     name = "Get confirmed subscribers",
     skip(pool),
 )]
 async fn get_confirmed_subscribers(
     pool: &PgPool,
+    segment: &serde_json::Value,
+    topic: Option<&str>,
 ) -> Result<Vec<Result<ConfirmedSubscriber, anyhow::Error>>, anyhow::Error> {
     let confirmed_subscribers = sqlx::query!(
         r#"
-        SELECT email 
+        SELECT id, email, name, attributes
         FROM subscriptions
-        WHERE status = 'confirmed'
+        WHERE status = $2 AND attributes @> $1
+            AND delivery_frequency = $3
+            AND ($4::text IS NULL OR cardinality(topics) = 0 OR $4 = ANY(topics))
         "#,
+        segment,
+        SubscriptionStatus::Confirmed.as_str(),
+        DeliveryFrequency::Immediately.as_str(),
+        topic,
     )
     .fetch_all(pool)
     .await?
     .into_iter()
     .map(|row| match SubscriberEmail::parse(row.email) {
-        Ok(email) => Ok(ConfirmedSubscriber { email }),
+        Ok(email) => Ok(ConfirmedSubscriber {
+            id: row.id,
+            email,
+            name: row.name,
+            attributes: match row.attributes {
+                serde_json::Value::Object(attributes) => attributes,
+                _ => serde_json::Map::new(),
+            },
+        }),
         Err(error) => Err(anyhow::anyhow!(error)),
     })
     .collect();
 
     Ok(confirmed_subscribers)
 }
+
+#[tracing::instrument(
+    name = "Store a newsletter issue",
+    skip(pool, body, segment),
+)]
+async fn insert_newsletter_issue(
+    pool: &PgPool,
+    body: &BodyData,
+    segment: &serde_json::Value,
+) -> Result<uuid::Uuid, sqlx::Error> {
+    let newsletter_issue_id = uuid::Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id, title, text_content, html_content, published_at, topic, segment
+        )
+        VALUES ($1, $2, $3, $4, now(), $5, $6)
+        "#,
+        newsletter_issue_id,
+        body.title,
+        body.content.text,
+        body.content.html,
+        body.topic,
+        segment,
+    )
+    .execute(pool)
+    .await?;
+    Ok(newsletter_issue_id)
+}
@@ -1,22 +1,33 @@
-use axum::{extract::State, Form};
+use std::collections::HashMap;
+
+use axum::{extract::{Query, State}, Form};
 use axum::response::{IntoResponse, Response};
-use http::StatusCode;
+use http::{HeaderMap, StatusCode};
 use serde::Deserialize;
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
-use sqlx::{Transaction, Postgres};
+use sha2::{Digest, Sha256};
+use sqlx::{PgExecutor, Transaction, Postgres};
 use anyhow::Context;
 
 use crate::{
     startup::AppState, 
-    domain::{NewSubscriber, SubscriberName, SubscriberEmail},
-    email_client::EmailClient,
+    attribute_schema::get_attribute_schema,
+    domain::{AttributeSchema, NewSubscriber, SubscriberName, SubscriberEmail, SubscriptionStatus},
+    email_client::{EmailClient, EmailMessage, SendEmailError, SendReceipt},
+    consent::{record_consent, ConsentEvent},
+    routes::ClientInfo,
+    bot_protection::BotRejection,
+    signup_attribution::SignupAttribution,
+    subscriber_email_queue::{queue_emails, QueuedEmail},
 };
 
 #[derive(thiserror::Error)]
 pub enum SubscribeError {
     #[error("{0}")]
     ValidationError(String),
+    #[error("The subscription looked automated ({}).", .0.as_str())]
+    BotRejected(BotRejection),
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -31,10 +42,10 @@ pub fn error_chain_fmt(
     e: &impl std::error::Error,
     f: &mut std::fmt::Formatter<'_>,
 ) -> std::fmt::Result {
-    write!(f, "{}\n", e)?;
+    writeln!(f, "{}", e)?;
     let mut source = e.source();
     while let Some(e) = source {
-        write!(f, "Caused by: {}\n", e)?;
+        writeln!(f, "Caused by: {}", e)?;
         source = e.source();
     }
     Ok(())
@@ -44,6 +55,8 @@ impl SubscribeError {
     fn status_code(&self) -> StatusCode {
         match self {
             Self::ValidationError(_) => StatusCode::BAD_REQUEST,
+            Self::BotRejected(rejection) if rejection.is_rate_limit() => StatusCode::TOO_MANY_REQUESTS,
+            Self::BotRejected(_) => StatusCode::BAD_REQUEST,
             Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -58,13 +71,16 @@ impl From<String> for SubscribeError {
 impl IntoResponse for SubscribeError {
     fn into_response(self) -> Response {
         match self {
-            Self::ValidationError(_) => self.status_code().into_response(),
+            // The message says what to fix, e.g. which domain isn't accepted.
+            Self::ValidationError(ref message) => (self.status_code(), message.clone()).into_response(),
+            // Deliberately vague, there is nothing a person needs to fix.
+            Self::BotRejected(_) => self.status_code().into_response(),
             Self::UnexpectedError(_) => self.status_code().into_response(),
         }
     }
 }
 
-fn generate_subscription_token() -> String {
+pub fn generate_subscription_token() -> String {
     thread_rng()
         .sample_iter(&Alphanumeric)
         .take(25)
@@ -72,10 +88,27 @@ fn generate_subscription_token() -> String {
         .collect()
 }
 
+/// What is stored of a subscription token: its SHA-256 hash, hex encoded. The
+/// token itself is only ever in the confirmation email.
+pub fn hash_subscription_token(subscription_token: &str) -> String {
+    hex::encode(Sha256::digest(subscription_token.as_bytes()))
+}
+
 #[derive(Deserialize)]
 pub struct FormData {
     name: String,
     email: String,
+    /// Which form was filled in and how the subscriber got to it. `source`
+    /// also goes in the consent log.
+    #[serde(flatten)]
+    attribution: SignupAttribution,
+    /// The honeypot, see `home.html`.
+    website: Option<String>,
+    form_token: Option<String>,
+    /// Custom attributes come in as `attributes.<name>`, everything else
+    /// is ignored.
+    #[serde(flatten)]
+    other_fields: HashMap<String, String>,
 }
 
 // #[tracing::instrument] creates a span at the beginning of the function invocation and automat-
@@ -85,7 +118,7 @@ pub struct FormData {
 // It creates a span for the request and adds the form data as attributes
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form_data, state),
+    skip(form_data, state, query, headers),
     fields(
         name = %form_data.name,
         email = %form_data.email,
@@ -93,48 +126,125 @@ pub struct FormData {
 )]
 pub async fn subscribe(
     State(state): State<AppState>,
+    client: ClientInfo,
+    query: Option<Query<SignupAttribution>>,
+    headers: HeaderMap,
     Form(form_data): Form<FormData>,
 ) -> Result<StatusCode, SubscribeError> {
-    let new_subscriber = form_data.try_into()?;
+    let bot_protection = &state.bot_protection;
+    bot_protection
+        .check_form(form_data.website.as_deref(), form_data.form_token.as_deref())
+        .and_then(|()| bot_protection.check_ip(client.ip))
+        .map_err(|rejection| reject_bot(&state, rejection))?;
+    let query = query.map(|Query(query)| query).unwrap_or_default();
+    let attribution =
+        SignupAttribution::collect(form_data.attribution.clone(), query, &headers, &state.base_url);
+    let schema = get_attribute_schema(&state.db_pool)
+        .await
+        .context("Failed to fetch the attribute schema.")?;
+    let new_subscriber = parse_form_data(form_data, &schema)?;
+    state.domain_blocklist.check(&new_subscriber.email)?;
+    register_subscriber(&state, &client, &new_subscriber, &attribution).await?;
+    Ok(StatusCode::OK)
+}
+
+/// Everything that happens to a validated subscriber, whichever route it came
+/// through: the subscription, its consent and the email that follows are
+/// stored in one transaction. The email itself is sent by the
+/// [`SubscriberEmailWorker`](crate::subscriber_email_queue::SubscriberEmailWorker).
+///
+/// Someone who is already on the list gets the same response, after the same
+/// amount of work, as a new subscriber, so callers can't use it to find out
+/// who is subscribed. A confirmed subscriber is emailed that they already
+/// are, someone who unsubscribed becomes pending again and gets a new
+/// confirmation email. Their signup keeps the attribution of the first
+/// attempt.
+pub(crate) async fn register_subscriber(
+    state: &AppState,
+    client: &ClientInfo,
+    new_subscriber: &NewSubscriber,
+    attribution: &SignupAttribution,
+) -> Result<(), SubscribeError> {
+    state.bot_protection
+        .check_domain(&new_subscriber.email)
+        .map_err(|rejection| reject_bot(state, rejection))?;
     let mut transaction = state.db_pool.begin().await.context("Failed to acquire a database connection.")?;
-    let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber).await
-        .context("Failed to insert new subscriber.")?;
-    let subscription_token = generate_subscription_token();
-    store_token(&mut transaction, subscriber_id, &subscription_token).await
-        .context("Failed to store the confirmation token for a new subscriber")?;
+    // Whether they (still) want to subscribe, rather than being told they
+    // already are or being left alone.
+    let (subscriber_id, email, subscribing) =
+        match insert_subscriber(&mut transaction, new_subscriber, attribution).await
+            .context("Failed to insert new subscriber.")?
+        {
+            Some(subscriber_id) => (subscriber_id, QueuedEmail::Confirmation, true),
+            None => {
+                let existing = get_subscriber_by_email(&mut transaction, &new_subscriber.email).await
+                    .context("Failed to fetch an existing subscriber.")?
+                    .context("The existing subscriber is gone.")?;
+                tracing::info!(status = %existing.status, "The email address is already on the list.");
+                match existing.status {
+                    SubscriptionStatus::Confirmed => (existing.id, QueuedEmail::AlreadySubscribed, false),
+                    SubscriptionStatus::PendingConfirmation => (existing.id, QueuedEmail::Confirmation, true),
+                    SubscriptionStatus::Unsubscribed => {
+                        set_subscription_status(&mut transaction, existing.id, SubscriptionStatus::PendingConfirmation)
+                            .await
+                            .context("Failed to resubscribe an unsubscribed subscriber.")?;
+                        (existing.id, QueuedEmail::Confirmation, true)
+                    }
+                    // Suppressed addresses are never emailed again, the
+                    // worker drops it.
+                    SubscriptionStatus::Suppressed => {
+                        (existing.id, QueuedEmail::Confirmation, false)
+                    }
+                }
+            }
+        };
+    if subscribing {
+        record_consent(
+            &mut transaction,
+            subscriber_id,
+            ConsentEvent::Subscribed,
+            client,
+            attribution.source.as_deref(),
+            &state.privacy_policy_version,
+        ).await
+            .context("Failed to record the consent of a subscriber.")?;
+    }
+    queue_emails(&mut transaction, &[subscriber_id], email).await
+        .context("Failed to queue an email to a subscriber.")?;
     transaction.commit().await.context("Failed to commit SQL transaction to store a new subscriber.")?;
-
-    send_confirmation_email(
-        new_subscriber, 
-        &state.email_client, 
-        &state.base_url,
-        &subscription_token,
-    )
-    .await
-    .context("Failed to send a confirmation email.")?;
-    Ok(StatusCode::OK)
+    Ok(())
 }
 
-impl TryFrom<FormData> for NewSubscriber {
-    type Error = String;
+pub(crate) fn reject_bot(state: &AppState, rejection: BotRejection) -> SubscribeError {
+    state.bot_protection.record(rejection);
+    tracing::warn!(reason = rejection.as_str(), "Rejected a subscription that looked automated.");
+    SubscribeError::BotRejected(rejection)
+}
 
-    fn try_from(value: FormData) -> Result<Self, Self::Error> {
-        let name = SubscriberName::parse(value.name)?;
-        let email = SubscriberEmail::parse(value.email)?;
-        Ok(Self { name, email })
-    }
+fn parse_form_data(form_data: FormData, schema: &AttributeSchema) -> Result<NewSubscriber, String> {
+    let name = SubscriberName::parse(form_data.name)?;
+    let email = SubscriberEmail::parse(form_data.email)?;
+    let attributes = form_data
+        .other_fields
+        .into_iter()
+        .filter_map(|(field, value)| Some((field.strip_prefix("attributes.")?.to_string(), value)))
+        .collect();
+    let attributes = schema.parse_form(&attributes).map_err(|errors| {
+        errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(", ")
+    })?;
+    Ok(NewSubscriber { name, email, attributes })
 }
 
 #[tracing::instrument(
     name = "Send a confirmation email to a new subscriber",
-    skip(new_subscriber, email_client, base_url)
+    skip(email, email_client, base_url)
 )]
 pub async fn send_confirmation_email(
-    new_subscriber: NewSubscriber,
+    email: &SubscriberEmail,
     email_client: &EmailClient,
     base_url: &str,
     subscription_token: &str,
-) -> Result<(), reqwest::Error> {
+) -> Result<SendReceipt, SendEmailError> {
     let confirmation_link = format!(
         "{}/subscriptions/confirm?subscription_token={}",
         base_url,
@@ -149,39 +259,152 @@ pub async fn send_confirmation_email(
         Click <a href=\"{}\">here</a> to confirm your subscription.",
         confirmation_link
     );
-    email_client
-        .send_email(
-            &new_subscriber.email,
-            "Welcome!",
-            &html_body,
-            &plain_body,
-        )
-        .await
+    let message = EmailMessage::builder(
+        email.clone(),
+        "Welcome!",
+        html_body,
+        plain_body,
+    )
+    .tag("confirmation")
+    .build()
+    .expect("The confirmation email is always valid");
+    email_client.send(&message).await
 }
 
 
+#[tracing::instrument(
+    name = "Tell a subscriber they already are one",
+    skip(email, email_client, preferences_link)
+)]
+pub async fn send_already_subscribed_email(
+    email: &SubscriberEmail,
+    email_client: &EmailClient,
+    preferences_link: &str,
+) -> Result<SendReceipt, SendEmailError> {
+    let plain_body = format!(
+        "Someone, hopefully you, asked to subscribe this address to our newsletter again.\n\
+        You are already subscribed, there is nothing else to do.\n\
+        Visit {} to manage your preferences or unsubscribe.",
+        preferences_link
+    );
+    let html_body = format!(
+        "Someone, hopefully you, asked to subscribe this address to our newsletter again.<br />\
+        You are already subscribed, there is nothing else to do.<br />\
+        Click <a href=\"{}\">here</a> to manage your preferences or unsubscribe.",
+        preferences_link
+    );
+    let message = EmailMessage::builder(
+        email.clone(),
+        "You are already subscribed",
+        html_body,
+        plain_body,
+    )
+    .tag("already_subscribed")
+    .build()
+    .expect("The already subscribed email is always valid");
+    email_client.send(&message).await
+}
+
 // insert a new subscriber into the database.
 // this procedural macro instuments the function insert_subscriber
 // with a span that has the name "insert_subscriber"
+// Returns None when the email address is already on the list.
 #[tracing::instrument(name = "Saving new subscriber details in the database", skip(new_subscriber, transaction))]
 pub async fn insert_subscriber(
     transaction: &mut Transaction<'_, Postgres>,
-    new_subscriber: &NewSubscriber
-) -> Result<uuid::Uuid, sqlx::Error> {
+    new_subscriber: &NewSubscriber,
+    attribution: &SignupAttribution,
+) -> Result<Option<uuid::Uuid>, sqlx::Error> {
     let subscriber_id = uuid::Uuid::new_v4();
-    sqlx::query!(
+    let inserted = sqlx::query!(
         r#"
-        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-        VALUES ($1, $2, $3, $4, 'pending_confirmation')
+        INSERT INTO subscriptions (
+            id, email, name, subscribed_at, status, attributes, signup_source, referrer,
+            utm_source, utm_medium, utm_campaign, utm_term, utm_content
+        )
+        VALUES ($1, $2, $3, $4, $13, $5, $6, $7, $8, $9, $10, $11, $12)
+        ON CONFLICT (email_canonical) DO NOTHING
+        RETURNING id
         "#,
         subscriber_id,
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
         chrono::Utc::now(),
+        new_subscriber.attributes.to_json(),
+        attribution.source,
+        attribution.referrer,
+        attribution.utm_source,
+        attribution.utm_medium,
+        attribution.utm_campaign,
+        attribution.utm_term,
+        attribution.utm_content,
+        SubscriptionStatus::PendingConfirmation.as_str(),
     )
-    .execute(transaction)
+    .fetch_optional(transaction)
     .await?;
-    Ok(subscriber_id)
+    Ok(inserted.map(|r| r.id))
+}
+
+pub struct ExistingSubscriber {
+    pub id: uuid::Uuid,
+    pub status: SubscriptionStatus,
+}
+
+#[tracing::instrument(
+    name = "Fetch an existing subscriber by email",
+    skip(transaction, email),
+)]
+pub async fn get_subscriber_by_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &SubscriberEmail,
+) -> Result<Option<ExistingSubscriber>, sqlx::Error> {
+    // Lock the row so that concurrent submissions don't both hand out a token.
+    let subscriber = sqlx::query!(
+        r#"
+        SELECT id, status
+        FROM subscriptions
+        WHERE email_canonical = lower($1)
+        FOR UPDATE
+        "#,
+        email.as_ref(),
+    )
+    .fetch_optional(transaction)
+    .await?;
+    subscriber
+        .map(|row| Ok(ExistingSubscriber { id: row.id, status: decode_status(&row.status)? }))
+        .transpose()
+}
+
+/// For statuses read back from the database, which only holds valid ones.
+pub(crate) fn decode_status(status: &str) -> Result<SubscriptionStatus, sqlx::Error> {
+    SubscriptionStatus::parse(status).map_err(|e| sqlx::Error::Decode(e.into()))
+}
+
+// As bound to `status = ANY(...)`.
+fn preceding_statuses(status: SubscriptionStatus) -> Vec<String> {
+    SubscriptionStatus::preceding(status)
+        .iter()
+        .map(|status| status.as_str().to_owned())
+        .collect()
+}
+
+/// Moves a subscriber to `status`, provided their current status allows it
+/// (see [`SubscriptionStatus::transition`]). Returns whether it did.
+#[tracing::instrument(name = "Change the status of a subscriber", skip(executor))]
+pub async fn set_subscription_status(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: uuid::Uuid,
+    status: SubscriptionStatus,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"UPDATE subscriptions SET status = $2 WHERE id = $1 AND status = ANY($3)"#,
+        subscriber_id,
+        status.as_str(),
+        &preceding_statuses(status),
+    )
+    .execute(executor)
+    .await?;
+    Ok(result.rows_affected() > 0)
 }
 
 #[tracing::instrument(
@@ -192,16 +415,46 @@ pub async fn store_token(
     transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: uuid::Uuid,
     subscription_token: &str,
+    ttl: chrono::Duration,
 ) -> Result<(), sqlx::Error> {
+    let issued_at = chrono::Utc::now();
     sqlx::query!(
         r#"
-        INSERT INTO subscription_tokens (subscription_token, subscriber_id)
-        VALUES ($1, $2)
+        INSERT INTO subscription_tokens (token_hash, subscriber_id, issued_at, expires_at)
+        VALUES ($1, $2, $3, $4)
         "#,
-        subscription_token,
-        subscriber_id
+        hash_subscription_token(subscription_token),
+        subscriber_id,
+        issued_at,
+        issued_at + ttl,
     )
     .execute(transaction)
     .await?;
     Ok(())
 }
+
+// The email provider told us this address can never receive our email (hard
+// bounce, spam complaint, ...). Stop sending to it.
+#[tracing::instrument(
+    name = "Suppress an undeliverable subscriber",
+    skip(pool, email),
+    fields(email = %email)
+)]
+pub async fn suppress_subscriber(
+    pool: &sqlx::PgPool,
+    email: &SubscriberEmail,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = $2
+        WHERE email_canonical = lower($1) AND status = ANY($3)
+        "#,
+        email.as_ref(),
+        SubscriptionStatus::Suppressed.as_str(),
+        &preceding_statuses(SubscriptionStatus::Suppressed),
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
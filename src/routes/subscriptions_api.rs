@@ -0,0 +1,173 @@
+use axum::{
+    extract::{rejection::JsonRejection, Query, State},
+    response::{IntoResponse, Response},
+    Json,
+};
+use anyhow::Context;
+use http::{HeaderMap, StatusCode};
+
+use crate::{
+    attribute_schema::get_attribute_schema,
+    bot_protection::BotRejection,
+    domain::{AttributeSchema, NewSubscriber, SubscriberEmail, SubscriberName},
+    routes::{error_chain_fmt, register_subscriber, reject_bot, ClientInfo, SubscribeError},
+    signup_attribution::SignupAttribution,
+    startup::AppState,
+};
+
+#[derive(serde::Deserialize)]
+pub struct SubscribeRequest {
+    // Missing fields are reported like empty ones, next to the other field errors.
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    email: String,
+    /// Which part of the app the request came from, for the consent log,
+    /// and how the subscriber got there.
+    #[serde(flatten)]
+    attribution: SignupAttribution,
+    #[serde(default)]
+    attributes: serde_json::Map<String, serde_json::Value>,
+    /// The honeypot, for the app to render as a field people don't see.
+    website: Option<String>,
+    /// From `GET /api/subscriptions/form_token`, when the form is shown.
+    form_token: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct FieldError {
+    field: String,
+    message: String,
+}
+
+#[derive(serde::Serialize)]
+struct ErrorBody<'a> {
+    message: &'a str,
+    errors: &'a [FieldError],
+}
+
+#[derive(thiserror::Error)]
+pub enum ApiSubscribeError {
+    #[error("The request body is not a valid subscription request.")]
+    MalformedBody(#[source] JsonRejection),
+    #[error("The subscription request is invalid.")]
+    ValidationError(Vec<FieldError>),
+    #[error(transparent)]
+    SubscribeError(#[from] SubscribeError),
+}
+
+impl std::fmt::Debug for ApiSubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for ApiSubscribeError {
+    fn into_response(self) -> Response {
+        let (status, message) = match &self {
+            Self::MalformedBody(rejection) => (rejection.status(), rejection.body_text()),
+            Self::ValidationError(_) => (StatusCode::BAD_REQUEST, self.to_string()),
+            Self::SubscribeError(e) => match e {
+                SubscribeError::ValidationError(message) => (StatusCode::BAD_REQUEST, message.clone()),
+                SubscribeError::BotRejected(rejection) if rejection.is_rate_limit() => {
+                    (StatusCode::TOO_MANY_REQUESTS, "Too many subscription requests, try again later.".into())
+                }
+                // The app may have kept its form open for too long.
+                SubscribeError::BotRejected(BotRejection::InvalidFormToken | BotRejection::ExpiredFormToken) => (
+                    StatusCode::BAD_REQUEST,
+                    "The form token is missing or has expired, fetch a new one.".into(),
+                ),
+                SubscribeError::BotRejected(_) => (StatusCode::BAD_REQUEST, "The subscription was rejected.".into()),
+                SubscribeError::UnexpectedError(_) => {
+                    (StatusCode::INTERNAL_SERVER_ERROR, "Something went wrong, try again later.".into())
+                }
+            },
+        };
+        let errors = match &self {
+            Self::ValidationError(errors) => &errors[..],
+            _ => &[],
+        };
+        (status, Json(ErrorBody { message: &message, errors })).into_response()
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct SubscribeResponse {
+    message: &'static str,
+}
+
+#[derive(serde::Serialize)]
+pub struct FormTokenResponse {
+    form_token: String,
+}
+
+/// What the home page puts in its form, for the single-page app to fetch
+/// when it shows its own.
+pub async fn api_form_token(State(state): State<AppState>) -> Json<FormTokenResponse> {
+    Json(FormTokenResponse {
+        form_token: state.bot_protection.form_token(),
+    })
+}
+
+// The JSON counterpart of `subscribe`, for the single-page app. It goes
+// through the same bot checks as the form: the form token comes from
+// `api_form_token`, and the honeypot is a field the app hides.
+#[tracing::instrument(
+    name = "Adding a new subscriber through the API",
+    skip(payload, state, client, query, headers),
+)]
+pub async fn subscribe_api(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    query: Option<Query<SignupAttribution>>,
+    headers: HeaderMap,
+    payload: Result<Json<SubscribeRequest>, JsonRejection>,
+) -> Result<Json<SubscribeResponse>, ApiSubscribeError> {
+    let Json(request) = payload.map_err(ApiSubscribeError::MalformedBody)?;
+    let bot_protection = &state.bot_protection;
+    bot_protection
+        .check_form(request.website.as_deref(), request.form_token.as_deref())
+        .and_then(|()| bot_protection.check_ip(client.ip))
+        .map_err(|rejection| reject_bot(&state, rejection))?;
+    let schema = get_attribute_schema(&state.db_pool)
+        .await
+        .context("Failed to fetch the attribute schema.")
+        .map_err(SubscribeError::UnexpectedError)?;
+    let new_subscriber = parse_request(&state, &schema, &request)?;
+    let query = query.map(|Query(query)| query).unwrap_or_default();
+    let mut attribution = SignupAttribution::collect(request.attribution, query, &headers, &state.base_url);
+    attribution.source.get_or_insert_with(|| "api".into());
+    register_subscriber(&state, &client, &new_subscriber, &attribution).await?;
+    Ok(Json(SubscribeResponse {
+        message: "Check your inbox to confirm your subscription.",
+    }))
+}
+
+// Unlike the form, every invalid field is reported at once.
+fn parse_request(
+    state: &AppState,
+    schema: &AttributeSchema,
+    request: &SubscribeRequest,
+) -> Result<NewSubscriber, ApiSubscribeError> {
+    let mut errors = Vec::new();
+    let name = SubscriberName::parse(request.name.clone())
+        .map_err(|message| errors.push(FieldError { field: "name".into(), message }))
+        .ok();
+    let email = SubscriberEmail::parse(request.email.clone())
+        .and_then(|email| state.domain_blocklist.check(&email).map(|()| email))
+        .map_err(|message| errors.push(FieldError { field: "email".into(), message }))
+        .ok();
+    let attributes = schema
+        .parse_json(&request.attributes)
+        .map_err(|attribute_errors| {
+            errors.extend(attribute_errors.into_iter().map(|e| FieldError {
+                field: format!("attributes.{}", e.attribute),
+                message: e.message,
+            }))
+        })
+        .ok();
+    match (name, email, attributes) {
+        (Some(name), Some(email), Some(attributes)) => Ok(NewSubscriber { name, email, attributes }),
+        _ => Err(ApiSubscribeError::ValidationError(errors)),
+    }
+}
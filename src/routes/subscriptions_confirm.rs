@@ -1,50 +1,183 @@
 use axum::extract::{Query, State};
+use axum::response::{Html, IntoResponse, Response};
+use axum::Json;
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, Postgres, Transaction};
+use crate::automation::enroll_subscriber;
+use crate::consent::{record_consent, ConsentEvent};
+use crate::domain::{IllegalTransition, SubscriptionStatus};
+use crate::routes::{branded_page, decode_status, hash_subscription_token, set_subscription_status, ClientInfo};
 use crate::startup::AppState;
-use http::StatusCode;
+use http::header::ACCEPT;
+use http::{HeaderMap, StatusCode};
+use subtle::ConstantTimeEq;
 
 #[derive(serde::Deserialize, Debug)]
 pub struct Parameters {
     subscription_token: String,
 }
 
+/// What happened when a confirmation link was followed.
+#[derive(serde::Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfirmationOutcome {
+    Confirmed,
+    AlreadyConfirmed,
+    InvalidToken,
+    ExpiredToken,
+    Failed,
+}
+
+impl ConfirmationOutcome {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::Confirmed | Self::AlreadyConfirmed => StatusCode::OK,
+            Self::InvalidToken => StatusCode::UNAUTHORIZED,
+            Self::ExpiredToken => StatusCode::GONE,
+            Self::Failed => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            Self::Confirmed => "You're subscribed!",
+            Self::AlreadyConfirmed => "You're already subscribed",
+            Self::InvalidToken => "This link isn't valid",
+            Self::ExpiredToken => "This link has expired",
+            Self::Failed => "Something went wrong",
+        }
+    }
+
+    fn message(&self) -> &'static str {
+        match self {
+            Self::Confirmed => "Thanks for confirming your email address. The next issue will land in your inbox.",
+            Self::AlreadyConfirmed => "Your email address was already confirmed, there is nothing else to do.",
+            Self::InvalidToken => "The confirmation link is unknown or has already been used. Make sure you copied the whole link from the email.",
+            Self::ExpiredToken => "Confirmation links are only valid for a limited time. Enter your email address and we will send you a new one.",
+            Self::Failed => "We couldn't confirm your subscription. Please try again in a few minutes.",
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct ConfirmationResponse {
+    status: ConfirmationOutcome,
+    message: &'static str,
+}
+
 #[tracing::instrument(
     name = "Confirm a pending subscriber",
-    skip(state, parameters),
+    skip(state, client, parameters, headers),
 )]
 pub async fn confirm(
     State(state): State<AppState>,
+    client: ClientInfo,
+    headers: HeaderMap,
     parameters: Query<Parameters>,
-) -> StatusCode {
-    let id = match get_subscriber_id_from_token(&state.db_pool, &parameters.subscription_token).await {
-        Ok(id) => id,
-        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR,
-    };
-    match id {
-        Some(id) => {
-            if confirm_subscriber(&state.db_pool, id).await.is_err() {
-                return StatusCode::INTERNAL_SERVER_ERROR;
-            }
-            StatusCode::OK
+) -> Response {
+    let outcome = match try_confirm(&state, &client, &parameters.subscription_token).await {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            tracing::error!(error.cause_chain = ?e, "Failed to confirm a subscriber.");
+            ConfirmationOutcome::Failed
         }
-        None => StatusCode::UNAUTHORIZED,
+    };
+    if wants_json(&headers) {
+        let body = ConfirmationResponse {
+            status: outcome,
+            message: outcome.message(),
+        };
+        return (outcome.status_code(), Json(body)).into_response();
+    }
+    let mut content = format!("<p>{}</p>", outcome.message());
+    if outcome == ConfirmationOutcome::ExpiredToken {
+        content.push_str(RESEND_FORM_HTML);
     }
+    let page = branded_page(&state.branding, outcome.title(), &content);
+    (outcome.status_code(), Html(page)).into_response()
 }
 
+async fn try_confirm(
+    state: &AppState,
+    client: &ClientInfo,
+    subscription_token: &str,
+) -> Result<ConfirmationOutcome, sqlx::Error> {
+    let Some(token) = get_subscription_token(&state.db_pool, subscription_token).await? else {
+        return Ok(ConfirmationOutcome::InvalidToken);
+    };
+    if token.used_at.is_some() {
+        // The link was followed before, or another one they were sent. It
+        // never confirms anyone again, e.g. after they unsubscribed.
+        return Ok(match token.subscriber_status {
+            SubscriptionStatus::Confirmed => ConfirmationOutcome::AlreadyConfirmed,
+            _ => ConfirmationOutcome::InvalidToken,
+        });
+    }
+    if token.expires_at <= Utc::now() {
+        return Ok(ConfirmationOutcome::ExpiredToken);
+    }
+    let mut transaction = state.db_pool.begin().await?;
+    let outcome = match confirm_subscriber(&mut transaction, token.subscriber_id).await? {
+        Ok(()) => {
+            record_consent(
+                &mut transaction,
+                token.subscriber_id,
+                ConsentEvent::Confirmed,
+                client,
+                None,
+                &state.privacy_policy_version,
+            )
+            .await?;
+            enroll_subscriber(&mut transaction, token.subscriber_id).await?;
+            ConfirmationOutcome::Confirmed
+        }
+        Err(IllegalTransition { from: SubscriptionStatus::Confirmed, .. }) => ConfirmationOutcome::AlreadyConfirmed,
+        // They left, or the address was suppressed, since the link was sent.
+        Err(e) => {
+            tracing::info!(error = %e, "Refused to confirm a subscriber.");
+            ConfirmationOutcome::InvalidToken
+        }
+    };
+    mark_subscription_tokens_used(&mut transaction, token.subscriber_id).await?;
+    transaction.commit().await?;
+    Ok(outcome)
+}
+
+// Browsers send `text/html` first, API clients opt in to JSON explicitly.
+fn wants_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .and_then(|accept| accept.split(',').next())
+        .is_some_and(|preferred| preferred.trim().starts_with("application/json"))
+}
+
+pub struct SubscriptionToken {
+    pub subscriber_id: uuid::Uuid,
+    pub subscriber_status: SubscriptionStatus,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+}
+
+// Looked up by hash; the stored hash is then compared once more in constant
+// time, so nothing about it leaks through how long the check takes.
 #[tracing::instrument(
-    name = "Get a subscriber id from a subscription token",
+    name = "Get a subscription token",
     skip(pool, subscription_token),
 )]
-pub async fn get_subscriber_id_from_token(
+pub async fn get_subscription_token(
     pool: &sqlx::PgPool,
     subscription_token: &str,
-) -> Result<Option<uuid::Uuid>, sqlx::Error> {
-    let result = sqlx::query!(
+) -> Result<Option<SubscriptionToken>, sqlx::Error> {
+    let token_hash = hash_subscription_token(subscription_token);
+    let row = sqlx::query!(
         r#"
-        SELECT subscriber_id
-        FROM subscription_tokens
-        WHERE subscription_token = $1
+        SELECT t.token_hash, t.subscriber_id, t.expires_at, t.used_at, s.status
+        FROM subscription_tokens t
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        WHERE t.token_hash = $1
         "#,
-        subscription_token
+        token_hash
     )
     .fetch_optional(pool)
     .await
@@ -52,26 +185,97 @@ pub async fn get_subscriber_id_from_token(
         tracing::error!("Failed to execute query: {:?}", error);
         error
     })?;
-    Ok(result.map(|r|r.subscriber_id))
+    row.filter(|row| bool::from(row.token_hash.as_bytes().ct_eq(token_hash.as_bytes())))
+        .map(|row| {
+            Ok(SubscriptionToken {
+                subscriber_id: row.subscriber_id,
+                subscriber_status: decode_status(&row.status)?,
+                expires_at: row.expires_at,
+                used_at: row.used_at,
+            })
+        })
+        .transpose()
 }
 
+// Fails with the reason if the subscriber isn't pending anymore, e.g. they
+// already confirmed.
 #[tracing::instrument(
     name = "Confirm a subscriber",
-    skip(pool, subscriber_id),
+    skip(transaction, subscriber_id),
 )]
 pub async fn confirm_subscriber(
-    pool: &sqlx::PgPool,
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: uuid::Uuid,
+) -> Result<Result<(), IllegalTransition>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT status FROM subscriptions WHERE id = $1 FOR UPDATE"#,
+        subscriber_id
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute query: {:?}", error);
+        error
+    })?;
+    if let Err(e) = decode_status(&row.status)?.transition(SubscriptionStatus::Confirmed) {
+        return Ok(Err(e));
+    }
+    set_subscription_status(transaction, subscriber_id, SubscriptionStatus::Confirmed).await?;
+    Ok(Ok(()))
+}
+
+// A token is single use: once the subscriber is confirmed none of the links
+// they were sent confirm anything anymore. They are kept to tell whoever
+// follows one again that there is nothing left to do, until the worker
+// deletes them with the expired ones.
+#[tracing::instrument(
+    name = "Mark the subscription tokens of a subscriber as used",
+    skip(transaction, subscriber_id),
+)]
+pub async fn mark_subscription_tokens_used(
+    transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: uuid::Uuid,
 ) -> Result<(), sqlx::Error> {
     sqlx::query!(
-        r#"
-        UPDATE subscriptions
-        SET status = 'confirmed'
-        WHERE id = $1
-        "#,
+        r#"UPDATE subscription_tokens SET used_at = now() WHERE subscriber_id = $1 AND used_at IS NULL"#,
+        subscriber_id
+    )
+    .execute(transaction)
+    .await
+    .map_err(|error| {
+        tracing::error!("Failed to execute query: {:?}", error);
+        error
+    })?;
+    Ok(())
+}
+
+/// Deletes the tokens that expired more than a day ago, used or not. Until
+/// then, a late click still gets the expired page with a way to get a new
+/// link. Returns how many were deleted.
+#[tracing::instrument(name = "Delete expired subscription tokens", skip(executor))]
+pub async fn delete_expired_subscription_tokens(executor: impl PgExecutor<'_>) -> Result<u64, sqlx::Error> {
+    let deleted = sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE expires_at < now() - interval '1 day'"#,
+    )
+    .execute(executor)
+    .await?;
+    Ok(deleted.rows_affected())
+}
+
+// For subscribers who leave: none of their links work anymore.
+#[tracing::instrument(
+    name = "Delete the subscription tokens of a subscriber",
+    skip(transaction, subscriber_id),
+)]
+pub async fn delete_subscription_tokens(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: uuid::Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
         subscriber_id
     )
-    .execute(pool)
+    .execute(transaction)
     .await
     .map_err(|error| {
         tracing::error!("Failed to execute query: {:?}", error);
@@ -79,3 +283,14 @@ pub async fn confirm_subscriber(
     })?;
     Ok(())
 }
+
+const RESEND_FORM_HTML: &str = r#"<form action="/subscriptions/resend" method="post">
+        <label>Email
+            <input
+                type="email"
+                placeholder="Enter your email"
+                name="email"
+            >
+        </label>
+        <button type="submit">Send a new link</button>
+    </form>"#;
@@ -0,0 +1,524 @@
+use anyhow::Context;
+use axum::extract::{Form, Query, State};
+use axum::response::{Html, IntoResponse, Response};
+use axum::Json;
+use axum_extra::extract::cookie::Key;
+use chrono::{DateTime, TimeZone, Utc};
+use htmlescape::encode_attribute;
+use http::header::CONTENT_DISPOSITION;
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::consent::{get_consent_records, ConsentRecord};
+use crate::domain::{SubscriberEmail, SubscriptionStatus};
+use crate::email_client::{EmailClient, EmailMessage, SendEmailError, SendReceipt};
+use crate::routes::{branded_page, decode_status, error_chain_fmt, invalid_link_page, subscriber_id_from_token};
+use crate::signature;
+use crate::startup::AppState;
+use crate::subscriber_email_queue::{queue_email_to_address, queue_emails, QueuedEmail};
+
+#[derive(thiserror::Error)]
+pub enum DataRequestError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("Too many data exports were requested for this address.")]
+    RateLimited,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for DataRequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for DataRequestError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::ValidationError(_) => StatusCode::BAD_REQUEST.into_response(),
+            Self::RateLimited => StatusCode::TOO_MANY_REQUESTS.into_response(),
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        }
+    }
+}
+
+const DATA_EXPORT_TOKEN_PURPOSE: &str = "subscriber-data-export";
+const ERASURE_TOKEN_PURPOSE: &str = "subscriber-erasure";
+
+// The expiry is part of the signed payload, nothing needs to be stored.
+fn data_request_token(key: &Key, purpose: &str, subscriber_id: Uuid, ttl: chrono::Duration) -> String {
+    let expires_at = Utc::now() + ttl;
+    let payload = format!("{}.{}", subscriber_id.simple(), expires_at.timestamp());
+    signature::sign(key, purpose, &payload)
+}
+
+fn verify_data_request_token(key: &Key, purpose: &str, token: &str) -> Option<(Uuid, DateTime<Utc>)> {
+    let payload = signature::verify(key, purpose, token)?;
+    let (subscriber_id, expires_at) = payload.split_once('.')?;
+    let expires_at = Utc.timestamp_opt(expires_at.parse().ok()?, 0).single()?;
+    Some((Uuid::parse_str(subscriber_id).ok()?, expires_at))
+}
+
+/// Where a subscriber downloads their data, for `ttl`.
+pub fn data_export_link(base_url: &str, key: &Key, subscriber_id: Uuid, ttl: chrono::Duration) -> String {
+    let token = data_request_token(key, DATA_EXPORT_TOKEN_PURPOSE, subscriber_id, ttl);
+    format!("{}/subscriptions/data_export?token={}", base_url, token)
+}
+
+/// Where a subscriber confirms deleting their data, for `ttl`. Unlike the
+/// preferences link, which is in every issue and may be forwarded with it,
+/// it is only ever sent on request.
+pub fn erasure_link(base_url: &str, key: &Key, subscriber_id: Uuid, ttl: chrono::Duration) -> String {
+    let token = data_request_token(key, ERASURE_TOKEN_PURPOSE, subscriber_id, ttl);
+    format!("{}/subscriptions/erase?token={}", base_url, token)
+}
+
+fn expired_link_page(state: &AppState, content: &str) -> Response {
+    let page = branded_page(&state.branding, "This link has expired", content);
+    (StatusCode::GONE, Html(page)).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct DataExportFormData {
+    email: String,
+}
+
+// The link is sent to the address itself: only its owner gets the data. The
+// request does the same work whether or not we know the address, so neither
+// the answer nor its timing tells.
+#[tracing::instrument(
+    name = "Request a data export",
+    skip(state, form_data),
+    fields(email = %form_data.email)
+)]
+pub async fn request_data_export(
+    State(state): State<AppState>,
+    Form(form_data): Form<DataExportFormData>,
+) -> Result<Html<String>, DataRequestError> {
+    let email = SubscriberEmail::parse(form_data.email).map_err(DataRequestError::ValidationError)?;
+    if !state.data_export_rate_limiter.check(&email.as_ref().to_lowercase()) {
+        return Err(DataRequestError::RateLimited);
+    }
+    queue_email_to_address(&state.db_pool, &email, QueuedEmail::DataExport)
+        .await
+        .context("Failed to queue a data export link.")?;
+    Ok(Html(branded_page(
+        &state.branding,
+        "Check your inbox",
+        "<p>If we hold any data about this address, a link to download it is on its way.</p>",
+    )))
+}
+
+#[derive(Deserialize)]
+pub struct DataRequestParameters {
+    token: String,
+}
+
+#[tracing::instrument(name = "Download a data export", skip(state, parameters))]
+pub async fn download_data_export(
+    State(state): State<AppState>,
+    Query(parameters): Query<DataRequestParameters>,
+) -> Result<Response, DataRequestError> {
+    let Some((subscriber_id, expires_at)) =
+        verify_data_request_token(&state.secret, DATA_EXPORT_TOKEN_PURPOSE, &parameters.token)
+    else {
+        return Ok(invalid_link_page(&state));
+    };
+    if expires_at <= Utc::now() {
+        return Ok(expired_link_page(
+            &state,
+            "<p>Download links only work for a limited time. Ask for a new one from your preferences page.</p>",
+        ));
+    }
+    let Some(export) = export_subscriber_data(&state.db_pool, subscriber_id)
+        .await
+        .context("Failed to gather the data of a subscriber.")?
+    else {
+        return Ok(invalid_link_page(&state));
+    };
+    Ok((
+        [(CONTENT_DISPOSITION, r#"attachment; filename="subscriber-data.json""#)],
+        Json(export),
+    )
+        .into_response())
+}
+
+#[derive(Deserialize)]
+pub struct EraseFormData {
+    token: String,
+}
+
+// Asked for from the preferences page. Holding the preferences token is not
+// enough to erase anything, a link is emailed to the address first.
+#[tracing::instrument(name = "Request the erasure of a subscriber", skip(state, form_data))]
+pub async fn request_erasure(
+    State(state): State<AppState>,
+    Form(form_data): Form<EraseFormData>,
+) -> Result<Response, DataRequestError> {
+    let Some(subscriber_id) = subscriber_id_from_token(&state.secret, &form_data.token) else {
+        return Ok(invalid_link_page(&state));
+    };
+    queue_emails(&state.db_pool, &[subscriber_id], QueuedEmail::ErasureLink)
+        .await
+        .context("Failed to queue an erasure link.")?;
+    let page = branded_page(
+        &state.branding,
+        "Check your inbox",
+        "<p>To make sure it's you, we sent a link to confirm that your data should be deleted.</p>",
+    );
+    Ok(Html(page).into_response())
+}
+
+// Following the link only shows a button: link checkers in mailboxes must not
+// erase anyone.
+#[tracing::instrument(name = "Show the erasure confirmation", skip(state, parameters))]
+pub async fn erasure_form(
+    State(state): State<AppState>,
+    Query(parameters): Query<DataRequestParameters>,
+) -> Result<Response, DataRequestError> {
+    let Some((_, expires_at)) = verify_data_request_token(&state.secret, ERASURE_TOKEN_PURPOSE, &parameters.token)
+    else {
+        return Ok(invalid_link_page(&state));
+    };
+    if expires_at <= Utc::now() {
+        return Ok(expired_link_page(&state, ERASURE_LINK_EXPIRED_HTML));
+    }
+    let content = format!(
+        r#"<form action="/subscriptions/erase" method="post">
+        <input type="hidden" name="token" value="{}">
+        <p>Deleting your data removes your address and everything we keep about it. It can't be undone.</p>
+        <button type="submit">Delete my data</button>
+    </form>"#,
+        encode_attribute(&parameters.token),
+    );
+    Ok(Html(branded_page(&state.branding, "Delete your data", &content)).into_response())
+}
+
+const ERASURE_LINK_EXPIRED_HTML: &str =
+    "<p>Erasure links only work for a limited time. Ask for a new one from your preferences page.</p>";
+
+#[tracing::instrument(name = "Erase the data of a subscriber", skip(state, form_data))]
+pub async fn erase_subscriber_data(
+    State(state): State<AppState>,
+    Form(form_data): Form<EraseFormData>,
+) -> Result<Response, DataRequestError> {
+    let Some((subscriber_id, expires_at)) =
+        verify_data_request_token(&state.secret, ERASURE_TOKEN_PURPOSE, &form_data.token)
+    else {
+        return Ok(invalid_link_page(&state));
+    };
+    if expires_at <= Utc::now() {
+        return Ok(expired_link_page(&state, ERASURE_LINK_EXPIRED_HTML));
+    }
+    let mut transaction = state
+        .db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a database connection.")?;
+    // Asking twice is not an error: the data is gone either way.
+    erase_subscriber(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to erase a subscriber.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to erase a subscriber.")?;
+    let page = branded_page(
+        &state.branding,
+        "Your data was deleted",
+        "<p>We no longer hold your email address or anything related to it.</p>",
+    );
+    Ok(Html(page).into_response())
+}
+
+/// Everything we hold about a subscriber, as handed over on request: the
+/// subscription with its preferences, attributes and signup attribution,
+/// confirmation tokens, pending email changes, every email sent to them,
+/// their consent records and their automations.
+///
+/// Left out are emails still waiting to be sent, which only say which email
+/// is next, and the admin audit log, which is about what admins did.
+#[derive(Serialize)]
+pub struct SubscriberDataExport {
+    pub exported_at: DateTime<Utc>,
+    pub subscription: SubscriptionData,
+    pub subscription_tokens: Vec<SubscriptionTokenData>,
+    pub email_change_requests: Vec<EmailChangeRequestData>,
+    pub email_deliveries: Vec<EmailDeliveryData>,
+    pub consent_records: Vec<ConsentRecord>,
+    pub automation_enrollments: Vec<AutomationEnrollmentData>,
+    pub automation_step_deliveries: Vec<AutomationStepDeliveryData>,
+}
+
+#[derive(Serialize)]
+pub struct SubscriptionData {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: SubscriptionStatus,
+    pub subscribed_at: DateTime<Utc>,
+    pub topics: Vec<String>,
+    pub delivery_frequency: String,
+    pub attributes: serde_json::Value,
+    pub signup_source: Option<String>,
+    pub referrer: Option<String>,
+    pub utm_source: Option<String>,
+    pub utm_medium: Option<String>,
+    pub utm_campaign: Option<String>,
+    pub utm_term: Option<String>,
+    pub utm_content: Option<String>,
+}
+
+// The token values are left out: they are credentials, not data about anyone.
+#[derive(Serialize)]
+pub struct SubscriptionTokenData {
+    pub issued_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+pub struct EmailChangeRequestData {
+    pub new_email: String,
+    pub issued_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct EmailDeliveryData {
+    pub kind: String,
+    pub newsletter_title: Option<String>,
+    pub message_id: Option<String>,
+    pub sent_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct AutomationEnrollmentData {
+    pub automation_name: String,
+    pub enrolled_at: DateTime<Utc>,
+    pub stopped_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+pub struct AutomationStepDeliveryData {
+    pub automation_name: String,
+    pub subject: String,
+    pub due_at: DateTime<Utc>,
+    pub status: String,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Gather the data of a subscriber", skip(pool))]
+pub async fn export_subscriber_data(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriberDataExport>, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let Some(row) = sqlx::query!(
+        r#"
+        SELECT
+            id, email, name, status, subscribed_at, topics, delivery_frequency, attributes,
+            signup_source, referrer, utm_source, utm_medium, utm_campaign, utm_term, utm_content
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(&mut transaction)
+    .await?
+    else {
+        return Ok(None);
+    };
+    let subscription = SubscriptionData {
+        id: row.id,
+        email: row.email,
+        name: row.name,
+        status: decode_status(&row.status)?,
+        subscribed_at: row.subscribed_at,
+        topics: row.topics,
+        delivery_frequency: row.delivery_frequency,
+        attributes: row.attributes,
+        signup_source: row.signup_source,
+        referrer: row.referrer,
+        utm_source: row.utm_source,
+        utm_medium: row.utm_medium,
+        utm_campaign: row.utm_campaign,
+        utm_term: row.utm_term,
+        utm_content: row.utm_content,
+    };
+    let subscription_tokens = sqlx::query_as!(
+        SubscriptionTokenData,
+        r#"
+        SELECT issued_at, expires_at, used_at
+        FROM subscription_tokens
+        WHERE subscriber_id = $1
+        ORDER BY issued_at
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(&mut transaction)
+    .await?;
+    let email_change_requests = sqlx::query_as!(
+        EmailChangeRequestData,
+        r#"
+        SELECT new_email, issued_at, expires_at
+        FROM email_change_requests
+        WHERE subscriber_id = $1
+        ORDER BY issued_at
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(&mut transaction)
+    .await?;
+    let email_deliveries = sqlx::query_as!(
+        EmailDeliveryData,
+        r#"
+        SELECT d.kind, i.title AS "newsletter_title?", d.message_id, d.sent_at
+        FROM email_deliveries d
+        LEFT JOIN newsletter_issues i ON i.newsletter_issue_id = d.newsletter_issue_id
+        WHERE d.subscriber_id = $1
+        ORDER BY d.sent_at
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(&mut transaction)
+    .await?;
+    let consent_records = get_consent_records(&mut transaction, subscriber_id).await?;
+    let automation_enrollments = sqlx::query_as!(
+        AutomationEnrollmentData,
+        r#"
+        SELECT a.name AS automation_name, e.enrolled_at, e.stopped_at
+        FROM automation_enrollments e
+        JOIN automations a ON a.id = e.automation_id
+        WHERE e.subscriber_id = $1
+        ORDER BY e.enrolled_at
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(&mut transaction)
+    .await?;
+    let automation_step_deliveries = sqlx::query_as!(
+        AutomationStepDeliveryData,
+        r#"
+        SELECT
+            a.name AS automation_name, st.subject, d.due_at, d.status, d.attempts, d.last_error,
+            d.updated_at
+        FROM automation_step_deliveries d
+        JOIN automation_enrollments e ON e.id = d.enrollment_id
+        JOIN automations a ON a.id = e.automation_id
+        JOIN automation_steps st ON st.id = d.step_id
+        WHERE e.subscriber_id = $1
+        ORDER BY d.due_at
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(Some(SubscriberDataExport {
+        exported_at: Utc::now(),
+        subscription,
+        subscription_tokens,
+        email_change_requests,
+        email_deliveries,
+        consent_records,
+        automation_enrollments,
+        automation_step_deliveries,
+    }))
+}
+
+/// Deletes a subscriber. Everything keyed on them goes with them through
+/// `ON DELETE CASCADE`: tokens, email change requests, delivery history,
+/// consent records, automation enrollments with their step deliveries, and
+/// queued emails. Returns false if there was no such subscriber.
+///
+/// The admin audit log keeps its entries. They only hold the id, status
+/// changes and the names of edited attributes, so nothing points back to the
+/// person. Imports don't link to subscribers, and their error reports hold no
+/// addresses.
+#[tracing::instrument(name = "Erase a subscriber", skip(transaction))]
+pub async fn erase_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"DELETE FROM subscriptions WHERE id = $1"#,
+        subscriber_id,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+#[tracing::instrument(name = "Fetch a subscriber id by email", skip(pool, email))]
+pub(crate) async fn get_subscriber_id(
+    pool: &PgPool,
+    email: &SubscriberEmail,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE email_canonical = lower($1)"#,
+        email.as_ref(),
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(result.map(|r| r.id))
+}
+
+#[tracing::instrument(name = "Send a data export link", skip(email, email_client, download_link))]
+pub async fn send_data_export_link(
+    email: &SubscriberEmail,
+    email_client: &EmailClient,
+    download_link: &str,
+) -> Result<SendReceipt, SendEmailError> {
+    let plain_body = format!(
+        "You asked for a copy of the data we hold about this address.\nDownload it from {}",
+        download_link
+    );
+    let html_body = format!(
+        "You asked for a copy of the data we hold about this address.<br />\
+        Click <a href=\"{}\">here</a> to download it.",
+        download_link
+    );
+    let message = EmailMessage::builder(
+        email.clone(),
+        "Your data export",
+        html_body,
+        plain_body,
+    )
+    .tag("data_export")
+    .build()
+    .expect("The data export email is always valid");
+    email_client.send(&message).await
+}
+
+#[tracing::instrument(name = "Send an erasure link", skip(email, email_client, erasure_link))]
+pub async fn send_erasure_link(
+    email: &SubscriberEmail,
+    email_client: &EmailClient,
+    erasure_link: &str,
+) -> Result<SendReceipt, SendEmailError> {
+    let plain_body = format!(
+        "You asked for the data we hold about this address to be deleted.\nConfirm it at {}",
+        erasure_link
+    );
+    let html_body = format!(
+        "You asked for the data we hold about this address to be deleted.<br />\
+        Click <a href=\"{}\">here</a> to confirm it.",
+        erasure_link
+    );
+    let message = EmailMessage::builder(
+        email.clone(),
+        "Confirm deleting your data",
+        html_body,
+        plain_body,
+    )
+    .tag("erasure_link")
+    .build()
+    .expect("The erasure email is always valid");
+    email_client.send(&message).await
+}
@@ -0,0 +1,686 @@
+use anyhow::Context;
+use axum::extract::{Form, Query, State};
+use axum::response::{Html, IntoResponse, Response};
+use axum_extra::extract::cookie::Key;
+use chrono::{DateTime, Utc};
+use htmlescape::{encode_attribute, encode_minimal};
+use http::StatusCode;
+use serde::Deserialize;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::automation::stop_enrollments;
+use crate::domain::{DeliveryFrequency, SubscriberEmail, SubscriberName, SubscriptionStatus};
+use crate::email_client::{EmailClient, EmailMessage, SendEmailError, SendReceipt};
+use crate::email_delivery::{record_email_delivery, EmailKind};
+use crate::routes::{
+    branded_page, decode_status, error_chain_fmt, generate_subscription_token, hash_subscription_token,
+    set_subscription_status,
+};
+use crate::signature;
+use crate::startup::AppState;
+
+#[derive(thiserror::Error)]
+pub enum PreferencesError {
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for PreferencesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for PreferencesError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        }
+    }
+}
+
+const PREFERENCES_TOKEN_PURPOSE: &str = "subscriber-preferences";
+
+/// A token that lets a subscriber manage their preferences without an
+/// account. It is sent with every issue, so it does not expire.
+pub fn preferences_token(key: &Key, subscriber_id: Uuid) -> String {
+    signature::sign(key, PREFERENCES_TOKEN_PURPOSE, &subscriber_id.simple().to_string())
+}
+
+pub fn preferences_link(base_url: &str, key: &Key, subscriber_id: Uuid) -> String {
+    format!(
+        "{}/subscriptions/preferences?token={}",
+        base_url,
+        preferences_token(key, subscriber_id),
+    )
+}
+
+pub(crate) fn subscriber_id_from_token(key: &Key, token: &str) -> Option<Uuid> {
+    let subscriber_id = signature::verify(key, PREFERENCES_TOKEN_PURPOSE, token)?;
+    Uuid::parse_str(subscriber_id).ok()
+}
+
+#[derive(Deserialize)]
+pub struct PreferencesParameters {
+    token: String,
+}
+
+#[tracing::instrument(name = "Show the preferences of a subscriber", skip(state, parameters))]
+pub async fn preferences_form(
+    State(state): State<AppState>,
+    Query(parameters): Query<PreferencesParameters>,
+) -> Result<Response, PreferencesError> {
+    let Some(subscriber_id) = subscriber_id_from_token(&state.secret, &parameters.token) else {
+        return Ok(invalid_link_page(&state));
+    };
+    let Some(preferences) = get_preferences(&state.db_pool, subscriber_id)
+        .await
+        .context("Failed to fetch the preferences of a subscriber.")?
+    else {
+        return Ok(invalid_link_page(&state));
+    };
+    if !preferences.is_subscribed() {
+        return Ok(unsubscribed_page(&state, &parameters.token));
+    }
+    Ok(Html(preferences_page(&state, &parameters.token, &preferences, None)).into_response())
+}
+
+/// The submitted preferences form. Topics are checkboxes sharing a name, so
+/// the form is read as a list of pairs rather than straight into a struct.
+#[derive(Default)]
+struct PreferencesFormData {
+    token: String,
+    name: String,
+    email: String,
+    topics: Vec<String>,
+    delivery_frequency: String,
+}
+
+impl From<Vec<(String, String)>> for PreferencesFormData {
+    fn from(fields: Vec<(String, String)>) -> Self {
+        let mut form_data = Self::default();
+        for (key, value) in fields {
+            match key.as_str() {
+                "token" => form_data.token = value,
+                "name" => form_data.name = value,
+                "email" => form_data.email = value,
+                "topics" => form_data.topics.push(value),
+                "delivery_frequency" => form_data.delivery_frequency = value,
+                _ => {}
+            }
+        }
+        form_data
+    }
+}
+
+struct PreferencesUpdate {
+    name: SubscriberName,
+    email: SubscriberEmail,
+    topics: Vec<String>,
+    delivery_frequency: DeliveryFrequency,
+}
+
+impl PreferencesFormData {
+    fn parse(self, state: &AppState) -> Result<PreferencesUpdate, String> {
+        let name = SubscriberName::parse(self.name)?;
+        let email = SubscriberEmail::parse(self.email)?;
+        let delivery_frequency = DeliveryFrequency::parse(&self.delivery_frequency)?;
+        let mut topics = Vec::new();
+        for topic in self.topics {
+            if !state.topics.iter().any(|t| t.id == topic) {
+                return Err(format!("{} is not a topic", topic));
+            }
+            if !topics.contains(&topic) {
+                topics.push(topic);
+            }
+        }
+        Ok(PreferencesUpdate {
+            name,
+            email,
+            topics,
+            delivery_frequency,
+        })
+    }
+}
+
+#[tracing::instrument(name = "Update the preferences of a subscriber", skip(state, fields))]
+pub async fn update_preferences(
+    State(state): State<AppState>,
+    Form(fields): Form<Vec<(String, String)>>,
+) -> Result<Response, PreferencesError> {
+    let form_data = PreferencesFormData::from(fields);
+    let token = form_data.token.clone();
+    let Some(subscriber_id) = subscriber_id_from_token(&state.secret, &token) else {
+        return Ok(invalid_link_page(&state));
+    };
+    let Some(preferences) = get_preferences(&state.db_pool, subscriber_id)
+        .await
+        .context("Failed to fetch the preferences of a subscriber.")?
+    else {
+        return Ok(invalid_link_page(&state));
+    };
+    if !preferences.is_subscribed() {
+        return Ok(unsubscribed_page(&state, &token));
+    }
+    let update = match form_data.parse(&state).and_then(|update| {
+        // An address that is already on the list stays usable.
+        if update.email.as_ref() != preferences.email {
+            state.domain_blocklist.check(&update.email)?;
+        }
+        Ok(update)
+    }) {
+        Ok(update) => update,
+        Err(e) => {
+            let message = format!("Your preferences were not saved: {}.", e);
+            let page = preferences_page(&state, &token, &preferences, Some(&message));
+            return Ok((StatusCode::BAD_REQUEST, Html(page)).into_response());
+        }
+    };
+
+    let mut transaction = state
+        .db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a database connection.")?;
+    save_preferences(&mut transaction, subscriber_id, &update)
+        .await
+        .context("Failed to save the preferences of a subscriber.")?;
+    let email_change_token = if update.email.as_ref() != preferences.email {
+        let token = request_email_change(
+            &mut transaction,
+            subscriber_id,
+            &update.email,
+            state.confirmation_token_ttl,
+        )
+        .await
+        .context("Failed to store an email change request.")?;
+        Some(token)
+    } else {
+        None
+    };
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to save preferences.")?;
+
+    let mut message = "Your preferences were saved.".to_string();
+    if let Some(email_change_token) = email_change_token {
+        deliver_email_change_confirmation(&state, subscriber_id, &update.email, &email_change_token)
+            .await?;
+        message.push_str(&format!(
+            " We sent a link to {}: your address changes once you follow it.",
+            update.email,
+        ));
+    }
+    let preferences = get_preferences(&state.db_pool, subscriber_id)
+        .await
+        .context("Failed to fetch the preferences of a subscriber.")?
+        .context("The subscriber is gone.")?;
+    Ok(Html(preferences_page(&state, &token, &preferences, Some(&message))).into_response())
+}
+
+#[derive(Deserialize)]
+pub struct UnsubscribeFormData {
+    token: String,
+}
+
+#[tracing::instrument(name = "Unsubscribe a subscriber", skip(state, form_data))]
+pub async fn unsubscribe(
+    State(state): State<AppState>,
+    Form(form_data): Form<UnsubscribeFormData>,
+) -> Result<Response, PreferencesError> {
+    let Some(subscriber_id) = subscriber_id_from_token(&state.secret, &form_data.token) else {
+        return Ok(invalid_link_page(&state));
+    };
+    let mut transaction = state
+        .db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a database connection.")?;
+    mark_as_unsubscribed(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to unsubscribe a subscriber.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to unsubscribe a subscriber.")?;
+    Ok(unsubscribed_page(&state, &form_data.token))
+}
+
+#[derive(Deserialize)]
+pub struct EmailChangeParameters {
+    token: String,
+}
+
+#[tracing::instrument(name = "Confirm a new email address", skip(state, parameters))]
+pub async fn confirm_email_change(
+    State(state): State<AppState>,
+    Query(parameters): Query<EmailChangeParameters>,
+) -> Result<Response, PreferencesError> {
+    let mut transaction = state
+        .db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a database connection.")?;
+    let Some(request) = get_email_change_request(&mut transaction, &parameters.token)
+        .await
+        .context("Failed to fetch an email change request.")?
+    else {
+        let page = branded_page(
+            &state.branding,
+            "This link isn't valid",
+            "<p>The link is unknown or has already been used.</p>",
+        );
+        return Ok((StatusCode::UNAUTHORIZED, Html(page)).into_response());
+    };
+    if request.expires_at <= Utc::now() {
+        let page = branded_page(
+            &state.branding,
+            "This link has expired",
+            "<p>Change your address again from your preferences page to get a new link.</p>",
+        );
+        return Ok((StatusCode::GONE, Html(page)).into_response());
+    }
+    delete_email_change_requests(&mut transaction, request.subscriber_id)
+        .await
+        .context("Failed to delete the email change requests of a subscriber.")?;
+    match change_email(&mut transaction, request.subscriber_id, &request.new_email).await {
+        Ok(()) => {}
+        Err(e) if is_unique_violation(&e) => {
+            let page = branded_page(
+                &state.branding,
+                "This address is already subscribed",
+                "<p>Another subscription already uses this address, so yours was left unchanged.</p>",
+            );
+            return Ok((StatusCode::CONFLICT, Html(page)).into_response());
+        }
+        Err(e) => return Err(anyhow::Error::new(e).context("Failed to change the email of a subscriber.").into()),
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to change an email address.")?;
+    let content = format!(
+        r#"<p>From now on we will write to {}.</p><p><a href="{}">Back to your preferences</a></p>"#,
+        encode_minimal(&request.new_email),
+        encode_attribute(&preferences_link(&state.base_url, &state.secret, request.subscriber_id)),
+    );
+    Ok(Html(branded_page(&state.branding, "Your email address was updated", &content)).into_response())
+}
+
+fn is_unique_violation(e: &sqlx::Error) -> bool {
+    e.as_database_error()
+        .and_then(|e| e.code())
+        .is_some_and(|code| code == "23505")
+}
+
+pub(crate) fn invalid_link_page(state: &AppState) -> Response {
+    let page = branded_page(
+        &state.branding,
+        "This link isn't valid",
+        "<p>Make sure you copied the whole link from one of our emails.</p>",
+    );
+    (StatusCode::UNAUTHORIZED, Html(page)).into_response()
+}
+
+// Leaving the list is not the same as being forgotten, so erasure is still
+// offered from here.
+fn unsubscribed_page(state: &AppState, token: &str) -> Response {
+    let content = format!(
+        r#"<p>You won't receive any more emails from us.</p>
+    {}"#,
+        erase_form_html(token),
+    );
+    let page = branded_page(&state.branding, "You're unsubscribed", &content);
+    Html(page).into_response()
+}
+
+fn erase_form_html(token: &str) -> String {
+    format!(
+        r#"<form action="/subscriptions/preferences/erase" method="post">
+        <input type="hidden" name="token" value="{}">
+        <p>Deleting your data removes your address and everything we keep about it. It can't be undone.</p>
+        <button type="submit">Email me a link to delete my data</button>
+    </form>"#,
+        encode_attribute(token),
+    )
+}
+
+fn preferences_page(
+    state: &AppState,
+    token: &str,
+    preferences: &Preferences,
+    message: Option<&str>,
+) -> String {
+    let message_html = match message {
+        Some(message) => format!("<p><i>{}</i></p>", encode_minimal(message)),
+        None => String::new(),
+    };
+    let topics_html: String = state
+        .topics
+        .iter()
+        .map(|topic| {
+            let checked = if preferences.topics.contains(&topic.id) { " checked" } else { "" };
+            format!(
+                r#"<label><input type="checkbox" name="topics" value="{}"{}> {}</label><br>"#,
+                encode_attribute(&topic.id),
+                checked,
+                encode_minimal(&topic.name),
+            )
+        })
+        .collect();
+    let frequencies_html: String = DeliveryFrequency::ALL
+        .iter()
+        .map(|frequency| {
+            let checked = if frequency.as_str() == preferences.delivery_frequency { " checked" } else { "" };
+            format!(
+                r#"<label><input type="radio" name="delivery_frequency" value="{}"{}> {}</label><br>"#,
+                frequency.as_str(),
+                checked,
+                frequency.label(),
+            )
+        })
+        .collect();
+    let erase_form_html = erase_form_html(token);
+    let token = encode_attribute(token);
+    let content = format!(
+        r#"{message_html}
+    <form action="/subscriptions/preferences" method="post">
+        <input type="hidden" name="token" value="{token}">
+        <label>Name
+            <input type="text" name="name" value="{name}">
+        </label>
+        <br>
+        <label>Email
+            <input type="email" name="email" value="{email}">
+        </label>
+        <br>
+        <fieldset>
+            <legend>Topics</legend>
+            <p>Leave them all unticked to get every issue.</p>
+            {topics_html}
+        </fieldset>
+        <fieldset>
+            <legend>How often</legend>
+            {frequencies_html}
+        </fieldset>
+        <button type="submit">Save preferences</button>
+    </form>
+    <form action="/subscriptions/preferences/unsubscribe" method="post">
+        <input type="hidden" name="token" value="{token}">
+        <button type="submit">Unsubscribe</button>
+    </form>
+    <h2>Your data</h2>
+    <form action="/subscriptions/data_export" method="post">
+        <input type="hidden" name="email" value="{email}">
+        <button type="submit">Email me a copy of my data</button>
+    </form>
+    {erase_form_html}"#,
+        name = encode_attribute(&preferences.name),
+        email = encode_attribute(&preferences.email),
+    );
+    branded_page(&state.branding, "Your preferences", &content)
+}
+
+async fn deliver_email_change_confirmation(
+    state: &AppState,
+    subscriber_id: Uuid,
+    email: &SubscriberEmail,
+    token: &str,
+) -> Result<(), anyhow::Error> {
+    let receipt = send_email_change_confirmation(email, &state.email_client, &state.base_url, token)
+        .await
+        .context("Failed to send an email change confirmation.")?;
+    if let Err(e) = record_email_delivery(
+        &state.db_pool,
+        subscriber_id,
+        EmailKind::EmailChange,
+        &receipt,
+    )
+    .await
+    {
+        tracing::error!(error.cause_chain = ?e, "Failed to record an email change confirmation delivery.");
+    }
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Send an email change confirmation",
+    skip(email, email_client, base_url, token)
+)]
+async fn send_email_change_confirmation(
+    email: &SubscriberEmail,
+    email_client: &EmailClient,
+    base_url: &str,
+    token: &str,
+) -> Result<SendReceipt, SendEmailError> {
+    let confirmation_link = format!(
+        "{}/subscriptions/preferences/confirm_email?token={}",
+        base_url,
+        token,
+    );
+    let plain_body = format!(
+        "You asked to receive our newsletter at this address.\nVisit {} to confirm the change.",
+        confirmation_link
+    );
+    let html_body = format!(
+        "You asked to receive our newsletter at this address.<br />\
+        Click <a href=\"{}\">here</a> to confirm the change.",
+        confirmation_link
+    );
+    let message = EmailMessage::builder(
+        email.clone(),
+        "Confirm your new email address",
+        html_body,
+        plain_body,
+    )
+    .tag("email_change")
+    .build()
+    .expect("The email change confirmation is always valid");
+    email_client.send(&message).await
+}
+
+struct Preferences {
+    email: String,
+    name: String,
+    status: SubscriptionStatus,
+    topics: Vec<String>,
+    delivery_frequency: String,
+}
+
+impl Preferences {
+    fn is_subscribed(&self) -> bool {
+        self.status.is_subscribed()
+    }
+}
+
+#[tracing::instrument(name = "Fetch the preferences of a subscriber", skip(pool))]
+async fn get_preferences(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<Preferences>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT email, name, status, topics, delivery_frequency
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(pool)
+    .await?;
+    row.map(|row| {
+        Ok(Preferences {
+            email: row.email,
+            name: row.name,
+            status: decode_status(&row.status)?,
+            topics: row.topics,
+            delivery_frequency: row.delivery_frequency,
+        })
+    })
+    .transpose()
+}
+
+#[tracing::instrument(name = "Save the preferences of a subscriber", skip(transaction, update))]
+async fn save_preferences(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    update: &PreferencesUpdate,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET name = $2, topics = $3, delivery_frequency = $4,
+            -- Issues up to now went out straight away, the first digest
+            -- starts from here.
+            digest_covered_until = CASE
+                WHEN delivery_frequency = $5 THEN now()
+                ELSE digest_covered_until
+            END
+        WHERE id = $1
+        "#,
+        subscriber_id,
+        update.name.as_ref(),
+        &update.topics,
+        update.delivery_frequency.as_str(),
+        DeliveryFrequency::Immediately.as_str(),
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+// Only the latest request is honoured, earlier links stop working. Only the
+// hash of the token is stored, as for subscription tokens.
+#[tracing::instrument(name = "Store an email change request", skip(transaction, new_email))]
+async fn request_email_change(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    new_email: &SubscriberEmail,
+    ttl: chrono::Duration,
+) -> Result<String, sqlx::Error> {
+    delete_email_change_requests(transaction, subscriber_id).await?;
+    let token = generate_subscription_token();
+    let issued_at = Utc::now();
+    sqlx::query!(
+        r#"
+        INSERT INTO email_change_requests (token_hash, subscriber_id, new_email, issued_at, expires_at)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        hash_subscription_token(&token),
+        subscriber_id,
+        new_email.as_ref(),
+        issued_at,
+        issued_at + ttl,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(token)
+}
+
+struct EmailChangeRequest {
+    subscriber_id: Uuid,
+    new_email: String,
+    expires_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Fetch an email change request", skip(transaction, token))]
+async fn get_email_change_request(
+    transaction: &mut Transaction<'_, Postgres>,
+    token: &str,
+) -> Result<Option<EmailChangeRequest>, sqlx::Error> {
+    sqlx::query_as!(
+        EmailChangeRequest,
+        r#"
+        SELECT subscriber_id, new_email, expires_at
+        FROM email_change_requests
+        WHERE token_hash = $1
+        FOR UPDATE
+        "#,
+        hash_subscription_token(token),
+    )
+    .fetch_optional(transaction)
+    .await
+}
+
+#[tracing::instrument(name = "Delete the email change requests of a subscriber", skip(transaction))]
+async fn delete_email_change_requests(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"DELETE FROM email_change_requests WHERE subscriber_id = $1"#,
+        subscriber_id,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Change the email of a subscriber", skip(transaction, new_email))]
+async fn change_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    new_email: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE subscriptions SET email = $2 WHERE id = $1"#,
+        subscriber_id,
+        new_email,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+// Pending subscribers can opt out too, their confirmation link must then
+// stop working. Suppressed addresses stay suppressed. Returns whether the
+// status changed.
+#[tracing::instrument(name = "Mark a subscriber as unsubscribed", skip(transaction))]
+pub(crate) async fn mark_as_unsubscribed(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let unsubscribed = set_subscription_status(&mut *transaction, subscriber_id, SubscriptionStatus::Unsubscribed).await?;
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    delete_email_change_requests(transaction, subscriber_id).await?;
+    stop_enrollments(transaction, subscriber_id).await?;
+    Ok(unsubscribed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{preferences_token, subscriber_id_from_token};
+    use axum_extra::extract::cookie::Key;
+    use uuid::Uuid;
+
+    #[test]
+    fn a_token_leads_back_to_its_subscriber() {
+        let key = Key::generate();
+        let subscriber_id = Uuid::new_v4();
+        let token = preferences_token(&key, subscriber_id);
+        assert_eq!(subscriber_id_from_token(&key, &token), Some(subscriber_id));
+    }
+
+    #[test]
+    fn a_token_for_another_subscriber_cannot_be_forged() {
+        let key = Key::generate();
+        let token = preferences_token(&key, Uuid::new_v4());
+        let (_, tag) = token.split_once('.').unwrap();
+        let forged = format!("{}.{}", Uuid::new_v4().simple(), tag);
+        assert_eq!(subscriber_id_from_token(&key, &forged), None);
+    }
+
+    #[test]
+    fn a_token_signed_with_another_key_is_rejected() {
+        let token = preferences_token(&Key::generate(), Uuid::new_v4());
+        assert_eq!(subscriber_id_from_token(&Key::generate(), &token), None);
+        assert_eq!(subscriber_id_from_token(&Key::generate(), "garbage"), None);
+    }
+}
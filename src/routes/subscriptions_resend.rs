@@ -0,0 +1,74 @@
+use anyhow::Context;
+use axum::extract::{Form, State};
+use axum::response::{Html, IntoResponse, Response};
+use http::StatusCode;
+use serde::Deserialize;
+
+use crate::domain::SubscriberEmail;
+use crate::routes::{branded_page, error_chain_fmt};
+use crate::startup::AppState;
+use crate::subscriber_email_queue::{queue_email_to_address, QueuedEmail};
+
+#[derive(thiserror::Error)]
+pub enum ResendConfirmationError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("Too many confirmation emails were requested for this address.")]
+    RateLimited,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ResendConfirmationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for ResendConfirmationError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::ValidationError(_) => StatusCode::BAD_REQUEST.into_response(),
+            Self::RateLimited => StatusCode::TOO_MANY_REQUESTS.into_response(),
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ResendFormData {
+    email: String,
+}
+
+// The request does the same work whether or not the address is waiting for
+// confirmation, so that neither the answer nor its timing can be used to
+// probe the list. The worker only sends to pending subscribers, with a fresh
+// link next to the ones they already have.
+#[tracing::instrument(
+    name = "Resend a confirmation email",
+    skip(form_data, state),
+    fields(email = %form_data.email)
+)]
+pub async fn resend_confirmation(
+    State(state): State<AppState>,
+    Form(form_data): Form<ResendFormData>,
+) -> Result<Html<String>, ResendConfirmationError> {
+    let email = SubscriberEmail::parse(form_data.email)
+        .map_err(ResendConfirmationError::ValidationError)?;
+    // Limited per address, known or not, for the same reason.
+    if !state.resend_rate_limiter.check(&email.as_ref().to_lowercase()) {
+        return Err(ResendConfirmationError::RateLimited);
+    }
+    queue_email_to_address(&state.db_pool, &email, QueuedEmail::Confirmation)
+        .await
+        .context("Failed to queue a confirmation email.")?;
+    Ok(resent_page(&state))
+}
+
+fn resent_page(state: &AppState) -> Html<String> {
+    Html(branded_page(
+        &state.branding,
+        "Check your inbox",
+        "<p>If this address is waiting to be confirmed, a new confirmation link is on its way.</p>",
+    ))
+}
@@ -0,0 +1,142 @@
+use http::header::{HeaderName, HOST, REFERER};
+use http::HeaderMap;
+
+// Free text from the client, kept to a sane size.
+const MAX_SOURCE_LENGTH: usize = 64;
+const MAX_UTM_LENGTH: usize = 200;
+const MAX_REFERRER_LENGTH: usize = 1000;
+
+/// Where a signup came from: the form or app that sent it, the page that
+/// linked to it and the campaign in its `utm_*` parameters.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Deserialize)]
+pub struct SignupAttribution {
+    pub source: Option<String>,
+    pub referrer: Option<String>,
+    pub utm_source: Option<String>,
+    pub utm_medium: Option<String>,
+    pub utm_campaign: Option<String>,
+    pub utm_term: Option<String>,
+    pub utm_content: Option<String>,
+}
+
+impl SignupAttribution {
+    /// Takes each value from the request body first, then from the query
+    /// string, then, for the referrer, from the `Referer` header. A referrer
+    /// on this site says nothing about where the subscriber came from, so it
+    /// is dropped.
+    pub fn collect(body: Self, query: Self, headers: &HeaderMap, base_url: &str) -> Self {
+        let header = |name: HeaderName| headers.get(name).and_then(|value| value.to_str().ok());
+        let referer_header = header(REFERER).map(str::to_owned);
+        let is_this_site = |referrer: &str| {
+            referrer.starts_with(base_url)
+                || header(HOST).is_some_and(|host| authority(referrer).eq_ignore_ascii_case(host))
+        };
+        Self {
+            source: first_value([body.source, query.source], MAX_SOURCE_LENGTH),
+            referrer: first_value([body.referrer, query.referrer, referer_header], MAX_REFERRER_LENGTH)
+                .filter(|referrer| !is_this_site(referrer)),
+            utm_source: first_value([body.utm_source, query.utm_source], MAX_UTM_LENGTH),
+            utm_medium: first_value([body.utm_medium, query.utm_medium], MAX_UTM_LENGTH),
+            utm_campaign: first_value([body.utm_campaign, query.utm_campaign], MAX_UTM_LENGTH),
+            utm_term: first_value([body.utm_term, query.utm_term], MAX_UTM_LENGTH),
+            utm_content: first_value([body.utm_content, query.utm_content], MAX_UTM_LENGTH),
+        }
+    }
+
+    /// The values a page should pass on to `subscribe`, by field name.
+    pub fn fields(&self) -> Vec<(&'static str, &str)> {
+        [
+            ("source", &self.source),
+            ("referrer", &self.referrer),
+            ("utm_source", &self.utm_source),
+            ("utm_medium", &self.utm_medium),
+            ("utm_campaign", &self.utm_campaign),
+            ("utm_term", &self.utm_term),
+            ("utm_content", &self.utm_content),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value.as_deref()?)))
+        .collect()
+    }
+}
+
+// The first value that isn't blank, trimmed and truncated.
+fn first_value<const N: usize>(values: [Option<String>; N], max_length: usize) -> Option<String> {
+    values
+        .into_iter()
+        .flatten()
+        .map(|value| value.trim().chars().take(max_length).collect::<String>())
+        .find(|value| !value.is_empty())
+}
+
+// The `host:port` of a URL, empty if it has none.
+fn authority(url: &str) -> &str {
+    let Some((_, rest)) = url.split_once("://") else {
+        return "";
+    };
+    rest.split(['/', '?', '#']).next().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SignupAttribution;
+    use http::header::{HOST, REFERER};
+    use http::HeaderMap;
+
+    const BASE_URL: &str = "https://news.example.com";
+
+    fn referer(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(REFERER, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn the_body_wins_over_the_query_string_and_the_header() {
+        let body = SignupAttribution {
+            utm_source: Some("newsletter".into()),
+            referrer: Some("https://blog.example.org/post".into()),
+            ..Default::default()
+        };
+        let query = SignupAttribution {
+            utm_source: Some("twitter".into()),
+            utm_campaign: Some("launch".into()),
+            ..Default::default()
+        };
+
+        let attribution = SignupAttribution::collect(body, query, &referer("https://other.example.org"), BASE_URL);
+
+        assert_eq!(attribution.utm_source.as_deref(), Some("newsletter"));
+        assert_eq!(attribution.utm_campaign.as_deref(), Some("launch"));
+        assert_eq!(attribution.referrer.as_deref(), Some("https://blog.example.org/post"));
+    }
+
+    #[test]
+    fn blank_values_and_our_own_pages_are_left_out() {
+        let body = SignupAttribution { source: Some("  ".into()), ..Default::default() };
+
+        let attribution =
+            SignupAttribution::collect(body, Default::default(), &referer("https://news.example.com/"), BASE_URL);
+
+        assert_eq!(attribution, SignupAttribution::default());
+    }
+
+    #[test]
+    fn a_referrer_on_the_host_the_request_was_sent_to_is_left_out() {
+        let mut headers = referer("http://localhost:8000/?utm_source=x");
+        headers.insert(HOST, "localhost:8000".parse().unwrap());
+
+        let attribution = SignupAttribution::collect(Default::default(), Default::default(), &headers, BASE_URL);
+
+        assert_eq!(attribution.referrer, None);
+    }
+
+    #[test]
+    fn values_are_truncated() {
+        let body = SignupAttribution { source: Some("x".repeat(100)), ..Default::default() };
+
+        let attribution = SignupAttribution::collect(body, Default::default(), &HeaderMap::new(), BASE_URL);
+
+        assert_eq!(attribution.source.unwrap().len(), 64);
+    }
+}
@@ -1,20 +1,32 @@
+use anyhow::Context;
 use axum::{
     body::Body,
-    routing::{get, post, IntoMakeService},
+    extract::{connect_info::IntoMakeServiceWithConnectInfo, DefaultBodyLimit},
+    routing::{get, post},
     Router,
 };
 use hyper::server::{conn::AddrIncoming, Server};
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
-use std::net::TcpListener;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::Arc;
 use tower_http::trace::TraceLayer;
 use secrecy::{Secret, ExposeSecret};
 use axum_extra::extract::cookie::Key;
 use async_redis_session::RedisSessionStore;
 use axum_sessions::SessionLayer;
 
-use crate::configuration::{DatabaseSettings, Settings};
+use crate::configuration::{
+    BrandingSettings, DatabaseSettings, Environment, Settings, SubscriptionSettings,
+    TopicSettings,
+};
+use crate::automation_worker::AutomationWorker;
+use crate::subscriber_email_queue::SubscriberEmailWorker;
 use crate::email_client::EmailClient;
+use crate::email_normalization::normalize_stored_emails;
+use crate::bot_protection::BotProtection;
+use crate::domain_blocklist::DomainBlocklist;
+use crate::rate_limit::RateLimiter;
 use crate::middleware::RequestIdLayer;
 use crate::{routes::*, telemetry::TowerMakeSpanWithConstantId};
 
@@ -22,50 +34,85 @@ pub struct Application
 {
     pub port: u16,
     pub server: MyServer,
+    automation_worker: AutomationWorker,
+    subscriber_email_worker: SubscriberEmailWorker,
 }
 
 impl Application 
 {
-    pub async fn build(config: Settings) -> hyper::Result<Self> {
+    pub async fn build(config: Settings) -> Result<Self, anyhow::Error> {
         let db_pool = get_connection_pool(&config.database);
+        // A blocklist that can't be read stops the start, rather than the
+        // first signup.
+        let domain_blocklist = config
+            .subscriptions
+            .domain_blocklist
+            .load()
+            .context("Failed to read the domain blocklist.")?;
+        // Needs the migrations to have run, and the IDNA rules of the
+        // application to fix what they couldn't.
+        normalize_stored_emails(&db_pool)
+            .await
+            .context("Failed to normalise the stored email addresses.")?;
 
-        let sender_email = config
-            .email_client
-            .sender()
-            .expect("Invalid sender email address.");
-        let timeout = config.email_client.timeout();
-        let email_client = EmailClient::new(
-            sender_email,
-            config.email_client.base_url,
-            config.email_client.authorization_token,
-            timeout,
-        );
+        let email_client = config.email_client.client();
 
         let address = format!("{}:{}", config.application.host, config.application.port);
         let tcplistener = std::net::TcpListener::bind(address).expect("Failed to bind port");
         let port = tcplistener.local_addr().unwrap().port();
+        // Shares the email client, and with it the circuit breaker and the
+        // local mailbox, with the web server.
+        let automation_worker = AutomationWorker::new(
+            db_pool.clone(),
+            email_client.clone(),
+            config.application.base_url.clone(),
+            Key::from(config.application.hmac_secret.expose_secret().as_bytes()),
+        );
+        let subscriber_email_worker = SubscriberEmailWorker::new(
+            db_pool.clone(),
+            email_client.clone(),
+            config.application.base_url.clone(),
+            Key::from(config.application.hmac_secret.expose_secret().as_bytes()),
+            &config.subscriptions,
+        );
         let server = run(
             tcplistener, 
             db_pool, 
             email_client, 
             config.application.base_url,
             config.application.hmac_secret,
+            config.application.trust_forwarded_for,
             config.redis_uri,
+            config.application.environment,
+            config.subscriptions,
+            domain_blocklist,
+            config.branding,
         )?;
 
-        Ok(Self { port, server })
+        Ok(Self { port, server, automation_worker, subscriber_email_worker })
     }
 
     pub fn port(&self) -> u16 {
         self.port
     }
 
+    /// The worker sending automation steps, to run next to the server.
+    pub fn automation_worker(&self) -> AutomationWorker {
+        self.automation_worker.clone()
+    }
+
+    /// The worker sending queued emails to subscribers, to run next to the
+    /// server.
+    pub fn subscriber_email_worker(&self) -> SubscriberEmailWorker {
+        self.subscriber_email_worker.clone()
+    }
+
     pub async fn run_until_stopped(self) -> hyper::Result<()> {
         self.server.await
     }
 }
 
-pub type MyServer = Server<AddrIncoming, IntoMakeService<Router<(), Body>>>;
+pub type MyServer = Server<AddrIncoming, IntoMakeServiceWithConnectInfo<Router<(), Body>, SocketAddr>>;
 
 #[derive(Clone)]
 pub struct AppState {
@@ -73,18 +120,43 @@ pub struct AppState {
     pub email_client: EmailClient,
     pub base_url: String,
     pub secret: Key,
+    pub trust_forwarded_for: bool,
+    pub confirmation_token_ttl: chrono::Duration,
+    pub privacy_policy_version: String,
+    pub resend_rate_limiter: Arc<RateLimiter>,
+    pub data_export_rate_limiter: Arc<RateLimiter>,
+    pub branding: BrandingSettings,
+    pub topics: Arc<[TopicSettings]>,
+    pub domain_blocklist: Arc<DomainBlocklist>,
+    pub bot_protection: Arc<BotProtection>,
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn run(
     listener: TcpListener,
     db_pool: PgPool,
     email_client: EmailClient,
     base_url: String,
     secret: Secret<String>,
+    trust_forwarded_for: bool,
     redis_uri: Secret<String>,
+    environment: Environment,
+    subscriptions: SubscriptionSettings,
+    domain_blocklist: DomainBlocklist,
+    branding: BrandingSettings,
 ) -> Result<MyServer, hyper::Error> {
     let address = listener.local_addr().expect("Failed to get local address");
-    let app = app_router(db_pool, email_client, base_url, secret.clone());
+    let app = app_router(
+        db_pool,
+        email_client,
+        base_url,
+        secret.clone(),
+        trust_forwarded_for,
+        environment,
+        subscriptions,
+        domain_blocklist,
+        branding,
+    );
     
     let store = RedisSessionStore::new(redis_uri.expose_secret().as_str()).unwrap();
     let session_layer = SessionLayer::new(store, secret.expose_secret().as_bytes());
@@ -98,34 +170,95 @@ pub fn run(
             // A span is created for each request and ends with the response is sent
             .layer(TraceLayer::new_for_http().make_span_with(TowerMakeSpanWithConstantId))
             .layer(RequestIdLayer)
-            .into_make_service()
+            .into_make_service_with_connect_info::<SocketAddr>()
         )
     )
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn app_router(
     db_pool: PgPool, 
     email_client: EmailClient, 
     base_url: String,
     secret: Secret<String>,
+    trust_forwarded_for: bool,
+    environment: Environment,
+    subscriptions: SubscriptionSettings,
+    domain_blocklist: DomainBlocklist,
+    branding: BrandingSettings,
 ) -> Router {
+    if environment != Environment::Local && email_client.mailbox().is_some() {
+        tracing::warn!(
+            "Outgoing email is being captured outside of the local environment \
+            and will never be delivered."
+        );
+    }
+    let secret = Key::from(secret.expose_secret().as_bytes());
     let app_state = AppState {
         db_pool,
         email_client,
         base_url,
-        secret: Key::from(secret.expose_secret().as_bytes()),
+        bot_protection: Arc::new(subscriptions.bot_protection.protection(secret.clone())),
+        secret,
+        trust_forwarded_for,
+        confirmation_token_ttl: subscriptions.confirmation_token_ttl(),
+        privacy_policy_version: subscriptions.privacy_policy_version.clone(),
+        resend_rate_limiter: Arc::new(subscriptions.resend_rate_limit.limiter()),
+        data_export_rate_limiter: Arc::new(subscriptions.data_export_rate_limit.limiter()),
+        branding,
+        domain_blocklist: Arc::new(domain_blocklist),
+        topics: subscriptions.topics.into(),
     };
-    Router::new()
+    let mut router = Router::new()
         .route("/health_check", get(health_check))
+        .route("/metrics", get(metrics))
         .route("/", get(home))
         .route("/login", get(login_form).post(login))
         .route("/subscriptions", post(subscribe))
+        .route("/api/subscriptions", post(subscribe_api))
+        .route("/api/subscriptions/form_token", get(api_form_token))
         .route("/subscriptions/confirm", get(confirm))
+        .route("/subscriptions/resend", post(resend_confirmation))
+        .route("/subscriptions/preferences", get(preferences_form).post(update_preferences))
+        .route("/subscriptions/preferences/unsubscribe", post(unsubscribe))
+        .route("/subscriptions/preferences/confirm_email", get(confirm_email_change))
+        .route("/subscriptions/preferences/erase", post(request_erasure))
+        .route("/subscriptions/erase", get(erasure_form).post(erase_subscriber_data))
+        .route("/subscriptions/data_export", get(download_data_export).post(request_data_export))
         .route("/newsletters", post(publish_newsletter))
         .route("/admin/dashboard", get(admin_dashboard))
         .route("/admin/password", get(change_password_form).post(change_password))
-        .route("/admin/logout", post(logout))
-        .with_state(app_state)
+        .route("/admin/subscribers", get(browse_subscribers))
+        .route("/admin/subscribers/:subscriber_id", get(subscriber_detail))
+        .route("/admin/subscribers/:subscriber_id/attributes", post(update_subscriber_attributes))
+        .route("/admin/subscribers/:subscriber_id/:action", post(subscriber_action))
+        .route("/admin/attributes", get(attribute_schema_page).post(add_attribute))
+        .route("/admin/attributes/:name/delete", post(delete_attribute))
+        .route("/admin/signups", get(signup_report))
+        .route("/admin/automations", get(automations_page).post(add_automation))
+        .route("/admin/automations/:automation_id", get(automation_detail))
+        .route("/admin/automations/:automation_id/steps", post(add_automation_step))
+        .route("/admin/automations/:automation_id/steps/:step_id/delete", post(delete_step))
+        .route("/admin/automations/:automation_id/:action", post(toggle_automation))
+        .route("/admin/consent", get(consent_log))
+        .route("/admin/consent/export", get(export_consent_log))
+        .route(
+            "/admin/subscribers/import",
+            get(import_subscribers_form)
+                .post(import_subscribers)
+                .layer(DefaultBodyLimit::max(MAX_IMPORT_BYTES)),
+        )
+        .route("/admin/subscribers/imports/:import_id/errors", get(download_import_errors))
+        .route("/admin/subscribers/export", get(export_subscribers_form))
+        .route("/admin/subscribers/export/download", get(export_subscribers))
+        .route("/admin/logout", post(logout));
+    if environment == Environment::Local {
+        router = router
+            .route("/dev/mailbox", get(mailbox))
+            .route("/dev/mailbox/clear", post(clear_mailbox))
+            .route("/dev/mailbox/:message_id", get(mailbox_message));
+    }
+    router.with_state(app_state)
 }
 
 pub fn get_connection_pool(config: &DatabaseSettings) -> PgPool {
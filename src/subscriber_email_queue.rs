@@ -0,0 +1,381 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use axum_extra::extract::cookie::Key;
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::attribute_schema::get_attribute_schema;
+use crate::configuration::SubscriptionSettings;
+use crate::background_worker::{self, ExecutionOutcome, FailedTask, TaskError};
+use crate::domain::{DeliveryFrequency, SubscriberEmail, SubscriptionStatus};
+use crate::email_client::EmailClient;
+use crate::email_delivery::{record_email_delivery, EmailKind};
+use crate::newsletter_digest::{cover_digest, digest_email, get_digest_issues, queue_due_digests};
+use crate::newsletter_template::Recipient;
+use crate::routes::{
+    data_export_link, decode_status, delete_expired_subscription_tokens, erasure_link, generate_subscription_token,
+    preferences_link, send_already_subscribed_email, send_confirmation_email, send_data_export_link,
+    send_erasure_link, store_token, suppress_subscriber,
+};
+
+/// An email the worker sends to a subscriber.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueuedEmail {
+    /// With a fresh confirmation link, while they are still pending.
+    Confirmation,
+    /// A confirmed subscriber signed up again.
+    AlreadySubscribed,
+    /// The issues published since the last one, for weekly and monthly
+    /// subscribers.
+    Digest,
+    /// A link to download everything held about them.
+    DataExport,
+    /// A link to confirm deleting everything held about them.
+    ErasureLink,
+}
+
+impl QueuedEmail {
+    const ALL: [Self; 5] = [
+        Self::Confirmation,
+        Self::AlreadySubscribed,
+        Self::Digest,
+        Self::DataExport,
+        Self::ErasureLink,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Confirmation => "confirmation",
+            Self::AlreadySubscribed => "already_subscribed",
+            Self::Digest => "digest",
+            Self::DataExport => "data_export",
+            Self::ErasureLink => "erasure_link",
+        }
+    }
+
+    fn parse(s: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|email| email.as_str() == s)
+            .ok_or_else(|| format!("{} is not a known queued email", s))
+    }
+
+    // Whether the email still makes sense by the time it is sent, e.g. they
+    // may have confirmed through an earlier link in the meantime.
+    fn is_wanted(&self, status: SubscriptionStatus, frequency: DeliveryFrequency) -> bool {
+        match self {
+            Self::Confirmation => status == SubscriptionStatus::PendingConfirmation,
+            Self::AlreadySubscribed => status == SubscriptionStatus::Confirmed,
+            Self::Digest => status == SubscriptionStatus::Confirmed && frequency != DeliveryFrequency::Immediately,
+            // Whoever asked is owed an answer, whether or not they still
+            // get the newsletter.
+            Self::DataExport | Self::ErasureLink => true,
+        }
+    }
+}
+
+/// Queues `email` for each of the subscribers, to be sent as soon as the
+/// worker gets to it. One that is already waiting is not queued twice, and
+/// subscribers that no longer exist are skipped.
+#[tracing::instrument(name = "Queue emails to subscribers", skip(executor, subscriber_ids))]
+pub async fn queue_emails(
+    executor: impl PgExecutor<'_>,
+    subscriber_ids: &[Uuid],
+    email: QueuedEmail,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_email_queue (subscriber_id, kind, queued_at, due_at)
+        SELECT id, $2, now(), now()
+        FROM subscriptions
+        WHERE id = ANY($1)
+        ON CONFLICT (subscriber_id, kind) DO NOTHING
+        "#,
+        subscriber_ids,
+        email.as_str(),
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Queues `email` for the subscriber with this address, if there is one.
+/// Whether there is takes the same single statement either way.
+#[tracing::instrument(name = "Queue an email to an address", skip(executor, address), fields(address = %address))]
+pub async fn queue_email_to_address(
+    executor: impl PgExecutor<'_>,
+    address: &SubscriberEmail,
+    email: QueuedEmail,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_email_queue (subscriber_id, kind, queued_at, due_at)
+        SELECT id, $2, now(), now()
+        FROM subscriptions
+        WHERE email_canonical = lower($1)
+        ON CONFLICT (subscriber_id, kind) DO NOTHING
+        "#,
+        address.as_ref(),
+        email.as_str(),
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Sends the queued emails, next to the web server.
+#[derive(Clone)]
+pub struct SubscriberEmailWorker {
+    pool: PgPool,
+    email_client: EmailClient,
+    base_url: String,
+    key: Key,
+    confirmation_token_ttl: chrono::Duration,
+    data_export_link_ttl: chrono::Duration,
+    erasure_link_ttl: chrono::Duration,
+}
+
+impl SubscriberEmailWorker {
+    pub fn new(
+        pool: PgPool,
+        email_client: EmailClient,
+        base_url: String,
+        key: Key,
+        subscriptions: &SubscriptionSettings,
+    ) -> Self {
+        Self {
+            pool,
+            email_client,
+            base_url,
+            key,
+            confirmation_token_ttl: subscriptions.confirmation_token_ttl(),
+            data_export_link_ttl: subscriptions.data_export_link_ttl(),
+            erasure_link_ttl: subscriptions.erasure_link_ttl(),
+        }
+    }
+
+    pub async fn run_until_stopped(self) -> Result<(), anyhow::Error> {
+        let worker = &self;
+        background_worker::run_until_stopped(
+            move || worker.try_execute_task(),
+            Duration::from_secs(1),
+            "a queued email",
+        )
+        .await
+    }
+
+    /// Sends one queued email that is due, if there is any. Several workers
+    /// can run at once, each email is only picked up by one of them. Once
+    /// nothing else is waiting, expired confirmation tokens are deleted and
+    /// the digests that are due are queued.
+    #[tracing::instrument(
+        skip_all,
+        fields(subscriber_id = tracing::field::Empty, kind = tracing::field::Empty),
+        err
+    )]
+    pub async fn try_execute_task(&self) -> Result<ExecutionOutcome, anyhow::Error> {
+        let mut transaction = self.pool.begin().await.context("Failed to acquire a database connection.")?;
+        let Some(task) = dequeue_task(&mut transaction).await.context("Failed to dequeue an email.")? else {
+            delete_expired_subscription_tokens(&self.pool)
+                .await
+                .context("Failed to delete expired subscription tokens.")?;
+            let queued = queue_due_digests(&self.pool).await.context("Failed to queue the digests that are due.")?;
+            return Ok(if queued > 0 { ExecutionOutcome::TaskCompleted } else { ExecutionOutcome::EmptyQueue });
+        };
+        tracing::Span::current()
+            .record("subscriber_id", tracing::field::display(task.subscriber_id))
+            .record("kind", task.email.as_str());
+        let retry = if !task.email.is_wanted(task.subscriber_status, task.delivery_frequency) {
+            tracing::info!(status = %task.subscriber_status, "The queued email is no longer needed.");
+            None
+        } else {
+            match self.send(&mut transaction, &task).await {
+                Ok(()) => None,
+                Err(e) => match e.into_failed_task(task.attempts, "a queued email") {
+                    FailedTask::GaveUp { .. } => None,
+                    FailedTask::RetryIn { minutes, error } => Some((minutes, error)),
+                },
+            }
+        };
+        match retry {
+            // A digest that was given up on is not sent again with the next
+            // one either.
+            None if task.email == QueuedEmail::Digest => {
+                match cover_digest(&mut transaction, task.subscriber_id, task.queued_at).await {
+                    Ok(()) => delete_task(&mut transaction, &task).await,
+                    Err(e) => Err(e),
+                }
+            }
+            None => delete_task(&mut transaction, &task).await,
+            Some((minutes, error)) => retry_task(&mut transaction, &task, minutes, &error).await,
+        }
+        .context("Failed to record the outcome of a queued email.")?;
+        transaction.commit().await.context("Failed to commit SQL transaction for a queued email.")?;
+        Ok(ExecutionOutcome::TaskCompleted)
+    }
+
+    async fn send(&self, transaction: &mut Transaction<'_, Postgres>, task: &Task) -> Result<(), TaskError> {
+        let email = SubscriberEmail::parse(task.address.clone())
+            .map_err(|e| TaskError::Permanent(anyhow::anyhow!(e).context("The stored email address is invalid.")))?;
+        let (result, kind) = match task.email {
+            QueuedEmail::Confirmation => {
+                // Only its hash is stored, so the token is made right before
+                // it goes out. Earlier confirmation emails keep working until
+                // they expire.
+                let token = generate_subscription_token();
+                store_token(transaction, task.subscriber_id, &token, self.confirmation_token_ttl)
+                    .await
+                    .context("Failed to store a confirmation token.")
+                    .map_err(TaskError::Transient)?;
+                let result = send_confirmation_email(&email, &self.email_client, &self.base_url, &token).await;
+                (result, EmailKind::Confirmation)
+            }
+            QueuedEmail::AlreadySubscribed => {
+                let preferences_link = preferences_link(&self.base_url, &self.key, task.subscriber_id);
+                let result = send_already_subscribed_email(&email, &self.email_client, &preferences_link).await;
+                (result, EmailKind::AlreadySubscribed)
+            }
+            QueuedEmail::DataExport => {
+                // Signed with its expiry when it goes out, nothing is stored.
+                let link = data_export_link(&self.base_url, &self.key, task.subscriber_id, self.data_export_link_ttl);
+                (send_data_export_link(&email, &self.email_client, &link).await, EmailKind::DataExport)
+            }
+            QueuedEmail::ErasureLink => {
+                let link = erasure_link(&self.base_url, &self.key, task.subscriber_id, self.erasure_link_ttl);
+                (send_erasure_link(&email, &self.email_client, &link).await, EmailKind::ErasureLink)
+            }
+            QueuedEmail::Digest => {
+                // Covers what was published by the time it was queued, so a
+                // retry doesn't pick up issues the next one will have.
+                let issues = get_digest_issues(&mut *transaction, task.subscriber_id, task.queued_at)
+                    .await
+                    .context("Failed to gather the issues of a digest.")
+                    .map_err(TaskError::Transient)?;
+                let schema = get_attribute_schema(&mut *transaction).await.map_err(TaskError::Transient)?;
+                let empty = serde_json::Map::new();
+                let recipient = Recipient {
+                    name: &task.name,
+                    email: &task.address,
+                    attributes: task.attributes.as_object().unwrap_or(&empty),
+                };
+                let preferences_link = preferences_link(&self.base_url, &self.key, task.subscriber_id);
+                let message = digest_email(
+                    email.clone(),
+                    task.delivery_frequency,
+                    &issues,
+                    &schema,
+                    &recipient,
+                    &preferences_link,
+                )
+                .map_err(|e| TaskError::Permanent(anyhow::Error::new(e)))?;
+                let Some(message) = message else {
+                    // Nothing was published for them in the meantime.
+                    return Ok(());
+                };
+                (self.email_client.send(&message).await, EmailKind::Digest)
+            }
+        };
+        let receipt = match result {
+            Ok(receipt) => receipt,
+            Err(e) if e.is_undeliverable_recipient() => {
+                suppress_subscriber(&self.pool, &email)
+                    .await
+                    .context("Failed to suppress an undeliverable subscriber.")
+                    .map_err(TaskError::Transient)?;
+                return Err(TaskError::Permanent(anyhow::Error::new(e)));
+            }
+            Err(e) => return Err(TaskError::Transient(anyhow::Error::new(e))),
+        };
+        // The email is already on its way, so losing track of it must not get
+        // it sent again.
+        if let Err(e) = record_email_delivery(&self.pool, task.subscriber_id, kind, &receipt).await {
+            tracing::error!(error.cause_chain = ?e, "Failed to record a queued email delivery.");
+        }
+        Ok(())
+    }
+}
+
+struct Task {
+    subscriber_id: Uuid,
+    email: QueuedEmail,
+    attempts: i32,
+    queued_at: DateTime<Utc>,
+    subscriber_status: SubscriptionStatus,
+    delivery_frequency: DeliveryFrequency,
+    address: String,
+    name: String,
+    attributes: serde_json::Value,
+}
+
+#[tracing::instrument(skip_all)]
+async fn dequeue_task(transaction: &mut Transaction<'_, Postgres>) -> Result<Option<Task>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            q.subscriber_id, q.kind, q.attempts, q.queued_at,
+            s.status, s.delivery_frequency, s.email, s.name, s.attributes
+        FROM subscriber_email_queue q
+        JOIN subscriptions s ON s.id = q.subscriber_id
+        WHERE q.due_at <= now()
+        ORDER BY q.due_at
+        FOR UPDATE OF q SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(transaction)
+    .await?;
+    row.map(|row| {
+        Ok(Task {
+            subscriber_id: row.subscriber_id,
+            email: QueuedEmail::parse(&row.kind).map_err(|e| sqlx::Error::Decode(e.into()))?,
+            attempts: row.attempts,
+            queued_at: row.queued_at,
+            subscriber_status: decode_status(&row.status)?,
+            delivery_frequency: DeliveryFrequency::parse(&row.delivery_frequency)
+                .map_err(|e| sqlx::Error::Decode(e.into()))?,
+            address: row.email,
+            name: row.name,
+            attributes: row.attributes,
+        })
+    })
+    .transpose()
+}
+
+#[tracing::instrument(skip(transaction, task))]
+async fn delete_task(transaction: &mut Transaction<'_, Postgres>, task: &Task) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM subscriber_email_queue WHERE subscriber_id = $1 AND kind = $2",
+        task.subscriber_id,
+        task.email.as_str(),
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(transaction, task))]
+async fn retry_task(
+    transaction: &mut Transaction<'_, Postgres>,
+    task: &Task,
+    minutes: i32,
+    error: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscriber_email_queue
+        SET attempts = attempts + 1,
+            last_error = $3,
+            due_at = now() + make_interval(mins => $4)
+        WHERE subscriber_id = $1 AND kind = $2
+        "#,
+        task.subscriber_id,
+        task.email.as_str(),
+        error,
+        minutes,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
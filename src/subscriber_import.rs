@@ -0,0 +1,293 @@
+use csv_core::ReadRecordResult;
+
+use std::collections::HashMap;
+
+use crate::domain::{AttributeSchema, NewSubscriber, SubscriberEmail, SubscriberName, SubscriptionStatus};
+use crate::domain_blocklist::DomainBlocklist;
+
+/// How imported subscribers join the list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportMode {
+    /// They already confirmed on the old platform, the admin attests to it.
+    Confirmed,
+    /// They get a confirmation email, like after signing up through the form.
+    SendConfirmation,
+}
+
+impl ImportMode {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "confirmed" => Ok(Self::Confirmed),
+            "send_confirmation" => Ok(Self::SendConfirmation),
+            other => Err(format!("{} is not a valid import mode", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Confirmed => "confirmed",
+            Self::SendConfirmation => "send_confirmation",
+        }
+    }
+
+    /// The status imported subscribers start with.
+    pub fn subscription_status(&self) -> SubscriptionStatus {
+        match self {
+            Self::Confirmed => SubscriptionStatus::Confirmed,
+            Self::SendConfirmation => SubscriptionStatus::PendingConfirmation,
+        }
+    }
+}
+
+/// A row of the uploaded file, numbered like in a spreadsheet: the header is
+/// row 1.
+#[derive(Debug)]
+pub struct CsvRecord {
+    pub row: u64,
+    pub fields: Result<Vec<String>, String>,
+}
+
+/// Turns a CSV file arriving in chunks into records, without holding on to
+/// more than the record being read.
+pub struct CsvRecordReader {
+    reader: csv_core::Reader,
+    output: Vec<u8>,
+    ends: Vec<usize>,
+    output_len: usize,
+    ends_len: usize,
+    rows: u64,
+}
+
+impl Default for CsvRecordReader {
+    fn default() -> Self {
+        Self {
+            reader: csv_core::Reader::new(),
+            output: vec![0; 1024],
+            ends: vec![0; 16],
+            output_len: 0,
+            ends_len: 0,
+            rows: 0,
+        }
+    }
+}
+
+impl CsvRecordReader {
+    /// Reads the records completed by `chunk`.
+    pub fn read(&mut self, chunk: &[u8]) -> Vec<CsvRecord> {
+        let mut records = Vec::new();
+        // An empty input means the end of the file to csv_core.
+        if !chunk.is_empty() {
+            self.read_input(chunk, &mut records);
+        }
+        records
+    }
+
+    /// Reads whatever is left once the file has ended.
+    pub fn finish(&mut self) -> Vec<CsvRecord> {
+        let mut records = Vec::new();
+        self.read_input(&[], &mut records);
+        records
+    }
+
+    fn read_input(&mut self, mut input: &[u8], records: &mut Vec<CsvRecord>) {
+        loop {
+            let (result, read, written, ends) = self.reader.read_record(
+                input,
+                &mut self.output[self.output_len..],
+                &mut self.ends[self.ends_len..],
+            );
+            input = &input[read..];
+            self.output_len += written;
+            self.ends_len += ends;
+            match result {
+                ReadRecordResult::InputEmpty => return,
+                ReadRecordResult::OutputFull => self.output.resize(self.output.len() * 2, 0),
+                ReadRecordResult::OutputEndsFull => self.ends.resize(self.ends.len() * 2, 0),
+                ReadRecordResult::Record => records.push(self.take_record()),
+                ReadRecordResult::End => return,
+            }
+        }
+    }
+
+    fn take_record(&mut self) -> CsvRecord {
+        self.rows += 1;
+        let mut start = 0;
+        let fields = self.ends[..self.ends_len]
+            .iter()
+            .map(|&end| {
+                let field = std::str::from_utf8(&self.output[start..end]).map(str::to_string);
+                start = end;
+                field
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| "The row is not valid UTF-8".to_string());
+        self.output_len = 0;
+        self.ends_len = 0;
+        CsvRecord { row: self.rows, fields }
+    }
+}
+
+/// Where the name, the email and the custom attributes are, going by the
+/// header row. Other columns are ignored.
+#[derive(Debug)]
+pub struct ImportColumns {
+    name: usize,
+    email: usize,
+    attributes: Vec<(String, usize)>,
+}
+
+impl ImportColumns {
+    pub fn from_header(header: &[String], schema: &AttributeSchema) -> Result<Self, String> {
+        let position = |column: &str| {
+            header
+                .iter()
+                .position(|field| field.trim().eq_ignore_ascii_case(column))
+                .ok_or_else(|| format!("The header row has no {} column", column))
+        };
+        let attributes = schema
+            .definitions()
+            .iter()
+            .filter_map(|definition| Some((definition.name.clone(), position(&definition.name).ok()?)))
+            .collect();
+        Ok(Self {
+            name: position("name")?,
+            email: position("email")?,
+            attributes,
+        })
+    }
+
+    /// The name and email of a row as they were in the file.
+    fn raw<'a>(&self, fields: &'a [String]) -> (&'a str, &'a str) {
+        let field = |i: usize| fields.get(i).map(|f| f.as_str()).unwrap_or("");
+        (field(self.name), field(self.email))
+    }
+
+    /// Validates a row the same way the subscription form does.
+    pub fn parse(
+        &self,
+        fields: &[String],
+        blocklist: &DomainBlocklist,
+        schema: &AttributeSchema,
+    ) -> Result<NewSubscriber, String> {
+        let (name, email) = self.raw(fields);
+        let name = SubscriberName::parse(name.trim().to_string())?;
+        // The reason ends up in the error report, which keeps no addresses.
+        let email = SubscriberEmail::parse(email.trim().to_string())
+            .map_err(|_| "invalid email address".to_string())?;
+        blocklist.check(&email)?;
+        let attributes: HashMap<String, String> = self
+            .attributes
+            .iter()
+            .filter_map(|(attribute, i)| Some((attribute.clone(), fields.get(*i)?.clone())))
+            .collect();
+        let attributes = schema.parse_form(&attributes).map_err(|errors| {
+            errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(", ")
+        })?;
+        Ok(NewSubscriber { name, email, attributes })
+    }
+}
+
+/// A row that was not imported, and why.
+#[derive(Debug)]
+pub struct RowError {
+    pub row: u64,
+    pub reason: String,
+}
+
+/// The rows that were not imported, as a CSV file that points the admin at
+/// the rows of their own file to fix and upload again. It is stored with the
+/// import, so it holds no names or addresses: nothing would remove them when
+/// one of those people asks to be erased.
+pub fn error_report(errors: &[RowError]) -> Result<String, anyhow::Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(["row", "error"])?;
+    for error in errors {
+        writer.write_record([&error.row.to_string(), &error.reason])?;
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CsvRecordReader, ImportColumns};
+    use crate::domain::{AttributeDefinition, AttributeKind, AttributeSchema};
+    use crate::domain_blocklist::DomainBlocklist;
+
+    fn read_in_chunks(input: &str, chunk_size: usize) -> Vec<Vec<String>> {
+        let mut reader = CsvRecordReader::default();
+        let mut records = Vec::new();
+        for chunk in input.as_bytes().chunks(chunk_size) {
+            records.extend(reader.read(chunk));
+        }
+        records.extend(reader.finish());
+        records.into_iter().map(|record| record.fields.unwrap()).collect()
+    }
+
+    #[test]
+    fn records_split_across_chunks_are_read_whole() {
+        let input = "name,email\n\"Le Guin, Ursula\",ursula@earthsea.org\r\nged,\"ged\n@roke\"\nlast,line";
+        let expected = read_in_chunks(input, input.len());
+
+        for chunk_size in 1..8 {
+            assert_eq!(read_in_chunks(input, chunk_size), expected);
+        }
+        assert_eq!(expected.len(), 4);
+        assert_eq!(expected[1], vec!["Le Guin, Ursula", "ursula@earthsea.org"]);
+        assert_eq!(expected[2], vec!["ged", "ged\n@roke"]);
+        assert_eq!(expected[3], vec!["last", "line"]);
+    }
+
+    #[test]
+    fn rows_are_numbered_like_in_a_spreadsheet() {
+        let mut reader = CsvRecordReader::default();
+        let mut records = reader.read(b"name,email\na,b\n");
+        records.extend(reader.finish());
+
+        assert_eq!(records.iter().map(|r| r.row).collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    #[test]
+    fn a_row_that_is_not_utf8_is_an_error() {
+        let mut reader = CsvRecordReader::default();
+        let records = reader.read(b"\xff,b\n");
+
+        assert!(records[0].fields.is_err());
+    }
+
+    #[test]
+    fn columns_are_found_by_their_header_in_any_order_and_case() {
+        let header = vec!["Email ".to_string(), "created".to_string(), "NAME".to_string()];
+        let columns = ImportColumns::from_header(&header, &AttributeSchema::default()).unwrap();
+        let row = vec!["ursula@earthsea.org".to_string(), "2020".to_string(), "le guin".to_string()];
+
+        let subscriber = columns.parse(&row, &DomainBlocklist::default(), &AttributeSchema::default()).unwrap();
+
+        assert_eq!(subscriber.name.as_ref(), "le guin");
+        assert_eq!(subscriber.email.as_ref(), "ursula@earthsea.org");
+    }
+
+    #[test]
+    fn columns_named_after_an_attribute_are_imported_as_one() {
+        let schema = AttributeSchema::new(vec![AttributeDefinition {
+            name: "employees".into(),
+            kind: AttributeKind::Number,
+            required: false,
+        }]);
+        let header = vec!["name".to_string(), "email".to_string(), "Employees".to_string()];
+        let columns = ImportColumns::from_header(&header, &schema).unwrap();
+        let blocklist = DomainBlocklist::default();
+        let row = |employees: &str| vec!["le guin".to_string(), "ursula@earthsea.org".to_string(), employees.to_string()];
+
+        let subscriber = columns.parse(&row("12"), &blocklist, &schema).unwrap();
+
+        assert_eq!(subscriber.attributes.to_json(), serde_json::json!({"employees": 12.0}));
+        assert!(columns.parse(&row("a dozen"), &blocklist, &schema).is_err());
+    }
+
+    #[test]
+    fn a_header_without_an_email_column_is_rejected() {
+        let header = vec!["name".to_string(), "address".to_string()];
+
+        assert!(ImportColumns::from_header(&header, &AttributeSchema::default()).is_err());
+    }
+}
@@ -0,0 +1,224 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn insert_subscriber(app: &TestApp, status: &str) -> uuid::Uuid {
+    let subscriber_id = uuid::Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, 'ursula_le_guin@gmail.com', 'le guin', now(), $2)
+        "#,
+        subscriber_id,
+        status,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    subscriber_id
+}
+
+// Signs up through the form, leaving a token, a delivery and a consent record.
+async fn sign_up(app: &TestApp) -> uuid::Uuid {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+async fn act(app: &TestApp, subscriber_id: uuid::Uuid, action: &str) -> reqwest::Response {
+    act_with(app, subscriber_id, action, &[]).await
+}
+
+async fn act_with(
+    app: &TestApp,
+    subscriber_id: uuid::Uuid,
+    action: &str,
+    fields: &[(&str, &str)],
+) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/admin/subscribers/{}/{}", &app.address, subscriber_id, action))
+        .form(fields)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn status(app: &TestApp, subscriber_id: uuid::Uuid) -> String {
+    sqlx::query!("SELECT status FROM subscriptions WHERE id = $1", subscriber_id)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status
+}
+
+async fn detail_page(app: &TestApp, subscriber_id: uuid::Uuid) -> String {
+    app.get_admin_page(&format!("/admin/subscribers/{}", subscriber_id))
+        .await
+        .text()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_act_on_a_subscriber() {
+    let app = spawn_app().await;
+    let subscriber_id = insert_subscriber(&app, "pending_confirmation").await;
+
+    let response = act(&app, subscriber_id, "confirm").await;
+
+    assert_is_redirect_to(&response, "/login");
+    assert_eq!(status(&app, subscriber_id).await, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn an_admin_can_confirm_a_pending_subscriber() {
+    let app = spawn_app().await;
+    let subscriber_id = sign_up(&app).await;
+    app.log_in_as_admin().await;
+
+    let response = act_with(&app, subscriber_id, "confirm", &[("attestation", "Asked in person at our meetup")]).await;
+
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+    assert_eq!(status(&app, subscriber_id).await, "confirmed");
+    let consent = sqlx::query!(
+        "SELECT attested_by, attestation FROM consent_records WHERE event = 'confirmed_by_admin'"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(consent.attested_by.as_deref(), Some(app.test_user.username.as_str()));
+    assert_eq!(consent.attestation.as_deref(), Some("Asked in person at our meetup"));
+    // Their confirmation link says so too.
+    let unused_tokens =
+        sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscription_tokens WHERE used_at IS NULL"#)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .count;
+    assert_eq!(unused_tokens, 0);
+    let html = detail_page(&app, subscriber_id).await;
+    assert!(html.contains("<p><i>ursula_le_guin@gmail.com was confirmed.</i></p>"));
+    assert!(html.contains("<td>confirm</td><td>pending_confirmation -&gt; confirmed</td>"));
+    // The flash is only shown once.
+    assert!(!detail_page(&app, subscriber_id).await.contains("was confirmed."));
+}
+
+#[tokio::test]
+async fn confirming_by_hand_requires_an_attestation() {
+    let app = spawn_app().await;
+    let subscriber_id = sign_up(&app).await;
+    app.log_in_as_admin().await;
+
+    let response = act_with(&app, subscriber_id, "confirm", &[("attestation", " ")]).await;
+
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+    assert_eq!(status(&app, subscriber_id).await, "pending_confirmation");
+    assert!(detail_page(&app, subscriber_id).await.contains("needs a statement of how they consented"));
+    let consent = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM consent_records WHERE event <> 'subscribed'"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(consent, 0);
+}
+
+#[tokio::test]
+async fn an_admin_can_resend_the_confirmation_email() {
+    let app = spawn_app().await;
+    let subscriber_id = sign_up(&app).await;
+    app.log_in_as_admin().await;
+
+    let response = act(&app, subscriber_id, "resend_confirmation").await;
+
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+    assert!(detail_page(&app, subscriber_id).await.contains("A new confirmation email to"));
+    app.dispatch_all_queued_emails().await;
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    assert_eq!(email_requests.len(), 2);
+    let first_link = app.get_confirmation_links(&email_requests[0]).html;
+    let second_link = app.get_confirmation_links(&email_requests[1]).html;
+    assert_ne!(first_link, second_link);
+    assert_eq!(reqwest::get(second_link).await.unwrap().status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn an_admin_can_unsubscribe_or_suppress_a_subscriber() {
+    let app = spawn_app().await;
+    app.log_in_as_admin().await;
+
+    for (action, expected_status) in [("unsubscribe", "unsubscribed"), ("suppress", "suppressed")] {
+        sqlx::query!("DELETE FROM subscriptions").execute(&app.db_pool).await.unwrap();
+        let subscriber_id = insert_subscriber(&app, "confirmed").await;
+
+        act(&app, subscriber_id, action).await;
+
+        assert_eq!(status(&app, subscriber_id).await, expected_status);
+    }
+}
+
+#[tokio::test]
+async fn an_action_that_does_not_fit_the_status_changes_nothing() {
+    let app = spawn_app().await;
+    app.log_in_as_admin().await;
+    let subscriber_id = insert_subscriber(&app, "suppressed").await;
+
+    let response = act(&app, subscriber_id, "confirm").await;
+
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+    assert_eq!(status(&app, subscriber_id).await, "suppressed");
+    assert!(detail_page(&app, subscriber_id).await.contains("Confirm can&#x27;t be applied"));
+    let entries = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM admin_audit_log"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(entries, 0);
+}
+
+#[tokio::test]
+async fn an_unknown_action_is_not_found() {
+    let app = spawn_app().await;
+    app.log_in_as_admin().await;
+    let subscriber_id = insert_subscriber(&app, "confirmed").await;
+
+    assert_eq!(act(&app, subscriber_id, "promote").await.status().as_u16(), 404);
+    assert_eq!(act(&app, uuid::Uuid::new_v4(), "delete").await.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn deleting_a_subscriber_removes_everything_related_but_keeps_the_audit_entry() {
+    let app = spawn_app().await;
+    let subscriber_id = sign_up(&app).await;
+    app.log_in_as_admin().await;
+
+    let response = act(&app, subscriber_id, "delete").await;
+
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    for table in ["subscriptions", "subscription_tokens", "email_deliveries", "consent_records"] {
+        let count: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", table))
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 0, "{} still has rows for the deleted subscriber", table);
+    }
+    let entry = sqlx::query!("SELECT action, subscriber_id FROM admin_audit_log")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(entry.action, "delete");
+    assert_eq!(entry.subscriber_id, Some(subscriber_id));
+    let html = app.get_admin_page("/admin/subscribers").await.text().await.unwrap();
+    assert!(html.contains("ursula_le_guin@gmail.com was deleted."));
+}
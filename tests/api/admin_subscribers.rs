@@ -0,0 +1,153 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn insert_subscriber(app: &TestApp, email: &str, name: &str, status: &str, minutes_ago: i64) -> uuid::Uuid {
+    let subscriber_id = uuid::Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        subscriber_id,
+        email,
+        name,
+        chrono::Utc::now() - chrono::Duration::minutes(minutes_ago),
+        status,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    subscriber_id
+}
+
+async fn browse(app: &TestApp, query: &str) -> String {
+    let response = app.get_admin_page(&format!("/admin/subscribers?{}", query)).await;
+    assert_eq!(response.status().as_u16(), 200);
+    response.text().await.unwrap()
+}
+
+// The emails listed on a page, in order.
+fn listed_emails(html: &str) -> Vec<String> {
+    html.split(r#"<tr><td><a href="/admin/subscribers/"#)
+        .skip(1)
+        .map(|row| {
+            let start = row.find('>').unwrap() + 1;
+            let end = row.find("</a>").unwrap();
+            htmlescape::decode_html(&row[start..end]).unwrap()
+        })
+        .collect()
+}
+
+fn next_page_link(html: &str) -> Option<String> {
+    let end = html.find(r#"">Next page</a>"#)?;
+    let start = html[..end].rfind(r#"href=""#)? + r#"href=""#.len();
+    Some(htmlescape::decode_html(&html[start..end]).unwrap())
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_browse_subscribers() {
+    let app = spawn_app().await;
+    let subscriber_id = insert_subscriber(&app, "ursula@earthsea.org", "le guin", "confirmed", 1).await;
+
+    let response = app.get_admin_page("/admin/subscribers").await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app.get_admin_page(&format!("/admin/subscribers/{}", subscriber_id)).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscribers_are_listed_newest_first_by_default() {
+    let app = spawn_app().await;
+    app.log_in_as_admin().await;
+    insert_subscriber(&app, "old@example.com", "old", "confirmed", 30).await;
+    insert_subscriber(&app, "new@example.com", "new", "confirmed", 10).await;
+
+    assert_eq!(listed_emails(&browse(&app, "").await), vec!["new@example.com", "old@example.com"]);
+    assert_eq!(listed_emails(&browse(&app, "sort=oldest").await), vec!["old@example.com", "new@example.com"]);
+}
+
+#[tokio::test]
+async fn subscribers_can_be_searched_by_email_or_name_prefix_and_filtered_by_status() {
+    let app = spawn_app().await;
+    app.log_in_as_admin().await;
+    insert_subscriber(&app, "ursula@earthsea.org", "le guin", "confirmed", 40).await;
+    insert_subscriber(&app, "ged@roke.org", "Ursula's friend", "unsubscribed", 30).await;
+    insert_subscriber(&app, "tenar@atuan.org", "tenar", "confirmed", 20).await;
+    insert_subscriber(&app, "u_s@example.com", "underscore", "confirmed", 10).await;
+
+    assert_eq!(
+        listed_emails(&browse(&app, "q=URSULA&sort=oldest").await),
+        vec!["ursula@earthsea.org", "ged@roke.org"]
+    );
+    assert_eq!(listed_emails(&browse(&app, "q=ursula&status=confirmed").await), vec!["ursula@earthsea.org"]);
+    assert_eq!(listed_emails(&browse(&app, "q=u_").await), vec!["u_s@example.com"]);
+    assert_eq!(listed_emails(&browse(&app, "q=earthsea").await), Vec::<String>::new());
+}
+
+#[tokio::test]
+async fn subscribers_are_paginated_without_gaps_or_repeats() {
+    let app = spawn_app().await;
+    app.log_in_as_admin().await;
+    for i in 0..120 {
+        insert_subscriber(&app, &format!("subscriber{:03}@example.com", i), "subscriber", "confirmed", i).await;
+    }
+
+    let mut seen = Vec::new();
+    let mut html = browse(&app, "").await;
+    loop {
+        let emails = listed_emails(&html);
+        assert!(emails.len() <= 50);
+        seen.extend(emails);
+        let Some(next_page) = next_page_link(&html) else {
+            break;
+        };
+        let response = app.get_admin_page(&next_page).await;
+        html = response.text().await.unwrap();
+    }
+
+    let expected: Vec<String> = (0..120).map(|i| format!("subscriber{:03}@example.com", i)).collect();
+    assert_eq!(seen, expected);
+}
+
+#[tokio::test]
+async fn the_detail_page_shows_tokens_deliveries_and_consent_records() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    app.log_in_as_admin().await;
+
+    let response = app.get_admin_page(&format!("/admin/subscribers/{}", subscriber_id)).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    assert!(html.contains("<h1>ursula_le_guin@gmail.com</h1>"));
+    assert!(html.contains("pending_confirmation"));
+    assert!(html.contains("<td>Confirmation</td>"));
+    assert!(html.contains("<td>confirmation</td>"));
+    assert!(html.contains("<td>subscribed</td>"));
+}
+
+#[tokio::test]
+async fn the_detail_page_of_an_unknown_subscriber_is_not_found() {
+    let app = spawn_app().await;
+    app.log_in_as_admin().await;
+
+    let response = app.get_admin_page(&format!("/admin/subscribers/{}", uuid::Uuid::new_v4())).await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
@@ -0,0 +1,113 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+async fn insert_subscriber(app: &TestApp, email: &str, status: &str, subscribed_at: &str) {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status, topics)
+        VALUES ($1, $2, 'le guin', $3::text::timestamptz, $4, '{essays,announcements}')
+        "#,
+        uuid::Uuid::new_v4(),
+        email,
+        subscribed_at,
+        status,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+async fn export(app: &TestApp, query: &str) -> reqwest::Response {
+    app.get_admin_page(&format!("/admin/subscribers/export/download?{}", query)).await
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_export_subscribers() {
+    let app = spawn_app().await;
+
+    let response = export(&app, "format=csv").await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_export_is_a_csv_file_of_every_subscriber_by_default() {
+    let app = spawn_app().await;
+    app.log_in_as_admin().await;
+    insert_subscriber(&app, "ursula@earthsea.org", "confirmed", "2026-01-02T10:00:00Z").await;
+    insert_subscriber(&app, "ged@roke.org", "unsubscribed", "2026-03-04T10:00:00Z").await;
+
+    let response = export(&app, "").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Content-Type"], "text/csv; charset=utf-8");
+    assert!(response.headers()["Content-Disposition"].to_str().unwrap().starts_with("attachment"));
+    let csv = response.text().await.unwrap();
+    let rows: Vec<&str> = csv.lines().collect();
+    assert_eq!(rows.len(), 3);
+    assert_eq!(rows[0], "id,email,name,subscribed_at,status,delivery_frequency,topics,attributes");
+    assert!(rows[1].contains(",ursula@earthsea.org,le guin,2026-01-02T10:00:00+00:00,confirmed,immediately,essays;announcements"));
+    assert!(rows[2].contains(",ged@roke.org,"));
+}
+
+#[tokio::test]
+async fn the_export_can_be_filtered_by_status_and_date() {
+    let app = spawn_app().await;
+    app.log_in_as_admin().await;
+    insert_subscriber(&app, "early@example.com", "confirmed", "2026-01-01T10:00:00Z").await;
+    insert_subscriber(&app, "first-day@example.com", "confirmed", "2026-02-01T00:00:00Z").await;
+    insert_subscriber(&app, "last-day@example.com", "confirmed", "2026-02-28T23:59:00Z").await;
+    insert_subscriber(&app, "unsubscribed@example.com", "unsubscribed", "2026-02-10T10:00:00Z").await;
+    insert_subscriber(&app, "late@example.com", "confirmed", "2026-03-01T00:00:00Z").await;
+
+    let response = export(
+        &app,
+        "format=ndjson&status=confirmed&subscribed_from=2026-02-01&subscribed_to=2026-02-28",
+    )
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Content-Type"], "application/x-ndjson");
+    let body = response.text().await.unwrap();
+    let emails: Vec<String> = body
+        .lines()
+        .map(|line| {
+            let subscriber: serde_json::Value = serde_json::from_str(line).unwrap();
+            assert_eq!(subscriber["topics"], serde_json::json!(["essays", "announcements"]));
+            subscriber["email"].as_str().unwrap().to_string()
+        })
+        .collect();
+    assert_eq!(emails, vec!["first-day@example.com", "last-day@example.com"]);
+}
+
+#[tokio::test]
+async fn an_export_larger_than_a_chunk_is_streamed_whole() {
+    let app = spawn_app().await;
+    app.log_in_as_admin().await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        SELECT md5(i::text)::uuid, 'subscriber' || i || '@example.com', 'subscriber', now(), 'confirmed'
+        FROM generate_series(1, 1234) AS i
+        "#,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let csv = export(&app, "format=csv").await.text().await.unwrap();
+
+    assert_eq!(csv.lines().count(), 1235);
+    assert_eq!(csv.matches("id,email").count(), 1);
+}
+
+#[tokio::test]
+async fn invalid_filters_are_rejected() {
+    let app = spawn_app().await;
+    app.log_in_as_admin().await;
+
+    for query in ["format=xml", "subscribed_from=yesterday"] {
+        let response = export(&app, query).await;
+
+        assert_eq!(response.status().as_u16(), 400, "{} was not rejected", query);
+    }
+}
@@ -0,0 +1,217 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn insert_subscriber(app: &TestApp, email: &str) {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, $2, 'existing', now(), 'confirmed')
+        "#,
+        uuid::Uuid::new_v4(),
+        email,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+// The link to the error report on the result page.
+fn error_report_link(html: &str) -> String {
+    let start = html.find("/admin/subscribers/imports/").expect("No link to the error report");
+    let end = start + html[start..].find('"').unwrap();
+    html[start..end].to_string()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_import_subscribers() {
+    let app = spawn_app().await;
+
+    let response = app.get_admin_page("/admin/subscribers/import").await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post_subscriber_import("confirmed", "Signed up on our old platform", "name,email\nged,ged@roke.org\n")
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscribers_imported_as_confirmed_get_a_consent_record_and_no_email() {
+    let app = spawn_app().await;
+    app.log_in_as_admin().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriber_import(
+            "confirmed",
+            "Double opt-in on our old platform",
+            "Email,Name\nursula@earthsea.org,le guin\n\"ged@Roke.org\",\"Ged, Sparrowhawk\"\n",
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("Imported 2 subscribers."));
+    let saved = sqlx::query!("SELECT email, name, status FROM subscriptions ORDER BY email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 2);
+    assert_eq!(saved[0].email, "ged@roke.org");
+    assert_eq!(saved[0].name, "Ged, Sparrowhawk");
+    assert!(saved.iter().all(|s| s.status == "confirmed"));
+    let consent = sqlx::query!("SELECT event, form_source, attested_by, attestation FROM consent_records")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(consent.len(), 2);
+    assert!(consent.iter().all(|c| c.event == "imported"));
+    assert!(consent.iter().all(|c| c.attested_by.as_deref() == Some(app.test_user.username.as_str())));
+    assert!(consent
+        .iter()
+        .all(|c| c.attestation.as_deref() == Some("Double opt-in on our old platform")));
+    let attestation = sqlx::query!("SELECT attestation FROM subscriber_imports")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(attestation.attestation.as_deref(), Some("Double opt-in on our old platform"));
+}
+
+#[tokio::test]
+async fn importing_as_confirmed_requires_an_attestation() {
+    let app = spawn_app().await;
+    app.log_in_as_admin().await;
+
+    let response = app
+        .post_subscriber_import("confirmed", " ", "name,email\nged,ged@roke.org\n")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let count = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(count, 0);
+}
+
+#[tokio::test]
+async fn subscribers_imported_for_confirmation_get_a_working_confirmation_email_from_the_worker() {
+    let app = spawn_app().await;
+    app.log_in_as_admin().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriber_import(
+            "send_confirmation",
+            "",
+            "name,email\nle guin,ursula@earthsea.org\nged,ged@roke.org\n",
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    // The upload doesn't wait for the emails.
+    assert!(app.email_server.received_requests().await.unwrap().is_empty());
+    app.dispatch_all_queued_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html).await.unwrap().error_for_status().unwrap();
+    let statuses = sqlx::query!("SELECT status FROM subscriptions ORDER BY status")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(statuses[0].status, "confirmed");
+    assert_eq!(statuses[1].status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn invalid_and_duplicate_rows_end_up_in_the_error_report() {
+    let app = spawn_app().await;
+    app.log_in_as_admin().await;
+    insert_subscriber(&app, "existing@example.com").await;
+
+    let response = app
+        .post_subscriber_import(
+            "confirmed",
+            "Double opt-in on our old platform",
+            "name,email\n\
+            le guin,ursula@earthsea.org\n\
+            <script>,script@example.com\n\
+            no address,not-an-email\n\
+            again,URSULA@earthsea.org\n\
+            existing,Existing@example.com\n\
+            throwaway,someone@yopmail.com\n\
+            \n",
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    assert!(html.contains("Imported 1 subscribers."));
+    assert!(html.contains("5 rows were not imported."));
+    let report = app.get_admin_page(&error_report_link(&html)).await;
+    assert_eq!(report.status().as_u16(), 200);
+    assert_eq!(report.headers()["Content-Type"], "text/csv; charset=utf-8");
+    let report = report.text().await.unwrap();
+    let rows: Vec<&str> = report.lines().collect();
+    assert_eq!(
+        rows,
+        vec![
+            "row,error",
+            "3,invalid subscriber name",
+            "4,invalid email address",
+            "5,The address is already in the file",
+            "6,The address is already on the list",
+            "7,\"yopmail.com is a disposable email domain, please subscribe with a permanent address\"",
+        ]
+    );
+    // It is kept with the import, so it keeps no personal data.
+    for personal_data in ["script@example.com", "not-an-email", "URSULA", "Existing", "someone"] {
+        assert!(!report.contains(personal_data));
+    }
+}
+
+#[tokio::test]
+async fn a_file_without_an_email_column_is_rejected() {
+    let app = spawn_app().await;
+    app.log_in_as_admin().await;
+
+    let response = app
+        .post_subscriber_import("send_confirmation", "", "name,address\nged,ged@roke.org\n")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response.text().await.unwrap().contains("The header row has no email column"));
+}
+
+#[tokio::test]
+async fn large_files_are_imported_in_batches() {
+    let app = spawn_app().await;
+    app.log_in_as_admin().await;
+    let mut csv = String::from("name,email\n");
+    for i in 0..1200 {
+        csv.push_str(&format!("subscriber {i},subscriber{i}@example.com\n"));
+    }
+
+    let response = app.post_subscriber_import("confirmed", "Signed up at our events", &csv).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("Imported 1200 subscribers."));
+    let count = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM consent_records"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(count, 1200);
+}
@@ -0,0 +1,244 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn mock_email_server(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+// Goes through the admin pages, the way an admin would.
+async fn create_active_automation(app: &TestApp, steps: &[(&str, &str)]) -> Uuid {
+    let response = app
+        .api_client
+        .post(format!("{}/admin/automations", &app.address))
+        .form(&[("name", "Welcome series")])
+        .send()
+        .await
+        .unwrap();
+    let location = response.headers().get("Location").unwrap().to_str().unwrap().to_owned();
+    let automation_id: Uuid = location.trim_start_matches("/admin/automations/").parse().unwrap();
+    for (delay_days, subject) in steps {
+        let response = app
+            .api_client
+            .post(format!("{}/admin/automations/{}/steps", &app.address, automation_id))
+            .form(&[
+                ("delay_days", *delay_days),
+                ("delay_hours", ""),
+                ("subject", subject),
+                ("text_content", "Hi {{ name }}, welcome!"),
+                ("html_content", "<p>Hi {{ name }}, welcome!</p>"),
+            ])
+            .send()
+            .await
+            .unwrap();
+        assert_is_redirect_to(&response, &location);
+    }
+    let response = app
+        .api_client
+        .post(format!("{}/admin/automations/{}/activate", &app.address, automation_id))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, &location);
+    automation_id
+}
+
+// Returns the id of the new, confirmed subscriber.
+async fn subscribe_and_confirm(app: &TestApp) -> Uuid {
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html).await.unwrap().error_for_status().unwrap();
+    sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+// The subjects of the emails sent after the confirmation email.
+async fn sent_subjects(app: &TestApp) -> Vec<String> {
+    app.email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .skip(1)
+        .map(|request| {
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            body["Subject"].as_str().unwrap().to_owned()
+        })
+        .collect()
+}
+
+async fn step_statuses(app: &TestApp) -> Vec<String> {
+    sqlx::query!(
+        r#"
+        SELECT d.status
+        FROM automation_step_deliveries d
+        JOIN automation_steps s ON s.id = d.step_id
+        ORDER BY s.delay_hours
+        "#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|row| row.status)
+    .collect()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_automations() {
+    let app = spawn_app().await;
+
+    let response = app.get_admin_page("/admin/automations").await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .api_client
+        .post(format!("{}/admin/automations", &app.address))
+        .form(&[("name", "Welcome series")])
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_first_step_is_sent_right_after_confirming() {
+    let app = spawn_app().await;
+    app.log_in_as_admin().await;
+    mock_email_server(&app).await;
+    create_active_automation(&app, &[("0", "Welcome, {{ name }}"), ("3", "Getting the most out of it")]).await;
+
+    subscribe_and_confirm(&app).await;
+    app.dispatch_all_due_automation_steps().await;
+
+    assert_eq!(sent_subjects(&app).await, vec!["Welcome, le guin"]);
+    assert_eq!(step_statuses(&app).await, vec!["sent", "scheduled"]);
+}
+
+#[tokio::test]
+async fn later_steps_are_sent_once_they_are_due() {
+    let app = spawn_app().await;
+    app.log_in_as_admin().await;
+    mock_email_server(&app).await;
+    create_active_automation(&app, &[("0", "Welcome"), ("3", "Getting the most out of it")]).await;
+    subscribe_and_confirm(&app).await;
+    app.dispatch_all_due_automation_steps().await;
+
+    sqlx::query!("UPDATE automation_step_deliveries SET due_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_due_automation_steps().await;
+
+    assert_eq!(sent_subjects(&app).await, vec!["Welcome", "Getting the most out of it"]);
+    assert_eq!(step_statuses(&app).await, vec!["sent", "sent"]);
+}
+
+#[tokio::test]
+async fn unsubscribing_stops_the_automation() {
+    let app = spawn_app().await;
+    app.log_in_as_admin().await;
+    mock_email_server(&app).await;
+    create_active_automation(&app, &[("0", "Welcome"), ("3", "Getting the most out of it")]).await;
+    let subscriber_id = subscribe_and_confirm(&app).await;
+    app.dispatch_all_due_automation_steps().await;
+
+    app.post_unsubscribe(&app.preferences_token(subscriber_id))
+        .await
+        .error_for_status()
+        .unwrap();
+    sqlx::query!("UPDATE automation_step_deliveries SET due_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_due_automation_steps().await;
+
+    assert_eq!(sent_subjects(&app).await, vec!["Welcome"]);
+    assert_eq!(step_statuses(&app).await, vec!["sent", "skipped"]);
+}
+
+#[tokio::test]
+async fn inactive_automations_enroll_nobody() {
+    let app = spawn_app().await;
+    app.log_in_as_admin().await;
+    mock_email_server(&app).await;
+    let automation_id = create_active_automation(&app, &[("0", "Welcome")]).await;
+    app.api_client
+        .post(format!("{}/admin/automations/{}/deactivate", &app.address, automation_id))
+        .send()
+        .await
+        .unwrap();
+
+    subscribe_and_confirm(&app).await;
+    app.dispatch_all_due_automation_steps().await;
+
+    assert!(sent_subjects(&app).await.is_empty());
+    assert!(step_statuses(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn the_automation_page_tracks_each_step() {
+    let app = spawn_app().await;
+    app.log_in_as_admin().await;
+    mock_email_server(&app).await;
+    let automation_id =
+        create_active_automation(&app, &[("0", "Welcome"), ("3", "Getting the most out of it")]).await;
+    subscribe_and_confirm(&app).await;
+    app.dispatch_all_due_automation_steps().await;
+
+    let html = app
+        .get_admin_page(&format!("/admin/automations/{}", automation_id))
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    assert!(html.contains("<td>right away</td><td>Welcome</td><td>0</td><td>1</td><td>0</td><td>0</td>"));
+    assert!(html.contains("<td>3d</td><td>Getting the most out of it</td><td>1</td><td>0</td><td>0</td><td>0</td>"));
+}
+
+#[tokio::test]
+async fn steps_with_unknown_placeholders_are_rejected() {
+    let app = spawn_app().await;
+    app.log_in_as_admin().await;
+    let automation_id = create_active_automation(&app, &[]).await;
+
+    app.api_client
+        .post(format!("{}/admin/automations/{}/steps", &app.address, automation_id))
+        .form(&[
+            ("delay_days", "1"),
+            ("delay_hours", "0"),
+            ("subject", "Hi"),
+            ("text_content", "Hi {{ attributes.company }}"),
+            ("html_content", "<p>Hi</p>"),
+        ])
+        .send()
+        .await
+        .unwrap();
+
+    let steps = sqlx::query!("SELECT count(*) AS \"count!\" FROM automation_steps")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(steps.count, 0);
+    let html = app
+        .get_admin_page(&format!("/admin/automations/{}", automation_id))
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("The text content is invalid"));
+}
@@ -0,0 +1,144 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn subscribe_and_confirm(app: &TestApp, headers: &[(&str, &str)]) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let form_token = app.get_form_token().await;
+    let mut request = app
+        .api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .form(&[
+            ("name", "le guin"),
+            ("email", "ursula_le_guin@gmail.com"),
+            ("source", "footer"),
+            ("form_token", &form_token),
+        ]);
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    request.send().await.unwrap().error_for_status().unwrap();
+    app.dispatch_all_queued_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::Client::new()
+        .get(confirmation_links.html)
+        .header("User-Agent", "mail-client/2.0")
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn subscribing_and_confirming_are_recorded_in_the_consent_log() {
+    let app = spawn_app().await;
+
+    subscribe_and_confirm(&app, &[("User-Agent", "browser/1.0")]).await;
+
+    let records = sqlx::query!(
+        r#"
+        SELECT event, email, ip_address, user_agent, form_source, privacy_policy_version
+        FROM consent_records
+        ORDER BY recorded_at
+        "#,
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].event, "subscribed");
+    assert_eq!(records[0].email, "ursula_le_guin@gmail.com");
+    assert_eq!(records[0].ip_address.as_deref(), Some("127.0.0.1"));
+    assert_eq!(records[0].user_agent.as_deref(), Some("browser/1.0"));
+    assert_eq!(records[0].form_source.as_deref(), Some("footer"));
+    assert_eq!(records[0].privacy_policy_version, "2026-10-01");
+    assert_eq!(records[1].event, "confirmed");
+    assert_eq!(records[1].user_agent.as_deref(), Some("mail-client/2.0"));
+    assert_eq!(records[1].form_source, None);
+}
+
+#[tokio::test]
+async fn the_forwarded_address_is_only_used_when_trusted() {
+    for (trust_forwarded_for, expected_ip) in [(false, "127.0.0.1"), (true, "203.0.113.7")] {
+        let app = spawn_app_with(|c| c.application.trust_forwarded_for = trust_forwarded_for).await;
+
+        subscribe_and_confirm(&app, &[("X-Forwarded-For", "198.51.100.1, 203.0.113.7")]).await;
+
+        let record = sqlx::query!("SELECT ip_address FROM consent_records WHERE event = 'subscribed'",)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+        assert_eq!(record.ip_address.as_deref(), Some(expected_ip));
+    }
+}
+
+#[tokio::test]
+async fn the_consent_log_is_append_only_but_goes_away_on_erasure() {
+    let app = spawn_app().await;
+    subscribe_and_confirm(&app, &[]).await;
+
+    let update = sqlx::query!("UPDATE consent_records SET ip_address = '10.0.0.1'",)
+        .execute(&app.db_pool)
+        .await;
+    assert!(update.is_err());
+    let delete = sqlx::query!("DELETE FROM consent_records",)
+        .execute(&app.db_pool)
+        .await;
+    assert!(delete.is_err());
+
+    let subscriber = sqlx::query!("SELECT id FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    app.erase_subscriber(&app.preferences_token(subscriber.id))
+        .await
+        .error_for_status()
+        .unwrap();
+    let count = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM consent_records"#,)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(count.count, 0);
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_consent_log() {
+    let app = spawn_app().await;
+
+    let response = app.get_consent_log("ursula_le_guin@gmail.com").await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app.get_consent_log_export("ursula_le_guin@gmail.com").await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn admins_can_view_and_export_the_consent_log_of_a_subscriber() {
+    let app = spawn_app().await;
+    subscribe_and_confirm(&app, &[("User-Agent", "browser/1.0")]).await;
+    app.log_in_as_admin().await;
+
+    let html = app
+        .get_consent_log("ursula_le_guin@gmail.com")
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("browser/1.0"));
+    assert!(html.contains("confirmed"));
+
+    let response = app.get_consent_log_export("ursula_le_guin@gmail.com").await;
+    assert_eq!(response.status().as_u16(), 200);
+    let records: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(records.as_array().unwrap().len(), 2);
+    assert_eq!(records[0]["event"], "subscribed");
+
+    let response = app.get_consent_log_export("nobody@example.com").await;
+    assert_eq!(response.status().as_u16(), 404);
+}
@@ -0,0 +1,47 @@
+use crate::helpers::spawn_app_with;
+use zero2prod::configuration::{EmailTransport, Environment};
+
+#[tokio::test]
+async fn captured_confirmation_emails_can_be_browsed_and_clicked() {
+    let app = spawn_app_with(|c| c.email_client.transport = EmailTransport::Capture).await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    let response = app.post_subscriptions(body.into()).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(app.email_server.received_requests().await.unwrap().is_empty());
+
+    let mailbox_html = app.get_mailbox_html().await;
+    assert!(mailbox_html.contains("ursula_le_guin@gmail.com"));
+    let message_link = mailbox_html
+        .split('"')
+        .find(|s| s.starts_with("/dev/mailbox/") && *s != "/dev/mailbox/clear")
+        .expect("No link to the captured message.");
+
+    let message_html = app.api_client
+        .get(format!("{}{}", &app.address, message_link))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(message_html.contains("Welcome!"));
+    assert!(message_html.contains("/subscriptions/confirm?subscription_token="));
+}
+
+#[tokio::test]
+async fn the_mailbox_is_not_routed_outside_of_the_local_environment() {
+    let app = spawn_app_with(|c| {
+        c.email_client.transport = EmailTransport::Capture;
+        c.application.environment = Environment::Production;
+    })
+    .await;
+
+    let response = app.api_client
+        .get(format!("{}/dev/mailbox", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 404);
+}
@@ -0,0 +1,79 @@
+use crate::helpers::{spawn_app, TestApp};
+use zero2prod::email_normalization::normalize_stored_emails;
+
+async fn insert_subscriber(app: &TestApp, email: &str, status: &str) -> uuid::Uuid {
+    let subscriber_id = uuid::Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, $2, 'le guin', now(), $3)
+        "#,
+        subscriber_id,
+        email,
+        status,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    subscriber_id
+}
+
+async fn stored_email(app: &TestApp, subscriber_id: uuid::Uuid) -> Option<String> {
+    sqlx::query!("SELECT email FROM subscriptions WHERE id = $1", subscriber_id)
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap()
+        .map(|row| row.email)
+}
+
+#[tokio::test]
+async fn stored_domains_are_converted_the_way_new_signups_are() {
+    let app = spawn_app().await;
+    // Uppercase, a sharp s, fullwidth letters: UTS #46 maps each of them.
+    // ASCII domains were lowercased by the migration already.
+    let domains = ["BÜCHER.example", "straße.example", "ＲＯＫＥ.example", "例え.テスト"];
+    let mut stored = Vec::new();
+    for (i, domain) in domains.iter().enumerate() {
+        let email = format!("reader{}@{}", i, domain);
+        stored.push((insert_subscriber(&app, &email, "confirmed").await, domain));
+    }
+    let ip_literal = insert_subscriber(&app, "ged@[127.0.0.1]", "confirmed").await;
+
+    normalize_stored_emails(&app.db_pool).await.unwrap();
+
+    for (i, (subscriber_id, domain)) in stored.into_iter().enumerate() {
+        let expected = format!("reader{}@{}", i, idna::domain_to_ascii(domain).unwrap());
+        assert_eq!(stored_email(&app, subscriber_id).await.as_deref(), Some(expected.as_str()));
+    }
+    assert_eq!(stored_email(&app, ip_literal).await.as_deref(), Some("ged@[127.0.0.1]"));
+    assert_eq!(normalize_stored_emails(&app.db_pool).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn an_address_that_turns_out_to_be_on_the_list_already_is_merged_into_it() {
+    let app = spawn_app().await;
+    let pending = insert_subscriber(&app, "ursula@Bücher.example", "pending_confirmation").await;
+    let unsubscribed = insert_subscriber(&app, "URSULA@xn--bcher-kva.example", "unsubscribed").await;
+    sqlx::query!(
+        r#"
+        INSERT INTO consent_records (id, subscriber_id, event, email, recorded_at, privacy_policy_version)
+        VALUES ($1, $2, 'subscribed', 'ursula@Bücher.example', now(), '2026-10-01')
+        "#,
+        uuid::Uuid::new_v4(),
+        pending,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    normalize_stored_emails(&app.db_pool).await.unwrap();
+
+    // Nobody who left starts getting email again.
+    assert_eq!(stored_email(&app, pending).await, None);
+    assert_eq!(stored_email(&app, unsubscribed).await.as_deref(), Some("URSULA@xn--bcher-kva.example"));
+    let consent = sqlx::query!("SELECT subscriber_id FROM consent_records")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(consent.subscriber_id, unsubscribed);
+}
@@ -1,5 +1,7 @@
 use crate::helpers::spawn_app;
 use crate::helpers::TestApp;
+use wiremock::{Mock, ResponseTemplate};
+use wiremock::matchers::any;
 
 #[tokio::test]
 async fn health_check_works() {
@@ -13,5 +15,42 @@ async fn health_check_works() {
         .expect("Failed to execute request.");
 
     assert!(response.status().is_success());
-    assert_eq!(Some(0), response.content_length());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "ok");
+    assert_eq!(body["email_client"]["circuit_breaker"], "closed");
+}
+
+#[tokio::test]
+async fn health_check_reports_an_open_email_circuit() {
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    for i in 0..5 {
+        let body = format!("name=le%20guin&email=ursula_le_guin{}%40gmail.com", i);
+        app.post_subscriptions(body).await;
+    }
+
+    let body: serde_json::Value = app.api_client
+        .get(format!("{}/health_check", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["status"], "degraded");
+    assert_eq!(body["email_client"]["circuit_breaker"], "open");
+
+    let metrics = app.api_client
+        .get(format!("{}/metrics", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .text()
+        .await
+        .unwrap();
+    assert!(metrics.contains("email_circuit_breaker_state 1"));
 }
@@ -4,9 +4,15 @@ use wiremock::MockServer;
 use argon2::password_hash::SaltString;
 use argon2::{Argon2, PasswordHasher, Algorithm, Params, Version};
 
+use zero2prod::automation_worker::AutomationWorker;
+use zero2prod::background_worker::ExecutionOutcome;
+use zero2prod::subscriber_email_queue::SubscriberEmailWorker;
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
-use zero2prod::configuration::{get_configuration, DatabaseSettings};
+use zero2prod::configuration::{get_configuration, DatabaseSettings, EmailTransport, Settings};
+use zero2prod::routes::preferences_token;
 use zero2prod::startup::{Application, get_connection_pool};
+use axum_extra::extract::cookie::Key;
+use secrecy::{ExposeSecret, Secret};
 
 pub struct TestUser {
     pub user_id: uuid::Uuid,
@@ -56,22 +62,99 @@ pub struct TestApp {
     pub port: u16,
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
+    pub hmac_secret: Secret<String>,
+    pub automation_worker: AutomationWorker,
+    pub subscriber_email_worker: SubscriberEmailWorker,
 }
 
 impl TestApp {
+    // Fills in the form served by the home page, the way a browser would.
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
-        self.api_client
-            .post(&format!("{}/subscriptions", &self.address))
+        let body = format!("{}&form_token={}", body, self.get_form_token().await);
+        self.post_subscriptions_raw(body).await
+    }
+
+    // The email that follows a signup is sent right after the response, as
+    // the worker would a moment later.
+    pub async fn post_subscriptions_raw(&self, body: String) -> reqwest::Response {
+        let response = self
+            .api_client
+            .post(format!("{}/subscriptions", &self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(body)
             .send()
             .await
+            .expect("Failed to execute request.");
+        self.dispatch_all_queued_emails().await;
+        response
+    }
+
+    /// Fills in a fresh form token, like the single-page app would, unless
+    /// the body already has one.
+    pub async fn post_api_subscriptions(&self, body: &serde_json::Value) -> reqwest::Response {
+        let mut body = body.clone();
+        if body.get("form_token").is_none() {
+            body["form_token"] = self.get_api_form_token().await.into();
+        }
+        self.post_api_subscriptions_raw(&body).await
+    }
+
+    pub async fn post_api_subscriptions_raw(&self, body: &serde_json::Value) -> reqwest::Response {
+        let response = self
+            .api_client
+            .post(format!("{}/api/subscriptions", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        self.dispatch_all_queued_emails().await;
+        response
+    }
+
+    pub async fn get_api_form_token(&self) -> String {
+        let body: serde_json::Value = self
+            .api_client
+            .get(format!("{}/api/subscriptions/form_token", &self.address))
+            .send()
+            .await
             .expect("Failed to execute request.")
+            .json()
+            .await
+            .unwrap();
+        body["form_token"].as_str().expect("No form token in the response.").to_string()
+    }
+
+    pub async fn get_form_token(&self) -> String {
+        let html = self
+            .api_client
+            .get(&self.address)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap();
+        let (_, rest) = html
+            .split_once(r#"name="form_token" value=""#)
+            .expect("The home page has no subscription form.");
+        rest.split('"').next().unwrap().to_string()
+    }
+
+    pub async fn post_resend_confirmation(&self, email: &str) -> reqwest::Response {
+        let response = self
+            .api_client
+            .post(format!("{}/subscriptions/resend", &self.address))
+            .form(&[("email", email)])
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        self.dispatch_all_queued_emails().await;
+        response
     }
 
     pub async fn post_newsletters(&self, body: serde_json::Value) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/newsletters", &self.address))
+            .post(format!("{}/newsletters", &self.address))
             .basic_auth(&self.test_user.username, Some(&self.test_user.password))
             .json(&body)
             .send()
@@ -79,6 +162,88 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub fn preferences_token(&self, subscriber_id: uuid::Uuid) -> String {
+        let key = Key::from(self.hmac_secret.expose_secret().as_bytes());
+        preferences_token(&key, subscriber_id)
+    }
+
+    pub async fn get_preferences(&self, token: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/subscriptions/preferences", &self.address))
+            .query(&[("token", token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_preferences(&self, fields: &[(&str, &str)]) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions/preferences", &self.address))
+            .form(fields)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_unsubscribe(&self, token: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions/preferences/unsubscribe", &self.address))
+            .form(&[("token", token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_data_export(&self, email: &str) -> reqwest::Response {
+        let response = self
+            .api_client
+            .post(format!("{}/subscriptions/data_export", &self.address))
+            .form(&[("email", email)])
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        self.dispatch_all_queued_emails().await;
+        response
+    }
+
+    // Asks for the erasure link with the preferences token, as the button on
+    // the preferences page does.
+    pub async fn post_erasure_request(&self, preferences_token: &str) -> reqwest::Response {
+        let response = self
+            .api_client
+            .post(format!("{}/subscriptions/preferences/erase", &self.address))
+            .form(&[("token", preferences_token)])
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        self.dispatch_all_queued_emails().await;
+        response
+    }
+
+    pub async fn post_erase(&self, erasure_token: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions/erase", &self.address))
+            .form(&[("token", erasure_token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    // Goes through the whole erasure flow: asks for the link, then confirms
+    // with the token from the email. Emails must be accepted by the mock.
+    pub async fn erase_subscriber(&self, preferences_token: &str) -> reqwest::Response {
+        self.post_erasure_request(preferences_token).await.error_for_status().unwrap();
+        let email_request = self.email_server.received_requests().await.unwrap().pop().unwrap();
+        let erasure_link = self.get_confirmation_links(&email_request).html;
+        let erasure_token = erasure_link
+            .query_pairs()
+            .find(|(key, _)| key == "token")
+            .unwrap()
+            .1
+            .into_owned();
+        self.post_erase(&erasure_token).await
+    }
+
     pub fn get_confirmation_links(
         &self,
         email_request: &wiremock::Request,
@@ -109,7 +274,7 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/login", &self.address))
+            .post(format!("{}/login", &self.address))
             .form(body)
             .send()
             .await
@@ -118,7 +283,7 @@ impl TestApp {
 
     pub async fn get_login_html(&self) -> String {
         self.api_client
-            .get(&format!("{}/login", &self.address))
+            .get(format!("{}/login", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -127,9 +292,69 @@ impl TestApp {
             .unwrap()
     }
 
+    pub async fn log_in_as_admin(&self) {
+        let response = self
+            .post_login(&serde_json::json!({
+                "username": &self.test_user.username,
+                "password": &self.test_user.password,
+            }))
+            .await;
+        assert_is_redirect_to(&response, "/admin/dashboard");
+    }
+
+    pub async fn get_consent_log(&self, email: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/consent", &self.address))
+            .query(&[("email", email)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_consent_log_export(&self, email: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/consent/export", &self.address))
+            .query(&[("email", email)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_subscriber_import(
+        &self,
+        mode: &str,
+        attestation: &str,
+        csv: &str,
+    ) -> reqwest::Response {
+        let form = reqwest::multipart::Form::new()
+            .text("mode", mode.to_string())
+            .text("attestation", attestation.to_string())
+            .part(
+                "file",
+                reqwest::multipart::Part::text(csv.to_string())
+                    .file_name("subscribers.csv")
+                    .mime_str("text/csv")
+                    .unwrap(),
+            );
+        self.api_client
+            .post(format!("{}/admin/subscribers/import", &self.address))
+            .multipart(form)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_page(&self, path: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}{}", &self.address, path))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn get_admin_dashboard(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/dashboard", &self.address))
+            .get(format!("{}/admin/dashboard", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -145,7 +370,7 @@ impl TestApp {
 
     pub async fn get_change_password(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/password", &self.address))
+            .get(format!("{}/admin/password", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -164,16 +389,58 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/admin/password", &self.address))
+            .post(format!("{}/admin/password", &self.address))
             .form(body)
             .send()
             .await
             .expect("Failed to execute request.")
     }
 
+    pub async fn get_mailbox_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/dev/mailbox", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    // Sends every automation step that is due, instead of waiting for a
+    // worker running in the background.
+    pub async fn dispatch_all_due_automation_steps(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue = self.automation_worker.try_execute_task().await.unwrap() {
+                break;
+            }
+        }
+    }
+
+    // Sends every queued email that is due, instead of waiting for a worker
+    // running in the background.
+    pub async fn dispatch_all_queued_emails(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue = self.subscriber_email_worker.try_execute_task().await.unwrap() {
+                break;
+            }
+        }
+    }
+
+    // Simulates a database that fails every write to the email queue, while
+    // the worker can still read it.
+    pub async fn fail_queueing_emails(&self) {
+        sqlx::query(
+            "ALTER TABLE subscriber_email_queue ADD CONSTRAINT simulate_failed_queue_insert CHECK (false) NOT VALID",
+        )
+        .execute(&self.db_pool)
+        .await
+        .expect("Failed to make queueing emails fail.");
+    }
+
     pub async fn post_logout(&self) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/admin/logout", &self.address))
+            .post(format!("{}/admin/logout", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -214,6 +481,11 @@ pub struct ConfirmationLinks {
 
 // Launch application in the background
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+// Launch application in the background after tweaking the test configuration
+pub async fn spawn_app_with(customise: impl FnOnce(&mut Settings)) -> TestApp {
     // Initialize tracing stack only once!
     Lazy::force(&TRACING);
     let email_server = MockServer::start().await;
@@ -223,6 +495,10 @@ pub async fn spawn_app() -> TestApp {
         c.database.database_name = uuid::Uuid::new_v4().to_string();
         c.application.port = 0;
         c.email_client.base_url = email_server.uri();
+        c.email_client.transport = EmailTransport::Postmark;
+        // Tests fill in the form faster than any person would.
+        c.subscriptions.bot_protection.min_form_seconds = 0;
+        customise(&mut c);
         c
     };
 
@@ -232,7 +508,9 @@ pub async fn spawn_app() -> TestApp {
         .await
         .expect("Failed to build application.");
     let application_port = application.port();
-    let _ = tokio::spawn(application.run_until_stopped());
+    let automation_worker = application.automation_worker();
+    let subscriber_email_worker = application.subscriber_email_worker();
+    drop(tokio::spawn(application.run_until_stopped()));
 
     let client = reqwest::Client::builder()
         .redirect(reqwest::redirect::Policy::none())
@@ -247,6 +525,9 @@ pub async fn spawn_app() -> TestApp {
         port: application_port,
         test_user: TestUser::generate(),
         api_client: client,
+        hmac_secret: config.application.hmac_secret.clone(),
+        automation_worker,
+        subscriber_email_worker,
     };
     test_app.test_user.store(&test_app.db_pool).await;
     test_app
@@ -2,7 +2,23 @@ mod helpers;
 mod health_check;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_resend;
+mod subscriptions_preferences;
+mod subscriptions_data;
+mod email_normalization;
+mod consent;
+mod subscriptions_api;
+mod subscriptions_bot_protection;
 mod newsletter;
 mod login;
 mod admin_dashboard;
+mod admin_subscribers;
+mod admin_subscriber_actions;
+mod admin_subscribers_export;
+mod admin_subscribers_import;
+mod subscriber_attributes;
+mod signup_attribution;
+mod automations;
+mod subscriber_email_queue;
 mod change_password;
+mod dev_mailbox;
@@ -29,6 +29,37 @@ async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
     assert_eq!(response.status().as_u16(), 200);
 }
 
+#[tokio::test]
+async fn newsletters_link_to_the_preferences_of_their_recipient() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let preferences_links = app.get_confirmation_links(&email_request);
+    assert_eq!(preferences_links.html, preferences_links.plain_text);
+    let response = reqwest::get(preferences_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    assert!(html.contains(&htmlescape::encode_attribute("ursula_le_guin@gmail.com")));
+}
+
 #[tokio::test]
 async fn newsletters_are_delivered_to_confirmed_subscribers() {
     let app = spawn_app().await;
@@ -87,7 +118,7 @@ async fn newsletters_returns_400_for_invalid_data() {
 async fn requests_missing_authorization_are_rejected() {
     let app = spawn_app().await;
     let response = reqwest::Client::new()
-        .post(&format!("{}/newsletters", &app.address))
+        .post(format!("{}/newsletters", &app.address))
         .json(&serde_json::json!({
             "title": "Newsletter title",
             "content": {
@@ -110,7 +141,7 @@ async fn non_existing_user_is_rejected() {
     let password = uuid::Uuid::new_v4().to_string();
 
     let response = reqwest::Client::new()
-        .post(&format!("{}/newsletters", &app.address))
+        .post(format!("{}/newsletters", &app.address))
         .basic_auth(username, Some(password))
         .json(&serde_json::json!({
             "title": "Newsletter title",
@@ -135,7 +166,7 @@ async fn invalid_password_is_rejected() {
     assert_ne!(password, app.test_user.password);
 
     let response = reqwest::Client::new()
-        .post(&format!("{}/newsletters", &app.address))
+        .post(format!("{}/newsletters", &app.address))
         .basic_auth(username, Some(password))
         .json(&serde_json::json!({
             "title": "Newsletter title",
@@ -152,6 +183,81 @@ async fn invalid_password_is_rejected() {
     assert_eq!(r#"Basic realm="publish""#, response.headers()["WWW-Authenticate"]);
 }
 
+#[tokio::test]
+async fn newsletter_deliveries_are_recorded_against_the_issue() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "MessageID": "4e1ab3e5-4b8c-4a6f-9c3c-0f5b0c2f6f11",
+            "ErrorCode": 0,
+            "Message": "OK"
+        })))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let delivery = sqlx::query!(
+        r#"
+        SELECT d.kind, d.message_id, i.title
+        FROM email_deliveries d
+        JOIN newsletter_issues i USING (newsletter_issue_id)
+        "#,
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch the newsletter delivery.");
+
+    assert_eq!(delivery.kind, "newsletter");
+    assert_eq!(delivery.message_id.as_deref(), Some("4e1ab3e5-4b8c-4a6f-9c3c-0f5b0c2f6f11"));
+    assert_eq!(delivery.title, "Newsletter title");
+}
+
+#[tokio::test]
+async fn inactive_recipients_are_suppressed() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(422).set_body_json(serde_json::json!({
+            "ErrorCode": 406,
+            "Message": "You tried to send to a recipient that has been marked as inactive."
+        })))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletters(serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    }))
+    .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriber.");
+    assert_eq!(saved.status, "suppressed");
+}
+
 
 async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
     let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
@@ -173,7 +279,7 @@ async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
         .unwrap()
         .pop()
         .unwrap();
-    app.get_confirmation_links(&email_request)
+    app.get_confirmation_links(email_request)
 }
 
 async fn create_confirmed_subscriber(app: &TestApp) {
@@ -184,3 +290,215 @@ async fn create_confirmed_subscriber(app: &TestApp) {
         .error_for_status()
         .unwrap();
 }
+
+async fn insert_confirmed_subscriber(app: &TestApp, email: &str, topics: &[&str], delivery_frequency: &str) {
+    let topics: Vec<String> = topics.iter().map(|topic| topic.to_string()).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status, topics, delivery_frequency)
+        VALUES ($1, $2, 'le guin', now(), 'confirmed', $3, $4)
+        "#,
+        uuid::Uuid::new_v4(),
+        email,
+        &topics,
+        delivery_frequency,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+// As if their last digest went out long enough ago for the next one.
+async fn make_digests_due(app: &TestApp) {
+    sqlx::query!("UPDATE subscriptions SET digest_covered_until = now() - interval '32 days'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+}
+
+async fn sent_emails(app: &TestApp) -> Vec<serde_json::Value> {
+    app.email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|request| serde_json::from_slice(&request.body).unwrap())
+        .collect()
+}
+
+async fn recipients(app: &TestApp) -> Vec<String> {
+    let mut recipients: Vec<String> = sent_emails(app)
+        .await
+        .iter()
+        .map(|email| email["To"].as_str().unwrap().to_owned())
+        .collect();
+    recipients.sort();
+    recipients
+}
+
+#[tokio::test]
+async fn an_issue_about_a_topic_goes_to_those_who_picked_it_or_no_topic_at_all() {
+    let app = spawn_app().await;
+    insert_confirmed_subscriber(&app, "essays@example.com", &["essays"], "immediately").await;
+    insert_confirmed_subscriber(&app, "announcements@example.com", &["announcements"], "immediately").await;
+    insert_confirmed_subscriber(&app, "everything@example.com", &[], "immediately").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletters(serde_json::json!({
+        "title": "On dragons",
+        "content": {"text": "Dragons", "html": "<p>Dragons</p>"},
+        "topic": "essays",
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    assert_eq!(recipients(&app).await, vec!["essays@example.com", "everything@example.com"]);
+}
+
+#[tokio::test]
+async fn an_issue_about_an_unknown_topic_is_rejected() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "On dragons",
+            "content": {"text": "Dragons", "html": "<p>Dragons</p>"},
+            "topic": "dragons",
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn weekly_and_monthly_subscribers_get_issues_in_a_digest() {
+    let app = spawn_app().await;
+    insert_confirmed_subscriber(&app, "immediately@example.com", &[], "immediately").await;
+    insert_confirmed_subscriber(&app, "weekly@example.com", &[], "weekly").await;
+    insert_confirmed_subscriber(&app, "monthly@example.com", &[], "monthly").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    sqlx::query!("UPDATE subscriptions SET digest_covered_until = now() - interval '8 days'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    for title in ["On dragons", "On wizards"] {
+        app.post_newsletters(serde_json::json!({
+            "title": title,
+            "content": {"text": format!("{} as text", title), "html": format!("<p>{}</p>", title)},
+        }))
+        .await
+        .error_for_status()
+        .unwrap();
+    }
+    let sent_straight_away = recipients(&app).await;
+    app.dispatch_all_queued_emails().await;
+
+    assert_eq!(sent_straight_away, vec!["immediately@example.com", "immediately@example.com"]);
+    let emails = sent_emails(&app).await;
+    assert_eq!(emails.len(), 3);
+    let digest = &emails[2];
+    assert_eq!(digest["To"], "weekly@example.com");
+    assert_eq!(digest["Subject"], "Your weekly digest");
+    let text = digest["TextBody"].as_str().unwrap();
+    assert!(text.find("On dragons as text").unwrap() < text.find("On wizards as text").unwrap());
+    // A month on, the monthly digest is due, the weekly one has nothing new.
+    make_digests_due(&app).await;
+    sqlx::query!("UPDATE subscriptions SET digest_covered_until = now() WHERE email = 'weekly@example.com'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_queued_emails().await;
+    let emails = sent_emails(&app).await;
+    assert_eq!(emails.len(), 4);
+    assert_eq!(emails[3]["To"], "monthly@example.com");
+    assert_eq!(emails[3]["Subject"], "Your monthly digest");
+}
+
+#[tokio::test]
+async fn a_digest_is_not_sent_twice() {
+    let app = spawn_app().await;
+    insert_confirmed_subscriber(&app, "weekly@example.com", &[], "weekly").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    make_digests_due(&app).await;
+    app.post_newsletters(serde_json::json!({
+        "title": "On dragons",
+        "content": {"text": "Dragons", "html": "<p>Dragons</p>"},
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    app.dispatch_all_queued_emails().await;
+    app.dispatch_all_queued_emails().await;
+
+    assert_eq!(recipients(&app).await, vec!["weekly@example.com"]);
+    let delivery = sqlx::query!("SELECT kind FROM email_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(delivery.kind, "digest");
+}
+
+#[tokio::test]
+async fn a_digest_only_has_the_issues_about_the_topics_of_its_subscriber() {
+    let app = spawn_app().await;
+    insert_confirmed_subscriber(&app, "weekly@example.com", &["essays"], "weekly").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    make_digests_due(&app).await;
+    for (title, topic) in [("On dragons", "essays"), ("A reading in Portland", "announcements")] {
+        app.post_newsletters(serde_json::json!({
+            "title": title,
+            "content": {"text": title, "html": format!("<p>{}</p>", title)},
+            "topic": topic,
+        }))
+        .await
+        .error_for_status()
+        .unwrap();
+    }
+
+    app.dispatch_all_queued_emails().await;
+
+    let emails = sent_emails(&app).await;
+    assert_eq!(emails.len(), 1);
+    let text = emails[0]["TextBody"].as_str().unwrap();
+    assert!(text.contains("On dragons"));
+    assert!(!text.contains("A reading in Portland"));
+}
+
+#[tokio::test]
+async fn there_is_no_digest_when_nothing_was_published() {
+    let app = spawn_app().await;
+    insert_confirmed_subscriber(&app, "weekly@example.com", &[], "weekly").await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    make_digests_due(&app).await;
+
+    app.dispatch_all_queued_emails().await;
+
+    let covered = sqlx::query!(r#"SELECT digest_covered_until > now() - interval '1 minute' AS "recent!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(covered.recent);
+}
@@ -0,0 +1,193 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+struct StoredAttribution {
+    signup_source: Option<String>,
+    referrer: Option<String>,
+    utm_source: Option<String>,
+    utm_medium: Option<String>,
+    utm_campaign: Option<String>,
+}
+
+async fn stored_attribution(app: &TestApp) -> StoredAttribution {
+    sqlx::query_as!(
+        StoredAttribution,
+        "SELECT signup_source, referrer, utm_source, utm_medium, utm_campaign FROM subscriptions",
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+}
+
+async fn mock_email_server(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+async fn insert_subscriber(app: &TestApp, status: &str, signup_source: Option<&str>, days_ago: i32) {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status, signup_source)
+        VALUES ($1, $2, 'le guin', now() - make_interval(days => $3), $4, $5)
+        "#,
+        uuid::Uuid::new_v4(),
+        format!("{}@example.com", uuid::Uuid::new_v4()),
+        days_ago,
+        status,
+        signup_source,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn the_home_page_passes_the_campaign_and_referrer_on_to_the_form() {
+    let app = spawn_app().await;
+
+    let html = app
+        .api_client
+        .get(format!("{}/?utm_source=mastodon&utm_campaign=launch%20week", &app.address))
+        .header("Referer", "https://blog.example.org/post")
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    assert!(html.contains(r#"name="source" value="home""#));
+    assert!(html.contains(r#"name="utm_source" value="mastodon""#));
+    assert!(html.contains(&format!(
+        r#"name="utm_campaign" value="{}""#,
+        htmlescape::encode_attribute("launch week")
+    )));
+    assert!(html.contains(&format!(
+        r#"name="referrer" value="{}""#,
+        htmlescape::encode_attribute("https://blog.example.org/post")
+    )));
+}
+
+#[tokio::test]
+async fn the_subscription_form_stores_where_the_signup_came_from() {
+    let app = spawn_app().await;
+    mock_email_server(&app).await;
+
+    app.post_subscriptions(
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&source=home&utm_source=mastodon\
+        &referrer=https%3A%2F%2Fblog.example.org%2Fpost"
+            .into(),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let saved = stored_attribution(&app).await;
+    assert_eq!(saved.signup_source.as_deref(), Some("home"));
+    assert_eq!(saved.utm_source.as_deref(), Some("mastodon"));
+    assert_eq!(saved.referrer.as_deref(), Some("https://blog.example.org/post"));
+    assert_eq!(saved.utm_medium, None);
+}
+
+#[tokio::test]
+async fn utm_parameters_are_also_taken_from_the_query_string() {
+    let app = spawn_app().await;
+    mock_email_server(&app).await;
+    let body = format!(
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&form_token={}",
+        app.get_form_token().await
+    );
+
+    app.api_client
+        .post(format!("{}/subscriptions?utm_medium=email&utm_campaign=spring", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Referer", format!("{}/", &app.address))
+        .body(body)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let saved = stored_attribution(&app).await;
+    assert_eq!(saved.utm_medium.as_deref(), Some("email"));
+    assert_eq!(saved.utm_campaign.as_deref(), Some("spring"));
+    // The form's own page is not where the subscriber came from.
+    assert_eq!(saved.referrer, None);
+}
+
+#[tokio::test]
+async fn the_api_stores_where_the_signup_came_from() {
+    let app = spawn_app().await;
+    mock_email_server(&app).await;
+
+    app.api_client
+        .post(format!("{}/api/subscriptions", &app.address))
+        .header("Referer", "https://app.example.org/pricing")
+        .json(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+            "utm_source": "newsletter",
+            "form_token": app.get_api_form_token().await,
+        }))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let saved = stored_attribution(&app).await;
+    assert_eq!(saved.signup_source.as_deref(), Some("api"));
+    assert_eq!(saved.utm_source.as_deref(), Some("newsletter"));
+    assert_eq!(saved.referrer.as_deref(), Some("https://app.example.org/pricing"));
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_signup_report() {
+    let app = spawn_app().await;
+
+    let response = app.get_admin_page("/admin/signups").await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_signup_report_counts_confirmed_signups_by_source() {
+    let app = spawn_app().await;
+    app.log_in_as_admin().await;
+    insert_subscriber(&app, "confirmed", Some("home"), 0).await;
+    insert_subscriber(&app, "confirmed", Some("home"), 0).await;
+    insert_subscriber(&app, "confirmed", Some("api"), 0).await;
+    insert_subscriber(&app, "confirmed", None, 0).await;
+    insert_subscriber(&app, "pending_confirmation", Some("footer"), 0).await;
+    insert_subscriber(&app, "confirmed", Some("ancient"), 400).await;
+
+    let html = app
+        .get_admin_page("/admin/signups?period=month&by=source")
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    assert!(html.contains("<th>home</th><th>(none)</th><th>api</th>"));
+    assert!(html.contains("<tr><th>Total</th><td>2</td><td>1</td><td>1</td></tr>"));
+    assert!(!html.contains("footer"));
+    assert!(!html.contains("ancient"));
+}
+
+#[tokio::test]
+async fn the_signup_report_rejects_unknown_options() {
+    let app = spawn_app().await;
+    app.log_in_as_admin().await;
+
+    for query in ["period=fortnight", "by=email"] {
+        let response = app.get_admin_page(&format!("/admin/signups?{}", query)).await;
+
+        assert_eq!(response.status().as_u16(), 400, "{} was accepted", query);
+    }
+}
@@ -0,0 +1,338 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn add_attribute(app: &TestApp, name: &str, kind: &str, required: bool) -> reqwest::Response {
+    let mut form = vec![("name", name), ("kind", kind)];
+    if required {
+        form.push(("required", "true"));
+    }
+    app.api_client
+        .post(format!("{}/admin/attributes", &app.address))
+        .form(&form)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn insert_confirmed_subscriber(app: &TestApp, email: &str, attributes: serde_json::Value) -> uuid::Uuid {
+    let subscriber_id = uuid::Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status, attributes)
+        VALUES ($1, $2, 'le guin', now(), 'confirmed', $3)
+        "#,
+        subscriber_id,
+        email,
+        attributes,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    subscriber_id
+}
+
+async fn stored_attributes(app: &TestApp) -> serde_json::Value {
+    sqlx::query!("SELECT attributes FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .attributes
+}
+
+async fn mock_email_server(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_define_attributes() {
+    let app = spawn_app().await;
+
+    let response = add_attribute(&app, "company", "text", false).await;
+
+    assert_is_redirect_to(&response, "/login");
+    let count = sqlx::query!("SELECT count(*) AS \"count!\" FROM attribute_definitions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(count, 0);
+}
+
+#[tokio::test]
+async fn defined_attributes_are_asked_for_on_the_subscription_form() {
+    let app = spawn_app().await;
+    app.log_in_as_admin().await;
+
+    let response = add_attribute(&app, "company", "text", true).await;
+    assert_is_redirect_to(&response, "/admin/attributes");
+    let page = app.get_admin_page("/admin/attributes").await.text().await.unwrap();
+    assert!(page.contains("The company attribute was added."));
+
+    let home = app.api_client.get(&app.address).send().await.unwrap().text().await.unwrap();
+    assert!(home.contains(&format!(r#"name="{}""#, htmlescape::encode_attribute("attributes.company"))));
+}
+
+#[tokio::test]
+async fn invalid_attribute_definitions_are_refused() {
+    let app = spawn_app().await;
+    app.log_in_as_admin().await;
+    add_attribute(&app, "company", "text", false).await;
+
+    for (name, kind, message) in [
+        ("Shoe Size", "number", "is not a valid attribute name"),
+        ("shoe_size", "colour", "is not a valid attribute type"),
+        ("company", "text", "There already is a company attribute."),
+    ] {
+        add_attribute(&app, name, kind, false).await;
+        let page = app.get_admin_page("/admin/attributes").await.text().await.unwrap();
+        assert!(page.contains(message), "No {:?} for {} ({})", message, name, kind);
+    }
+}
+
+#[tokio::test]
+async fn the_subscription_form_stores_attributes_checked_against_the_schema() {
+    let app = spawn_app().await;
+    app.log_in_as_admin().await;
+    add_attribute(&app, "company", "text", true).await;
+    add_attribute(&app, "employees", "number", false).await;
+    mock_email_server(&app).await;
+
+    let missing = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com&attributes.employees=12".into())
+        .await;
+    assert_eq!(missing.status().as_u16(), 400);
+    assert_eq!(missing.text().await.unwrap(), "company is required");
+
+    app.post_subscriptions(
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&attributes.company=Earthsea&attributes.employees=12".into(),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    assert_eq!(stored_attributes(&app).await, serde_json::json!({"company": "Earthsea", "employees": 12.0}));
+}
+
+#[tokio::test]
+async fn the_api_reports_every_invalid_attribute() {
+    let app = spawn_app().await;
+    app.log_in_as_admin().await;
+    add_attribute(&app, "company", "text", true).await;
+    add_attribute(&app, "customer", "boolean", false).await;
+
+    let response = app
+        .post_api_subscriptions(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+            "attributes": {"customer": "yes", "shoe_size": 38},
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let fields: Vec<&str> = body["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|error| error["field"].as_str().unwrap())
+        .collect();
+    assert_eq!(fields, vec!["attributes.company", "attributes.customer", "attributes.shoe_size"]);
+}
+
+#[tokio::test]
+async fn the_api_stores_attributes() {
+    let app = spawn_app().await;
+    app.log_in_as_admin().await;
+    add_attribute(&app, "customer", "boolean", false).await;
+    add_attribute(&app, "birthday", "date", false).await;
+    mock_email_server(&app).await;
+
+    app.post_api_subscriptions(&serde_json::json!({
+        "name": "le guin",
+        "email": "ursula_le_guin@gmail.com",
+        "attributes": {"customer": true, "birthday": "1929-10-21"},
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    assert_eq!(stored_attributes(&app).await, serde_json::json!({"customer": true, "birthday": "1929-10-21"}));
+}
+
+#[tokio::test]
+async fn an_admin_can_edit_the_attributes_of_a_subscriber() {
+    let app = spawn_app().await;
+    app.log_in_as_admin().await;
+    add_attribute(&app, "company", "text", false).await;
+    let subscriber_id = insert_confirmed_subscriber(&app, "ursula_le_guin@gmail.com", serde_json::json!({})).await;
+    let detail_page = format!("/admin/subscribers/{}", subscriber_id);
+
+    let response = app
+        .api_client
+        .post(format!("{}{}/attributes", &app.address, detail_page))
+        .form(&[("attributes.company", "Earthsea")])
+        .send()
+        .await
+        .unwrap();
+
+    assert_is_redirect_to(&response, &detail_page);
+    assert_eq!(stored_attributes(&app).await, serde_json::json!({"company": "Earthsea"}));
+    let page = app.get_admin_page(&detail_page).await.text().await.unwrap();
+    assert!(page.contains("The attributes were saved."));
+    assert!(page.contains(r#"value="Earthsea""#));
+    assert!(page.contains("edit_attributes"));
+    assert!(page.contains("changed company"));
+}
+
+#[tokio::test]
+async fn deleting_an_attribute_removes_it_from_every_subscriber() {
+    let app = spawn_app().await;
+    app.log_in_as_admin().await;
+    add_attribute(&app, "company", "text", false).await;
+    add_attribute(&app, "employees", "number", false).await;
+    insert_confirmed_subscriber(&app, "ursula_le_guin@gmail.com", serde_json::json!({"company": "Earthsea", "employees": 12})).await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/admin/attributes/company/delete", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    assert_is_redirect_to(&response, "/admin/attributes");
+    assert_eq!(stored_attributes(&app).await, serde_json::json!({"employees": 12}));
+}
+
+#[tokio::test]
+async fn newsletters_fill_in_variables_for_each_recipient() {
+    let app = spawn_app().await;
+    app.log_in_as_admin().await;
+    add_attribute(&app, "company", "text", false).await;
+    insert_confirmed_subscriber(&app, "ursula_le_guin@gmail.com", serde_json::json!({"company": "<Earthsea>"})).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletters(serde_json::json!({
+        "title": "News for {{ attributes.company }}",
+        "content": {
+            "text": "Hi {{ name }}, of {{ attributes.company }}",
+            "html": "<p>Hi {{ name }}, of {{ attributes.company }}</p>",
+        }
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["Subject"], "News for <Earthsea>");
+    assert!(body["TextBody"].as_str().unwrap().starts_with("Hi le guin, of <Earthsea>"));
+    assert!(body["HtmlBody"].as_str().unwrap().starts_with("<p>Hi le guin, of &lt;Earthsea&gt;</p>"));
+}
+
+#[tokio::test]
+async fn a_newsletter_with_an_unknown_variable_is_rejected() {
+    let app = spawn_app().await;
+    insert_confirmed_subscriber(&app, "ursula_le_guin@gmail.com", serde_json::json!({})).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Hi {{ attributes.company }}",
+                "html": "<p>Hi</p>",
+            }
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response.text().await.unwrap().contains("attributes.company"));
+}
+
+#[tokio::test]
+async fn a_segment_limits_who_gets_a_newsletter() {
+    let app = spawn_app().await;
+    app.log_in_as_admin().await;
+    add_attribute(&app, "customer", "boolean", false).await;
+    add_attribute(&app, "employees", "number", false).await;
+    insert_confirmed_subscriber(&app, "customer@example.com", serde_json::json!({"customer": true, "employees": 12.0})).await;
+    insert_confirmed_subscriber(&app, "prospect@example.com", serde_json::json!({"customer": false, "employees": 12.0})).await;
+    insert_confirmed_subscriber(&app, "unknown@example.com", serde_json::json!({})).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletters(serde_json::json!({
+        "title": "For customers",
+        "content": {"text": "Thanks", "html": "<p>Thanks</p>"},
+        "segment": [
+            {"attribute": "customer", "equals": true},
+            {"attribute": "employees", "equals": 12},
+        ],
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "customer@example.com");
+}
+
+#[tokio::test]
+async fn a_segment_must_match_the_schema() {
+    let app = spawn_app().await;
+    app.log_in_as_admin().await;
+    add_attribute(&app, "customer", "boolean", false).await;
+
+    for segment in [
+        serde_json::json!([{"attribute": "customer", "equals": "yes"}]),
+        serde_json::json!([{"attribute": "shoe_size", "equals": 38}]),
+        serde_json::json!([{"attribute": "customer", "equals": true}, {"attribute": "customer", "equals": false}]),
+    ] {
+        let response = app
+            .post_newsletters(serde_json::json!({
+                "title": "For customers",
+                "content": {"text": "Thanks", "html": "<p>Thanks</p>"},
+                "segment": segment,
+            }))
+            .await;
+
+        assert_eq!(response.status().as_u16(), 400, "{} was accepted", segment);
+    }
+}
+
+#[tokio::test]
+async fn exports_include_attributes() {
+    let app = spawn_app().await;
+    app.log_in_as_admin().await;
+    insert_confirmed_subscriber(&app, "ursula_le_guin@gmail.com", serde_json::json!({"company": "Earthsea"})).await;
+
+    let body = app
+        .get_admin_page("/admin/subscribers/export/download?format=ndjson")
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    let subscriber: serde_json::Value = serde_json::from_str(body.lines().next().unwrap()).unwrap();
+    assert_eq!(subscriber["attributes"], serde_json::json!({"company": "Earthsea"}));
+}
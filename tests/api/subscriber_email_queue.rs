@@ -0,0 +1,135 @@
+use crate::helpers::{spawn_app, TestApp};
+
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+// Queues a confirmation email for a new, pending subscriber.
+async fn import_for_confirmation(app: &TestApp) {
+    app.log_in_as_admin().await;
+    let response = app
+        .post_subscriber_import("send_confirmation", "", "name,email\nle guin,ursula@earthsea.org\n")
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+async fn queued_attempts(app: &TestApp) -> Vec<i32> {
+    sqlx::query!("SELECT attempts FROM subscriber_email_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| row.attempts)
+        .collect()
+}
+
+#[tokio::test]
+async fn a_sent_email_leaves_the_queue() {
+    let app = spawn_app().await;
+    import_for_confirmation(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.dispatch_all_queued_emails().await;
+    app.dispatch_all_queued_emails().await;
+
+    assert!(queued_attempts(&app).await.is_empty());
+    let deliveries = sqlx::query!("SELECT kind FROM email_deliveries")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0].kind, "confirmation");
+}
+
+#[tokio::test]
+async fn a_confirmation_email_is_not_sent_to_someone_who_is_no_longer_pending() {
+    let app = spawn_app().await;
+    import_for_confirmation(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    app.dispatch_all_queued_emails().await;
+
+    assert!(queued_attempts(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn an_email_that_fails_to_send_is_retried_later() {
+    let app = spawn_app().await;
+    import_for_confirmation(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.dispatch_all_queued_emails().await;
+
+    // Not due again yet.
+    assert_eq!(queued_attempts(&app).await, vec![1]);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    sqlx::query!("UPDATE subscriber_email_queue SET due_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_queued_emails().await;
+
+    assert!(queued_attempts(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn the_worker_deletes_tokens_a_day_after_they_expired() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    for email in ["ursula%40earthsea.org", "ged%40earthsea.org", "tenar%40earthsea.org"] {
+        app.post_subscriptions(format!("name=le%20guin&email={}", email)).await.error_for_status().unwrap();
+    }
+    // Used and long expired, unused and long expired, expired an hour ago.
+    sqlx::query!(
+        r#"
+        UPDATE subscription_tokens t
+        SET used_at = CASE WHEN s.email LIKE 'ursula%' THEN now() - interval '3 days' END,
+            expires_at = CASE WHEN s.email LIKE 'tenar%' THEN now() - interval '1 hour' ELSE now() - interval '2 days' END
+        FROM subscriptions s
+        WHERE s.id = t.subscriber_id
+        "#,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    app.dispatch_all_queued_emails().await;
+
+    let left = sqlx::query!(
+        "SELECT s.email FROM subscription_tokens t JOIN subscriptions s ON s.id = t.subscriber_id"
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(left.len(), 1);
+    assert_eq!(left[0].email, "tenar@earthsea.org");
+}
@@ -1,15 +1,14 @@
-use crate::helpers::spawn_app;
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
+use zero2prod::configuration::get_configuration;
+use zero2prod::startup::Application;
 
 #[tokio::test]
 async fn subscribe_fails_if_there_is_a_fatal_db_error() {
     let app = spawn_app().await;
     let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
-    sqlx::query!("ALTER TABLE subscription_tokens DROP COLUMN subscription_token;",)
-        .execute(&app.db_pool)
-        .await
-        .unwrap();
+    app.fail_queueing_emails().await;
 
     let response = app.post_subscriptions(body.into()).await;
 
@@ -30,7 +29,7 @@ async fn subscribe_sends_a_confirmation_email_with_a_link() {
     app.post_subscriptions(body.into()).await;
 
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
-    let confirmation_links = app.get_confirmation_links(&email_request);
+    let confirmation_links = app.get_confirmation_links(email_request);
 
     assert_eq!(confirmation_links.html, confirmation_links.plain_text);
 }
@@ -131,3 +130,297 @@ async fn subscribe_returns_a_400_when_fields_are_present_but_invalid() {
         );
     }
 }
+
+#[tokio::test]
+async fn subscribe_records_the_message_id_of_the_confirmation_email() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "To": "ursula_le_guin@gmail.com",
+            "MessageID": "b7bc2f4a-e38e-4336-af7d-e6c392c2f817",
+            "ErrorCode": 0,
+            "Message": "OK"
+        })))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+
+    let delivery = sqlx::query!(
+        r#"
+        SELECT d.kind, d.message_id, s.email
+        FROM email_deliveries d
+        JOIN subscriptions s ON s.id = d.subscriber_id
+        "#,
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch the email delivery.");
+
+    assert_eq!(delivery.kind, "confirmation");
+    assert_eq!(delivery.message_id.as_deref(), Some("b7bc2f4a-e38e-4336-af7d-e6c392c2f817"));
+    assert_eq!(delivery.email, "ursula_le_guin@gmail.com");
+}
+
+
+#[tokio::test]
+async fn subscribing_twice_while_pending_sends_a_new_link_and_keeps_the_first_one_working() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let first = app.post_subscriptions(body.into()).await;
+    let second = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(second.status().as_u16(), 200);
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let first_links = app.get_confirmation_links(&email_requests[0]);
+    let second_links = app.get_confirmation_links(&email_requests[1]);
+    assert_ne!(first_links.html, second_links.html);
+    let tokens = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscription_tokens WHERE expires_at > now()"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(tokens.count, 2);
+    reqwest::get(first_links.html).await.unwrap().error_for_status().unwrap();
+
+    let subscribers = sqlx::query!("SELECT COUNT(*) AS count FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscribers.count, Some(1));
+}
+
+// Returns the id of the new, confirmed subscriber.
+async fn subscribe_and_confirm(app: &TestApp, body: &str) -> uuid::Uuid {
+    let mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await.error_for_status().unwrap();
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html).await.unwrap().error_for_status().unwrap();
+    drop(mock_guard);
+    sqlx::query!("SELECT id FROM subscriptions").fetch_one(&app.db_pool).await.unwrap().id
+}
+
+#[tokio::test]
+async fn subscribing_again_once_confirmed_sends_a_notice_instead_of_a_confirmation() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    subscribe_and_confirm(&app, body).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(email["Subject"], "You are already subscribed");
+    assert!(email["TextBody"].as_str().unwrap().contains("/subscriptions/preferences?token="));
+    assert!(!email["TextBody"].as_str().unwrap().contains("/subscriptions/confirm"));
+    let saved = sqlx::query!("SELECT status FROM subscriptions",)
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].status, "confirmed");
+}
+
+#[tokio::test]
+async fn someone_who_unsubscribed_can_sign_up_and_confirm_again() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    let subscriber_id = subscribe_and_confirm(&app, body).await;
+    app.post_unsubscribe(&app.preferences_token(subscriber_id))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let status = || async {
+        sqlx::query!("SELECT status FROM subscriptions").fetch_one(&app.db_pool).await.unwrap().status
+    };
+    assert_eq!(status().await, "unsubscribed");
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await.error_for_status().unwrap();
+
+    assert_eq!(status().await, "pending_confirmation");
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let confirmation_links = app.get_confirmation_links(&email_request);
+    reqwest::get(confirmation_links.html).await.unwrap().error_for_status().unwrap();
+    assert_eq!(status().await, "confirmed");
+}
+
+#[tokio::test]
+async fn a_suppressed_address_that_signs_up_again_is_not_emailed() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    subscribe_and_confirm(&app, body).await;
+    sqlx::query!("UPDATE subscriptions SET status = 'suppressed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn no_signup_waits_for_the_email_provider() {
+    let app = spawn_app().await;
+    subscribe_and_confirm(&app, "name=le%20guin&email=ursula_le_guin%40gmail.com").await;
+    let requests_so_far = app.email_server.received_requests().await.unwrap().len();
+
+    // Whether the address is new or already confirmed, the email is queued
+    // and nothing is sent while the request waits.
+    for email in ["ged@roke.org", "ursula_le_guin@gmail.com"] {
+        let form_token = app.get_form_token().await;
+        let response = app
+            .api_client
+            .post(format!("{}/subscriptions", &app.address))
+            .form(&[("name", "someone"), ("email", email), ("form_token", &form_token)])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    assert_eq!(app.email_server.received_requests().await.unwrap().len(), requests_so_far);
+    let queued = sqlx::query!("SELECT kind FROM subscriber_email_queue ORDER BY kind")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    let queued: Vec<_> = queued.into_iter().map(|row| row.kind).collect();
+    assert_eq!(queued, vec!["already_subscribed", "confirmation"]);
+}
+
+#[tokio::test]
+async fn addresses_differing_only_in_case_are_the_same_subscriber() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=le%20guin&email=Ursula_Le_Guin%40Gmail.COM".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let saved = sqlx::query!("SELECT email FROM subscriptions",)
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 1);
+    // The domain is normalized, the local part is kept as first typed.
+    assert_eq!(saved[0].email, "Ursula_Le_Guin@gmail.com");
+}
+
+#[tokio::test]
+async fn subscribing_with_a_disposable_address_is_rejected_with_a_reason() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula%40Mailinator.com".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response.text().await.unwrap().contains("mailinator.com is a disposable email domain"));
+    let saved = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#,)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.count, 0);
+}
+
+#[tokio::test]
+async fn the_allowlist_overrides_the_blocklist() {
+    let app = spawn_app_with(|c| {
+        c.subscriptions.domain_blocklist.allowlist = vec!["mailinator.com".into()]
+    })
+    .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula%40mailinator.com".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn a_configured_blocklist_file_replaces_the_default_one() {
+    let blocklist = std::env::temp_dir().join(format!("blocklist-{}.txt", uuid::Uuid::new_v4()));
+    std::fs::write(&blocklist, "# Our own list\nearthsea.org\n").unwrap();
+    let app = spawn_app_with(|c| {
+        c.subscriptions.domain_blocklist.path = Some(blocklist.to_str().unwrap().into())
+    })
+    .await;
+    std::fs::remove_file(&blocklist).unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let blocked = app
+        .post_subscriptions("name=le%20guin&email=ursula%40earthsea.org".into())
+        .await;
+    let no_longer_blocked = app
+        .post_subscriptions("name=le%20guin&email=ursula%40mailinator.com".into())
+        .await;
+
+    assert_eq!(blocked.status().as_u16(), 400);
+    assert_eq!(no_longer_blocked.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn the_application_does_not_start_with_a_missing_blocklist_file() {
+    let mut config = get_configuration().expect("Failed to read configuration.");
+    config.application.port = 0;
+    let blocklist = std::env::temp_dir().join(format!("missing-{}.txt", uuid::Uuid::new_v4()));
+    config.subscriptions.domain_blocklist.path = Some(blocklist.to_str().unwrap().into());
+
+    let outcome = Application::build(config).await;
+
+    let error = outcome.err().expect("The application started without its blocklist.");
+    assert_eq!(error.to_string(), "Failed to read the domain blocklist.");
+}
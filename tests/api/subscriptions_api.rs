@@ -0,0 +1,176 @@
+use crate::helpers::{spawn_app, spawn_app_with};
+
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[tokio::test]
+async fn subscribe_through_the_api_persists_the_subscriber_and_sends_a_confirmation_email() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_api_subscriptions(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT email, name, status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+    assert_eq!(saved.name, "le guin");
+    assert_eq!(saved.status, "pending_confirmation");
+    let consent = sqlx::query!("SELECT form_source FROM consent_records",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(consent.form_source.as_deref(), Some("api"));
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn subscribe_through_the_api_reports_every_invalid_field() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_api_subscriptions(&serde_json::json!({
+            "name": "<script>",
+            "email": "definitely-not-an-email",
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let errors = body["errors"].as_array().unwrap();
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0]["field"], "name");
+    assert_eq!(errors[0]["message"], "invalid subscriber name");
+    assert_eq!(errors[1]["field"], "email");
+    assert_eq!(errors[1]["message"], "definitely-not-an-email is not a valid email address");
+}
+
+#[tokio::test]
+async fn subscribe_through_the_api_reports_missing_fields_and_disposable_domains() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_api_subscriptions(&serde_json::json!({ "email": "ursula@yopmail.com" }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["errors"][0]["field"], "name");
+    assert_eq!(body["errors"][1]["field"], "email");
+    assert!(body["errors"][1]["message"].as_str().unwrap().contains("disposable"));
+}
+
+#[tokio::test]
+async fn subscribe_through_the_api_rejects_a_malformed_body_with_a_json_error() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/api/subscriptions", &app.address))
+        .header("Content-Type", "application/json")
+        .body("{ not json")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body["message"].is_string());
+    assert_eq!(body["errors"], serde_json::json!([]));
+}
+
+#[tokio::test]
+async fn subscribe_through_the_api_is_rate_limited_per_client_address() {
+    let app = spawn_app_with(|c| c.subscriptions.bot_protection.ip_rate_limit.max_requests = 1).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let body = serde_json::json!({ "name": "le guin", "email": "ursula_le_guin@gmail.com" });
+
+    let first = app.post_api_subscriptions(&body).await;
+    let second = app.post_api_subscriptions(&body).await;
+
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(second.status().as_u16(), 429);
+    let body: serde_json::Value = second.json().await.unwrap();
+    assert!(body["message"].is_string());
+}
+
+#[tokio::test]
+async fn subscribe_through_the_api_requires_a_form_token() {
+    let app = spawn_app().await;
+    let body = serde_json::json!({ "name": "le guin", "email": "ursula_le_guin@gmail.com" });
+
+    let without_token = app.post_api_subscriptions_raw(&body).await;
+    let forged = app
+        .post_api_subscriptions_raw(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+            "form_token": "1700000000.forged",
+        }))
+        .await;
+
+    assert_eq!(without_token.status().as_u16(), 400);
+    let body: serde_json::Value = without_token.json().await.unwrap();
+    assert_eq!(body["message"], "The form token is missing or has expired, fetch a new one.");
+    assert_eq!(forged.status().as_u16(), 400);
+    let saved = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.count, 0);
+}
+
+#[tokio::test]
+async fn subscribe_through_the_api_rejects_a_filled_in_honeypot() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_api_subscriptions(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+            "website": "http://spam.example.com",
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["message"], "The subscription was rejected.");
+    let saved = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.count, 0);
+}
+
+#[tokio::test]
+async fn subscribe_through_the_api_is_not_accepted_faster_than_a_person_can_type() {
+    let app = spawn_app_with(|c| c.subscriptions.bot_protection.min_form_seconds = 60).await;
+
+    let response = app
+        .post_api_subscriptions(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
@@ -0,0 +1,132 @@
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+async fn subscriber_count(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#,)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+async fn metrics(app: &TestApp) -> String {
+    reqwest::get(format!("{}/metrics", app.address))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn the_home_page_serves_the_subscription_form() {
+    let app = spawn_app().await;
+
+    let form_token = app.get_form_token().await;
+
+    assert!(!form_token.is_empty());
+}
+
+#[tokio::test]
+async fn a_subscription_without_a_form_token_is_rejected() {
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    let missing = app.post_subscriptions_raw(body.into()).await;
+    let forged = app
+        .post_subscriptions_raw(format!("{}&form_token=1700000000.abcdef", body))
+        .await;
+
+    assert_eq!(missing.status().as_u16(), 400);
+    assert_eq!(forged.status().as_u16(), 400);
+    assert_eq!(subscriber_count(&app).await, 0);
+    assert!(metrics(&app)
+        .await
+        .contains(r#"subscribe_bot_rejections_total{reason="invalid_form_token"} 2"#));
+}
+
+#[tokio::test]
+async fn a_filled_in_honeypot_is_rejected() {
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions(
+            "name=le%20guin&email=ursula_le_guin%40gmail.com&website=http%3A%2F%2Fspam.example".into(),
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(subscriber_count(&app).await, 0);
+    assert!(metrics(&app)
+        .await
+        .contains(r#"subscribe_bot_rejections_total{reason="honeypot"} 1"#));
+}
+
+#[tokio::test]
+async fn a_form_submitted_faster_than_a_person_could_is_rejected() {
+    let app = spawn_app_with(|c| c.subscriptions.bot_protection.min_form_seconds = 60).await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(subscriber_count(&app).await, 0);
+}
+
+#[tokio::test]
+async fn subscriptions_are_rate_limited_per_client_address() {
+    let app = spawn_app_with(|c| c.subscriptions.bot_protection.ip_rate_limit.max_requests = 1).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let first = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let second = app
+        .post_subscriptions("name=octavia&email=octavia_butler%40example.com".into())
+        .await;
+
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(second.status().as_u16(), 429);
+    assert!(metrics(&app)
+        .await
+        .contains(r#"subscribe_bot_rejections_total{reason="ip_rate_limited"} 1"#));
+}
+
+#[tokio::test]
+async fn subscriptions_are_rate_limited_per_email_domain() {
+    let app = spawn_app_with(|c| c.subscriptions.bot_protection.domain_rate_limit.max_requests = 1).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let first = app
+        .post_subscriptions("name=le%20guin&email=ursula%40earthsea.org".into())
+        .await;
+    let same_domain = app
+        .post_subscriptions("name=ged&email=ged%40Earthsea.org".into())
+        .await;
+    let other_domain = app
+        .post_subscriptions("name=octavia&email=octavia_butler%40example.com".into())
+        .await;
+
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(same_domain.status().as_u16(), 429);
+    assert_eq!(other_domain.status().as_u16(), 200);
+}
@@ -0,0 +1,284 @@
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::subscriber_email_queue::{queue_emails, QueuedEmail};
+
+// Goes through the real flow so that there are tokens and deliveries to find.
+async fn create_unconfirmed_subscriber(app: &TestApp) -> uuid::Uuid {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    sqlx::query!("SELECT id FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+async fn request_export_link(app: &TestApp) -> reqwest::Url {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let response = app.post_data_export("ursula_le_guin@gmail.com").await;
+    assert_eq!(response.status().as_u16(), 200);
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    app.get_confirmation_links(&email_request).html
+}
+
+#[tokio::test]
+async fn a_data_export_link_downloads_everything_held_about_the_address() {
+    let app = spawn_app().await;
+    let subscriber_id = create_unconfirmed_subscriber(&app).await;
+
+    let export_link = request_export_link(&app).await;
+    let response = reqwest::get(export_link).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers()["content-disposition"]
+        .to_str()
+        .unwrap()
+        .starts_with("attachment"));
+    let export: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(export["subscription"]["id"], subscriber_id.to_string());
+    assert_eq!(export["subscription"]["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(export["subscription"]["status"], "pending_confirmation");
+    assert_eq!(export["subscription_tokens"].as_array().unwrap().len(), 1);
+    assert!(export["subscription_tokens"][0].get("subscription_token").is_none());
+    let kinds: Vec<_> = export["email_deliveries"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|d| d["kind"].as_str().unwrap())
+        .collect();
+    assert_eq!(kinds, vec!["confirmation", "data_export"]);
+}
+
+#[tokio::test]
+async fn a_data_export_includes_the_automations_of_the_subscriber() {
+    let app = spawn_app().await;
+    let automation_id = uuid::Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO automations (id, name, active, created_at) VALUES ($1, 'Welcome series', true, now())",
+        automation_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    for (delay_hours, subject) in [(0, "Welcome"), (72, "Getting the most out of it")] {
+        sqlx::query!(
+            r#"
+            INSERT INTO automation_steps (id, automation_id, delay_hours, subject, text_content, html_content, created_at)
+            VALUES ($1, $2, $3, $4, 'Hi!', '<p>Hi!</p>', now())
+            "#,
+            uuid::Uuid::new_v4(),
+            automation_id,
+            delay_hours,
+            subject,
+        )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    }
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    create_unconfirmed_subscriber(&app).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html).await.unwrap().error_for_status().unwrap();
+    app.dispatch_all_due_automation_steps().await;
+
+    let export_link = request_export_link(&app).await;
+    let export: serde_json::Value = reqwest::get(export_link).await.unwrap().json().await.unwrap();
+
+    let enrollments = export["automation_enrollments"].as_array().unwrap();
+    assert_eq!(enrollments.len(), 1);
+    assert_eq!(enrollments[0]["automation_name"], "Welcome series");
+    assert!(enrollments[0]["stopped_at"].is_null());
+    let deliveries: Vec<_> = export["automation_step_deliveries"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|d| (d["subject"].as_str().unwrap(), d["status"].as_str().unwrap()))
+        .collect();
+    assert_eq!(deliveries, vec![("Welcome", "sent"), ("Getting the most out of it", "scheduled")]);
+}
+
+#[tokio::test]
+async fn a_data_export_for_an_unknown_address_sends_nothing() {
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_data_export("nobody@example.com").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn data_export_requests_are_rate_limited_per_address() {
+    let app = spawn_app_with(|c| c.subscriptions.data_export_rate_limit.max_requests = 1).await;
+
+    let first = app.post_data_export("nobody@example.com").await;
+    let second = app.post_data_export("nobody@example.com").await;
+
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(second.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn an_expired_or_tampered_data_export_link_is_rejected() {
+    let app = spawn_app_with(|c| c.subscriptions.data_export_link_ttl_hours = 0).await;
+    create_unconfirmed_subscriber(&app).await;
+    let mut export_link = request_export_link(&app).await;
+
+    let response = reqwest::get(export_link.clone()).await.unwrap();
+    assert_eq!(response.status().as_u16(), 410);
+
+    let token = export_link
+        .query_pairs()
+        .find(|(key, _)| key == "token")
+        .unwrap()
+        .1
+        .into_owned();
+    let (payload, tag) = token.rsplit_once('.').unwrap();
+    let (subscriber_id, _) = payload.split_once('.').unwrap();
+    let extended = format!("{}.{}.{}", subscriber_id, i64::MAX, tag);
+    export_link.query_pairs_mut().clear().append_pair("token", &extended);
+    let response = reqwest::get(export_link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+// Accepts every email from here on.
+async fn accept_emails(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+fn token_of(link: &reqwest::Url) -> String {
+    link.query_pairs().find(|(key, _)| key == "token").unwrap().1.into_owned()
+}
+
+#[tokio::test]
+async fn erasing_a_subscriber_removes_them_from_every_table() {
+    let app = spawn_app().await;
+    let subscriber_id = create_unconfirmed_subscriber(&app).await;
+    let preferences_token = app.preferences_token(subscriber_id);
+    accept_emails(&app).await;
+    app.post_erasure_request(&preferences_token).await.error_for_status().unwrap();
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let erasure_link = app.get_confirmation_links(&email_request).html;
+    // A confirmation email that is still on its way.
+    queue_emails(&app.db_pool, &[subscriber_id], QueuedEmail::Confirmation).await.unwrap();
+
+    let response = reqwest::get(erasure_link.clone()).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains(r#"action="/subscriptions/erase""#));
+    let response = app.post_erase(&token_of(&erasure_link)).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let counts = sqlx::query!(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM subscriptions) AS "subscriptions!",
+            (SELECT COUNT(*) FROM subscription_tokens) AS "subscription_tokens!",
+            (SELECT COUNT(*) FROM email_deliveries) AS "email_deliveries!",
+            (SELECT COUNT(*) FROM consent_records) AS "consent_records!",
+            (SELECT COUNT(*) FROM subscriber_email_queue) AS "subscriber_email_queue!"
+        "#,
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(counts.subscriptions, 0);
+    assert_eq!(counts.subscription_tokens, 0);
+    assert_eq!(counts.email_deliveries, 0);
+    assert_eq!(counts.consent_records, 0);
+    assert_eq!(counts.subscriber_email_queue, 0);
+
+    let response = app.post_erase(&token_of(&erasure_link)).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app.post_erasure_request(&preferences_token).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app.get_preferences(&preferences_token).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn the_preferences_token_alone_does_not_erase_anything() {
+    let app = spawn_app().await;
+    let subscriber_id = create_unconfirmed_subscriber(&app).await;
+    let preferences_token = app.preferences_token(subscriber_id);
+    accept_emails(&app).await;
+
+    let response = app.post_erase(&preferences_token).await;
+    assert_eq!(response.status().as_u16(), 401);
+    let html = app.post_erasure_request(&preferences_token).await.text().await.unwrap();
+    assert!(html.contains("Check your inbox"));
+
+    let count = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#,)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(count.count, 1);
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "ursula_le_guin@gmail.com");
+    assert_eq!(body["Tag"], "erasure_link");
+}
+
+#[tokio::test]
+async fn an_expired_erasure_link_is_rejected() {
+    let app = spawn_app_with(|c| c.subscriptions.erasure_link_ttl_hours = 0).await;
+    let subscriber_id = create_unconfirmed_subscriber(&app).await;
+    accept_emails(&app).await;
+    app.post_erasure_request(&app.preferences_token(subscriber_id)).await.error_for_status().unwrap();
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let erasure_link = app.get_confirmation_links(&email_request).html;
+
+    let response = reqwest::get(erasure_link.clone()).await.unwrap();
+    assert_eq!(response.status().as_u16(), 410);
+    let response = app.post_erase(&token_of(&erasure_link)).await;
+    assert_eq!(response.status().as_u16(), 410);
+
+    let count = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#,)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(count.count, 1);
+}
+
+#[tokio::test]
+async fn an_unsubscribed_subscriber_can_still_erase_their_data() {
+    let app = spawn_app().await;
+    let subscriber_id = create_unconfirmed_subscriber(&app).await;
+    let token = app.preferences_token(subscriber_id);
+    accept_emails(&app).await;
+
+    let html = app.post_unsubscribe(&token).await.text().await.unwrap();
+    assert!(html.contains(r#"action="/subscriptions/preferences/erase""#));
+    app.erase_subscriber(&token).await.error_for_status().unwrap();
+
+    let count = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#,)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(count.count, 0);
+}
@@ -0,0 +1,300 @@
+use crate::helpers::{spawn_app, TestApp};
+
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn insert_subscriber(app: &TestApp, status: &str) -> uuid::Uuid {
+    let subscriber_id = uuid::Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, 'ursula_le_guin@gmail.com', 'le guin', now(), $2)
+        "#,
+        subscriber_id,
+        status,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    subscriber_id
+}
+
+#[tokio::test]
+async fn the_preferences_page_shows_the_current_preferences() {
+    let app = spawn_app().await;
+    let subscriber_id = insert_subscriber(&app, "confirmed").await;
+
+    let response = app.get_preferences(&app.preferences_token(subscriber_id)).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    assert!(html.contains(&htmlescape::encode_attribute("le guin")));
+    assert!(html.contains(&htmlescape::encode_attribute("ursula_le_guin@gmail.com")));
+    assert!(html.contains(r#"value="immediately" checked"#));
+    assert!(html.contains("Long-form essays"));
+}
+
+#[tokio::test]
+async fn the_first_digest_starts_from_the_switch_away_from_every_issue() {
+    let app = spawn_app().await;
+    let subscriber_id = insert_subscriber(&app, "confirmed").await;
+    sqlx::query!("UPDATE subscriptions SET digest_covered_until = now() - interval '1 year'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let token = app.preferences_token(subscriber_id);
+    let fields = |delivery_frequency| {
+        [
+            ("token", token.as_str()),
+            ("name", "le guin"),
+            ("email", "ursula_le_guin@gmail.com"),
+            ("delivery_frequency", delivery_frequency),
+        ]
+    };
+
+    app.post_preferences(&fields("weekly")).await.error_for_status().unwrap();
+    let after_switch = digest_covered_until(&app).await;
+    app.post_preferences(&fields("monthly")).await.error_for_status().unwrap();
+
+    // Everything until then went out straight away.
+    assert!(after_switch > chrono::Utc::now() - chrono::Duration::minutes(1));
+    // Moving between digests doesn't skip what the next one would have.
+    assert_eq!(digest_covered_until(&app).await, after_switch);
+}
+
+async fn digest_covered_until(app: &TestApp) -> chrono::DateTime<chrono::Utc> {
+    sqlx::query!("SELECT digest_covered_until FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .digest_covered_until
+}
+
+#[tokio::test]
+async fn a_tampered_token_is_rejected() {
+    let app = spawn_app().await;
+    let subscriber_id = insert_subscriber(&app, "confirmed").await;
+    let token = app.preferences_token(subscriber_id);
+    let (_, tag) = token.split_once('.').unwrap();
+    let forged = format!("{}.{}", uuid::Uuid::new_v4().simple(), tag);
+
+    let response = app.get_preferences(&forged).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .post_preferences(&[
+            ("token", &forged),
+            ("name", "someone else"),
+            ("email", "ursula_le_guin@gmail.com"),
+            ("delivery_frequency", "weekly"),
+        ])
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn a_subscriber_can_update_their_preferences() {
+    let app = spawn_app().await;
+    let subscriber_id = insert_subscriber(&app, "confirmed").await;
+    let token = app.preferences_token(subscriber_id);
+
+    let response = app
+        .post_preferences(&[
+            ("token", &token),
+            ("name", "Ursula K. Le Guin"),
+            ("email", "ursula_le_guin@gmail.com"),
+            ("topics", "essays"),
+            ("topics", "announcements"),
+            ("delivery_frequency", "weekly"),
+        ])
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("Your preferences were saved."));
+    let saved = sqlx::query!("SELECT name, topics, delivery_frequency FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "Ursula K. Le Guin");
+    assert_eq!(saved.topics, vec!["essays", "announcements"]);
+    assert_eq!(saved.delivery_frequency, "weekly");
+}
+
+#[tokio::test]
+async fn invalid_preferences_are_rejected_and_nothing_is_saved() {
+    let app = spawn_app().await;
+    let subscriber_id = insert_subscriber(&app, "confirmed").await;
+    let token = app.preferences_token(subscriber_id);
+    let test_cases = vec![
+        (vec![("name", ""), ("delivery_frequency", "weekly")], "empty name"),
+        (vec![("name", "<script>"), ("delivery_frequency", "weekly")], "forbidden characters in the name"),
+        (vec![("name", "Ursula"), ("delivery_frequency", "hourly")], "unknown frequency"),
+        (vec![("name", "Ursula"), ("delivery_frequency", "weekly"), ("topics", "poetry")], "unknown topic"),
+    ];
+
+    for (fields, description) in test_cases {
+        let mut form = vec![("token", token.as_str()), ("email", "ursula_le_guin@gmail.com")];
+        form.extend(fields);
+
+        let response = app.post_preferences(&form).await;
+
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The preferences were not rejected for an {}.",
+            description
+        );
+    }
+    let saved = sqlx::query!("SELECT name, delivery_frequency FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "le guin");
+    assert_eq!(saved.delivery_frequency, "immediately");
+}
+
+#[tokio::test]
+async fn changing_the_email_waits_for_the_new_address_to_be_confirmed() {
+    let app = spawn_app().await;
+    let subscriber_id = insert_subscriber(&app, "confirmed").await;
+    let token = app.preferences_token(subscriber_id);
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_preferences(&[
+            ("token", &token),
+            ("name", "le guin"),
+            ("email", "ursula@earthsea.org"),
+            ("delivery_frequency", "immediately"),
+        ])
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "ursula@earthsea.org");
+    let saved = sqlx::query!("SELECT email FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+
+    let confirmation_links = app.get_confirmation_links(email_request);
+    let change_token = confirmation_links
+        .html
+        .query_pairs()
+        .find(|(key, _)| key == "token")
+        .unwrap()
+        .1
+        .into_owned();
+    let stored = sqlx::query!("SELECT token_hash FROM email_change_requests")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(stored.token_hash, zero2prod::routes::hash_subscription_token(&change_token));
+    let response = reqwest::get(confirmation_links.html.clone()).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT email, status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, "ursula@earthsea.org");
+    assert_eq!(saved.status, "confirmed");
+
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn an_email_change_to_an_address_already_on_the_list_is_refused() {
+    let app = spawn_app().await;
+    let subscriber_id = insert_subscriber(&app, "confirmed").await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, 'ursula@earthsea.org', 'ursula', now(), 'confirmed')
+        "#,
+        uuid::Uuid::new_v4(),
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_preferences(&[
+        ("token", &app.preferences_token(subscriber_id)),
+        ("name", "le guin"),
+        ("email", "ursula@earthsea.org"),
+        ("delivery_frequency", "immediately"),
+    ])
+    .await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 409);
+    let saved = sqlx::query!("SELECT email FROM subscriptions WHERE id = $1", subscriber_id)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+}
+
+#[tokio::test]
+async fn a_subscriber_can_unsubscribe() {
+    let app = spawn_app().await;
+    let subscriber_id = insert_subscriber(&app, "confirmed").await;
+    let token = app.preferences_token(subscriber_id);
+
+    let response = app.post_unsubscribe(&token).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "unsubscribed");
+    let html = app.get_preferences(&token).await.text().await.unwrap();
+    assert!(html.contains("unsubscribed"));
+}
+
+#[tokio::test]
+async fn unsubscribing_does_not_lift_a_suppression() {
+    let app = spawn_app().await;
+    let subscriber_id = insert_subscriber(&app, "suppressed").await;
+
+    app.post_unsubscribe(&app.preferences_token(subscriber_id)).await;
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "suppressed");
+}
+
+#[tokio::test]
+async fn the_email_cannot_be_changed_to_a_disposable_address() {
+    let app = spawn_app().await;
+    let subscriber_id = insert_subscriber(&app, "confirmed").await;
+
+    let response = app
+        .post_preferences(&[
+            ("token", &app.preferences_token(subscriber_id)),
+            ("name", "le guin"),
+            ("email", "ursula@yopmail.com"),
+            ("delivery_frequency", "immediately"),
+        ])
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response.text().await.unwrap().contains("disposable"));
+}
@@ -0,0 +1,136 @@
+use crate::helpers::{spawn_app, spawn_app_with};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[tokio::test]
+async fn resending_issues_a_new_link_next_to_the_old_one() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await.error_for_status().unwrap();
+
+    let response = app.post_resend_confirmation("ursula_le_guin@gmail.com").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let old_link = app.get_confirmation_links(&email_requests[0]).html;
+    let new_link = app.get_confirmation_links(&email_requests[1]).html;
+    assert_ne!(old_link, new_link);
+    assert_eq!(reqwest::get(new_link).await.unwrap().status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn an_older_link_still_works_after_a_resend() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await.error_for_status().unwrap();
+
+    app.post_resend_confirmation("ursula_le_guin@gmail.com").await;
+
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let old_link = app.get_confirmation_links(&email_requests[0]).html;
+    assert_eq!(reqwest::get(old_link).await.unwrap().status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn resending_to_a_confirmed_subscriber_looks_the_same_but_sends_nothing() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await.error_for_status().unwrap();
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_link = app.get_confirmation_links(email_request).html;
+    reqwest::get(confirmation_link).await.unwrap().error_for_status().unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_resend_confirmation("ursula_le_guin@gmail.com").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn a_failed_resend_leaves_the_answer_and_the_old_link_alone() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await.error_for_status().unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_resend_confirmation("ursula_le_guin@gmail.com").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let old_link = app.get_confirmation_links(&email_requests[0]).html;
+    assert_eq!(reqwest::get(old_link).await.unwrap().status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn resending_to_an_unknown_address_looks_the_same_but_sends_nothing() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_resend_confirmation("ursula_le_guin@gmail.com").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn resending_rejects_invalid_email_addresses() {
+    let app = spawn_app().await;
+
+    let response = app.post_resend_confirmation("definitely-not-an-email").await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn resending_is_rate_limited_per_address() {
+    let app = spawn_app_with(|c| c.subscriptions.resend_rate_limit.max_requests = 2).await;
+
+    for _ in 0..2 {
+        let response = app.post_resend_confirmation("ursula_le_guin@gmail.com").await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    let response = app.post_resend_confirmation("URSULA_LE_GUIN@gmail.com").await;
+    assert_eq!(response.status().as_u16(), 429);
+
+    let response = app.post_resend_confirmation("someone_else@gmail.com").await;
+    assert_eq!(response.status().as_u16(), 200);
+}